@@ -26,6 +26,8 @@ struct TilemapVertexOutput {
 
 struct TilemapTextureDescriptor {
     tile_count: vec2u,
+    // Textures may be smaller than the array, or have unused pixels.
+    tile_uv_size: vec2f,
 }
 
 @group(0) @binding(0) var<uniform> view: View;
@@ -92,22 +94,44 @@ fn vertex(in: TilemapVertexInput) -> TilemapVertexOutput {
         texture_index = in.atlas_indices[0];
         atlas_index = in.atlas_indices[1];
     } else {
-        let fps = f32(animations[in.atlas_indices[0] - 1]);
+        let start = in.atlas_indices[0];
+        let len = in.atlas_indices[1];
+        let mode = animations[start - 2u];
+        let fps = f32(animations[start - 1u]);
         let offset = f32(in.atlas_indices[3]) * 0.001;
-        let cur_frame = u32((globals.time + offset) * fps) % in.atlas_indices[1];
-        let cur_index = cur_frame * 2u + in.atlas_indices[0];
+        let t = u32(((globals.time + offset) % 3600.) * fps);
+
+        // Keep synced with `TileAnimationMode`.
+        var cur_frame: u32;
+        switch mode {
+            // Ping-pong
+            case 1u: {
+                let period = max(len * 2u - 2u, 1u);
+                let i = t % period;
+                cur_frame = select(period - i, i, i < len);
+            }
+            // Once
+            case 2u: {
+                cur_frame = min(t, len - 1u);
+            }
+            // Loop
+            default: {
+                cur_frame = t % len;
+            }
+        }
+        let cur_index = cur_frame * 2u + start;
 
         texture_index = animations[cur_index];
         atlas_index = animations[cur_index + 1u];
     }
 
     var vert_uv = vert_uv(corner);
-    let tile_count = texture_desc[texture_index].tile_count;
+    let desc = texture_desc[texture_index];
     let decoded_atlas_index = decode_atlas_and_flip_uv(atlas_index, &vert_uv);
-    let atlas_index_2d = vec2u(decoded_atlas_index % tile_count.x, decoded_atlas_index / tile_count.x);
-    let tile_uv = vec2f(atlas_index_2d) / vec2f(tile_count);
+    let atlas_index_2d = vec2u(decoded_atlas_index % desc.tile_count.x, decoded_atlas_index / desc.tile_count.x);
+    let tile_uv = vec2f(atlas_index_2d) * desc.tile_uv_size;
     
-    out.uv = tile_uv + vert_uv / vec2f(tile_count);
+    out.uv = tile_uv + vert_uv * desc.tile_uv_size;
     out.texture_index = texture_index;
 
     return out;
@@ -0,0 +1,211 @@
+//! Compares [`ChunkedStorage`] with [`DenseChunkedStorage`] on tiles.
+//!
+//! Each operation is repeated on a fresh storage after a few warmup runs, and
+//! the mean and the fastest run are reported.
+//!
+//! Run with `cargo run --release --example chunk_storage`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use bevy::math::IVec2;
+use dystopia_core::{
+    map::tilemap::{Tile, TileIndex},
+    util::chunking::{ChunkedStorage, DenseChunkedStorage, DEFAULT_CHUNK_SIZE},
+};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+
+const MAP_SIZE: i32 = 512;
+const DENSITIES: [f64; 3] = [0.05, 0.5, 1.];
+const WARMUP_RUNS: u32 = 3;
+const RUNS: u32 = 20;
+
+fn main() {
+    for density in DENSITIES {
+        println!(
+            "=== {}x{} tiles, density {} ===",
+            MAP_SIZE, MAP_SIZE, density
+        );
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut indices = (0..MAP_SIZE)
+            .flat_map(|y| (0..MAP_SIZE).map(move |x| IVec2 { x, y }))
+            .filter(|_| rng.gen_bool(density))
+            .map(|i| TileIndex::from_direct(i, DEFAULT_CHUNK_SIZE))
+            .collect::<Vec<_>>();
+        indices.shuffle(&mut rng);
+
+        let new_sparse = || ChunkedStorage::<IVec2, Tile>::new(DEFAULT_CHUNK_SIZE);
+        let new_dense = || DenseChunkedStorage::<IVec2, Tile>::new(DEFAULT_CHUNK_SIZE);
+        let fill_sparse = |sparse: &mut ChunkedStorage<IVec2, Tile>| {
+            for index in &indices {
+                sparse.set(index.flattened(), tile(*index));
+            }
+        };
+        let fill_dense = |dense: &mut DenseChunkedStorage<IVec2, Tile>| {
+            for index in &indices {
+                dense.set(index.flattened(), tile(*index));
+            }
+        };
+
+        let mut sparse = new_sparse();
+        fill_sparse(&mut sparse);
+        let mut dense = new_dense();
+        fill_dense(&mut dense);
+
+        report(
+            "set",
+            measure(new_sparse, fill_sparse),
+            measure(new_dense, fill_dense),
+        );
+
+        report(
+            "get",
+            measure(
+                || &sparse,
+                |sparse| {
+                    for index in &indices {
+                        black_box(sparse.get(&index.flattened()));
+                    }
+                },
+            ),
+            measure(
+                || &dense,
+                |dense| {
+                    for index in &indices {
+                        black_box(dense.get(&index.flattened()));
+                    }
+                },
+            ),
+        );
+
+        report(
+            "iterate",
+            measure(
+                || &sparse,
+                |sparse| {
+                    for (_, chunk) in sparse.iter() {
+                        for tile in chunk.iter().flatten() {
+                            black_box(tile);
+                        }
+                    }
+                },
+            ),
+            measure(
+                || &dense,
+                |dense| {
+                    for (_, tile) in dense.iter_occupied() {
+                        black_box(tile);
+                    }
+                },
+            ),
+        );
+
+        println!(
+            "{:>10}: sparse {:>10} bytes, dense {:>10} bytes",
+            "memory",
+            sparse.keys().len() * sparse_chunk_bytes(),
+            dense.keys().len() * dense_chunk_bytes(),
+        );
+
+        let remove_sparse = |sparse: &mut ChunkedStorage<IVec2, Tile>| {
+            for index in &indices {
+                sparse.remove(&index.flattened());
+            }
+        };
+        let remove_dense = |dense: &mut DenseChunkedStorage<IVec2, Tile>| {
+            for index in &indices {
+                dense.remove(&index.flattened());
+            }
+        };
+
+        report(
+            "remove",
+            measure(
+                || {
+                    let mut sparse = new_sparse();
+                    fill_sparse(&mut sparse);
+                    sparse
+                },
+                remove_sparse,
+            ),
+            measure(
+                || {
+                    let mut dense = new_dense();
+                    fill_dense(&mut dense);
+                    dense
+                },
+                remove_dense,
+            ),
+        );
+
+        remove_sparse(&mut sparse);
+        remove_dense(&mut dense);
+        println!(
+            "{:>10}: sparse {:>10} chunks, dense {:>10} chunks",
+            "leftover",
+            sparse.keys().len(),
+            dense.keys().len(),
+        );
+    }
+}
+
+fn tile(index: TileIndex) -> Tile {
+    Tile {
+        index,
+        ..Default::default()
+    }
+}
+
+fn sparse_chunk_bytes() -> usize {
+    DEFAULT_CHUNK_SIZE.pow(2) as usize * std::mem::size_of::<Option<Tile>>()
+}
+
+fn dense_chunk_bytes() -> usize {
+    let n = DEFAULT_CHUNK_SIZE.pow(2) as usize;
+    n * std::mem::size_of::<Tile>() + n.div_ceil(64) * 8
+}
+
+struct Timing {
+    mean: Duration,
+    min: Duration,
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>10.3?} (min {:>10.3?})", self.mean, self.min)
+    }
+}
+
+/// Run `routine` [`WARMUP_RUNS`] times untimed, then [`RUNS`] times timed. Every
+/// run gets a fresh state from `setup`, which isn't timed, and neither is
+/// dropping the state.
+fn measure<S>(mut setup: impl FnMut() -> S, mut routine: impl FnMut(&mut S)) -> Timing {
+    for _ in 0..WARMUP_RUNS {
+        routine(&mut setup());
+    }
+
+    let mut total = Duration::ZERO;
+    let mut min = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut state = setup();
+        let start = Instant::now();
+        routine(&mut state);
+        let elapsed = start.elapsed();
+        black_box(state);
+
+        total += elapsed;
+        min = min.min(elapsed);
+    }
+
+    Timing {
+        mean: total / RUNS,
+        min,
+    }
+}
+
+fn report(name: &str, sparse: Timing, dense: Timing) {
+    println!("{:>10}: sparse {}, dense {}", name, sparse, dense);
+}
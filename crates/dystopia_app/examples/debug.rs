@@ -40,6 +40,7 @@ use dystopia_core::{
     input::{MouseClickCounter, MouseInput},
     map::{
         bundle::TilemapBundle,
+        serde::TilemapFormat,
         shape::rectangle,
         tilemap::{
             FlattenedTileIndex, Tile, TileAtlasIndex, TileFlip, TileIndex, TileRenderSize,
@@ -168,6 +169,7 @@ fn debug_tilemap(
         tilesets: TilemapTilesets::new(
             vec![
                 TilemapTexture {
+                    id: None,
                     handle: asset_server.load("images/test_tileset_a.png"),
                     desc: TilemapTextureDescriptor {
                         size: UVec2 { x: 32, y: 32 },
@@ -175,6 +177,7 @@ fn debug_tilemap(
                     },
                 },
                 TilemapTexture {
+                    id: None,
                     handle: asset_server.load("images/test_tileset_b.png"),
                     desc: TilemapTextureDescriptor {
                         size: UVec2 { x: 32, y: 32 },
@@ -195,7 +198,8 @@ fn debug_tilemap(
             (1, 1, TileFlip::NONE),
         ],
         3,
-    );
+    )
+    .unwrap();
     let anim_up = tilemap.animations.register(
         vec![
             (0, 3, TileFlip::NONE),
@@ -204,7 +208,8 @@ fn debug_tilemap(
             (1, 4, TileFlip::NONE),
         ],
         3,
-    );
+    )
+    .unwrap();
 
     let mut rng = rand::thread_rng();
     for (i_tile, index) in rectangle(10, 10).into_iter().enumerate() {
@@ -235,6 +240,7 @@ fn debug_rm_vis(
         if keyboard.just_pressed(KeyCode::Digit5) {
             commands.entity(entity).insert(ToSaveTilemap {
                 remove_after_done: true,
+                format: TilemapFormat::Binary,
             });
         }
 
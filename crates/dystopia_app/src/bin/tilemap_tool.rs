@@ -0,0 +1,62 @@
+//! Convert tilemaps between the binary (`.tmb`) and text (`.tm.json`) formats,
+//! and print their statistics.
+//!
+//! ```text
+//! tilemap_tool convert <input> <output>
+//! tilemap_tool stats <input>
+//! ```
+
+use std::{path::Path, process::ExitCode};
+
+use dystopia_core::map::serde::{BinaryTilemap, TilemapFormat};
+
+const USAGE: &str = "Usage:
+    tilemap_tool convert <input> <output>
+    tilemap_tool stats <input>
+
+Formats are decided by extensions: `.tmb` for binary and `.tm.json` for text.";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let result = match args.as_slice() {
+        ["convert", input, output] => {
+            read_tilemap(Path::new(input)).and_then(|t| write_tilemap(&t, Path::new(output)))
+        }
+        ["stats", input] => read_tilemap(Path::new(input)).map(|t| print!("{}", t.stats())),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn format(path: &Path) -> Result<TilemapFormat, String> {
+    TilemapFormat::from_path(path).ok_or_else(|| format!("Unknown tilemap format: {:?}", path))
+}
+
+fn read_tilemap(path: &Path) -> Result<BinaryTilemap, String> {
+    let format = format(path)?;
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+
+    format
+        .decode(&bytes)
+        .map_err(|e| format!("Failed to decode {:?}: {}", path, e))
+}
+
+fn write_tilemap(tilemap: &BinaryTilemap, path: &Path) -> Result<(), String> {
+    let bytes = format(path)?
+        .encode(tilemap)
+        .map_err(|e| format!("Failed to encode tilemap: {}", e))?;
+
+    std::fs::write(path, bytes).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
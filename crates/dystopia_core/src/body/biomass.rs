@@ -0,0 +1,207 @@
+//! Biomass, the mass of living things per area, in g/m².
+//!
+//! Growing crops add biomass to their tiles and harvesting takes most of it
+//! away, while pollution slowly kills it off. Changes on tiles are also applied
+//! to the body, weighted by the number of tiles.
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin},
+    math::IVec2,
+    prelude::{
+        in_state, Commands, Entity, EventReader, EventWriter, IntoSystemConfigs, Query, Res, ResMut,
+    },
+    utils::HashMap,
+};
+use bincode::{Decode, Encode};
+use rayon::iter::ParallelIterator;
+
+use crate::{
+    body::{
+        pollution::{TilePollution, POLLUTION_SPREAD_INTERVAL},
+        quantify::Biomass,
+        BodyQuantifiedChanged,
+    },
+    cosmos::celestial::{BodyIndex, BodyTilemap, Cosmos},
+    farming::{CropHarvested, CropStageChanged},
+    map::{
+        data::{SerializableTileData, TileDataAppExt, TileDataStorage},
+        tilemap::TilemapStorage,
+    },
+    schedule::state::GameState,
+    sci::Quantified,
+    sim::{global_clock, Ticker},
+};
+
+/// Biomass added to a tile each time the crop on it grows a stage.
+pub const CROP_STAGE_BIOMASS: f64 = 50.;
+/// Portion of biomass left on the tile after harvesting.
+pub const HARVEST_BIOMASS_RETAINED: f64 = 0.2;
+/// Portion of biomass lost per [`POLLUTION_SPREAD_INTERVAL`] at 100 DPI.
+pub const BIOMASS_POLLUTION_LOSS_RATE: f64 = 0.01;
+/// Tiles with less biomass are considered barren.
+const BIOMASS_EPSILON: f64 = 0.01;
+
+pub(super) struct BiomassPlugin;
+
+impl Plugin for BiomassPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_serializable_tile_data::<TileBiomass>().add_systems(
+            FixedUpdate,
+            (grow_biomass, erode_biomass)
+                .chain()
+                .after(global_clock)
+                .run_if(in_state(GameState::Simulate)),
+        );
+    }
+}
+
+/// Biomass on a tile, in g/m².
+#[derive(Encode, Decode, Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct TileBiomass(pub f64);
+
+impl SerializableTileData for TileBiomass {
+    const KEY: &'static str = "biomass";
+}
+
+impl TileBiomass {
+    #[inline]
+    pub fn quantified(self) -> Biomass {
+        Biomass::quantify(self.0.clamp(0., 10000. - f64::EPSILON))
+    }
+}
+
+fn grow_biomass(
+    mut commands: Commands,
+    mut stage_changed: EventReader<CropStageChanged>,
+    mut harvested: EventReader<CropHarvested>,
+    bodies_query: Query<(&BodyIndex, &BodyTilemap)>,
+    mut tilemaps_query: Query<(&TilemapStorage, Option<&mut TileDataStorage<TileBiomass>>)>,
+    mut quantified_changed: EventWriter<BodyQuantifiedChanged>,
+    mut cosmos: ResMut<Cosmos>,
+) {
+    let changes = stage_changed
+        .read()
+        .map(|e| (e.tilemap, e.index, None))
+        .chain(
+            harvested
+                .read()
+                .map(|e| (e.tilemap, e.index, Some(HARVEST_BIOMASS_RETAINED))),
+        )
+        .collect::<Vec<(Entity, IVec2, Option<f64>)>>();
+    if changes.is_empty() {
+        return;
+    }
+
+    let bodies = bodies_query
+        .iter()
+        .map(|(index, tilemap)| (**tilemap, *index))
+        .collect::<HashMap<_, _>>();
+    let mut created = HashMap::<Entity, TileDataStorage<TileBiomass>>::default();
+    // Sum of biomass changes on each body, and its number of tiles.
+    let mut deltas = HashMap::<Entity, (f64, usize)>::default();
+
+    for (tilemap, index, retained) in changes {
+        let Ok((tiles, biomass)) = tilemaps_query.get_mut(tilemap) else {
+            continue;
+        };
+        let biomass = match biomass {
+            Some(biomass) => biomass.into_inner(),
+            None => created
+                .entry(tilemap)
+                .or_insert_with(|| TileDataStorage::new(tiles.chunk_size())),
+        };
+
+        let cur = biomass.get(index).copied().unwrap_or_default().0;
+        let new = match retained {
+            Some(retained) => cur * retained,
+            None => cur + CROP_STAGE_BIOMASS,
+        }
+        .clamp(0., 10000.);
+
+        if new < BIOMASS_EPSILON {
+            biomass.remove(index);
+        } else {
+            biomass.set(index, TileBiomass(new));
+        }
+
+        deltas
+            .entry(tilemap)
+            .or_insert_with(|| (0., tiles.chunked_storage().iter_direct().count().max(1)))
+            .0 += new - cur;
+    }
+
+    for (tilemap, biomass) in created {
+        commands.entity(tilemap).insert(biomass);
+    }
+
+    let Cosmos {
+        parameterized,
+        quantified,
+        ..
+    } = &mut *cosmos;
+    for (tilemap, (delta, num_tiles)) in deltas {
+        let Some(body_index) = bodies.get(&tilemap) else {
+            continue;
+        };
+
+        let body = &mut parameterized[**body_index];
+        body.biomass += delta / num_tiles as f64;
+        body.clamp();
+        quantified_changed.send_batch(BodyQuantifiedChanged::requantify(
+            *body_index,
+            body,
+            &mut quantified[**body_index],
+        ));
+    }
+}
+
+fn erode_biomass(
+    bodies_query: Query<(&BodyIndex, &BodyTilemap)>,
+    mut tilemaps_query: Query<(
+        &mut TileDataStorage<TileBiomass>,
+        Option<&TileDataStorage<TilePollution>>,
+    )>,
+    mut quantified_changed: EventWriter<BodyQuantifiedChanged>,
+    mut cosmos: ResMut<Cosmos>,
+    ticker: Res<Ticker>,
+) {
+    if **ticker % POLLUTION_SPREAD_INTERVAL != 0 {
+        return;
+    }
+
+    for (body_index, body_tilemap) in &bodies_query {
+        if let Ok((mut biomass, Some(pollution))) = tilemaps_query.get_mut(**body_tilemap) {
+            let chunk_size = biomass.chunk_size();
+            let eroded = biomass
+                .par_iter()
+                .filter_map(|(index, b)| {
+                    let index = index.to_direct(chunk_size);
+                    let p = pollution.get(index)?.0;
+                    Some((index, b.0 * (1. - BIOMASS_POLLUTION_LOSS_RATE * p / 100.)))
+                })
+                .collect::<Vec<_>>();
+
+            for (index, new) in eroded {
+                if new < BIOMASS_EPSILON {
+                    biomass.remove(index);
+                } else {
+                    biomass.set(index, TileBiomass(new));
+                }
+            }
+        }
+
+        let Cosmos {
+            parameterized,
+            quantified,
+            ..
+        } = &mut *cosmos;
+        let body = &mut parameterized[**body_index];
+        body.biomass *= 1. - BIOMASS_POLLUTION_LOSS_RATE * body.pollution / 100.;
+        body.clamp();
+        quantified_changed.send_batch(BodyQuantifiedChanged::requantify(
+            *body_index,
+            body,
+            &mut quantified[**body_index],
+        ));
+    }
+}
@@ -1,21 +1,40 @@
+use std::ops::Add;
+
 use bevy::{
     app::{App, Plugin},
-    prelude::{Component, Entity, Resource},
+    prelude::{Component, Entity, Event, Resource},
 };
+use bincode::{Decode, Encode};
 
 use crate::{
     body::quantify::{
-        AtmosphericDensity, Density, Illuminance, Metallicity, Moisture, Temperature,
+        AtmosphericDensity, Biomass, Density, Illuminance, Metallicity, Moisture, Pollution,
+        Temperature,
     },
     cosmos::celestial::{BodyIndex, BodyTilemap},
+    map::data::{SerializableTileData, TileDataAppExt},
+    sci::Quantified,
 };
 
+pub mod biomass;
+pub mod pollution;
 pub mod quantify;
+pub mod terraform;
+pub mod weather;
 
 pub struct DystopiaBodyPlugin;
 
 impl Plugin for DystopiaBodyPlugin {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            biomass::BiomassPlugin,
+            pollution::PollutionPlugin,
+            terraform::TerraformPlugin,
+            weather::WeatherPlugin,
+        ))
+        .add_event::<BodyQuantifiedChanged>()
+        .add_serializable_tile_data::<TileEnvironment>();
+    }
 }
 
 /// The body currently focusing on. Not necessarily exist.
@@ -37,6 +56,10 @@ pub struct ParameterizedBody {
     pub density: f64,
     pub illuminance: f64,
     pub atmospheric_density: f64,
+    /// In DPI, averaged over all tiles.
+    pub pollution: f64,
+    /// In g/m².
+    pub biomass: f64,
 }
 
 impl Default for ParameterizedBody {
@@ -48,6 +71,55 @@ impl Default for ParameterizedBody {
             density: f64::NAN,
             illuminance: f64::NAN,
             atmospheric_density: f64::NAN,
+            pollution: f64::NAN,
+            biomass: f64::NAN,
+        }
+    }
+}
+
+impl ParameterizedBody {
+    /// Keep parameters in ranges that can be quantified.
+    pub fn clamp(&mut self) {
+        const BELOW_ONE: f64 = 1. - f64::EPSILON;
+
+        self.temperature = self.temperature.max(0.);
+        self.moisture = self.moisture.clamp(0., BELOW_ONE);
+        self.metallicity = self.metallicity.clamp(0., BELOW_ONE);
+        self.illuminance = self.illuminance.max(0.);
+        self.atmospheric_density = self.atmospheric_density.clamp(0., 10. - f64::EPSILON);
+        self.pollution = self.pollution.clamp(0., 100. - f64::EPSILON);
+        self.biomass = self.biomass.clamp(0., 10000. - f64::EPSILON);
+    }
+
+    /// Stars only have temperatures, and the rest of their parameters are
+    /// `NaN`, so they can't be quantified as a whole.
+    pub fn is_quantifiable(&self) -> bool {
+        [
+            self.temperature,
+            self.moisture,
+            self.metallicity,
+            self.density,
+            self.illuminance,
+            self.atmospheric_density,
+            self.pollution,
+            self.biomass,
+        ]
+        .iter()
+        .all(|p| !p.is_nan())
+    }
+
+    /// Requires parameters to be clamped and quantifiable. See [`Self::clamp`]
+    /// and [`Self::is_quantifiable`].
+    pub fn quantify(&self) -> QuantifiedBody {
+        QuantifiedBody {
+            temperature: Temperature::quantify(self.temperature),
+            moisture: Moisture::quantify(self.moisture),
+            metallicity: Metallicity::quantify(self.metallicity),
+            density: Density::quantify(self.density),
+            illuminance: Illuminance::quantify(self.illuminance),
+            atmospheric_density: AtmosphericDensity::quantify(self.atmospheric_density),
+            pollution: Pollution::quantify(self.pollution),
+            biomass: Biomass::quantify(self.biomass),
         }
     }
 }
@@ -60,6 +132,8 @@ pub struct QuantifiedBody {
     pub density: Density,
     pub illuminance: Illuminance,
     pub atmospheric_density: AtmosphericDensity,
+    pub pollution: Pollution,
+    pub biomass: Biomass,
 }
 
 impl Default for QuantifiedBody {
@@ -71,6 +145,173 @@ impl Default for QuantifiedBody {
             density: Density::Diffuse,
             illuminance: Illuminance::Faint,
             atmospheric_density: AtmosphericDensity::Sparse,
+            pollution: Pollution::Clean,
+            biomass: Biomass::Barren,
+        }
+    }
+}
+
+impl QuantifiedBody {
+    /// Parameters that fall into different buckets in `new`.
+    pub fn changes(&self, new: &QuantifiedBody) -> Vec<QuantifiedChange> {
+        macro_rules! diff {
+            ($($field: ident => $variant: ident),*) => {
+                [$((self.$field != new.$field).then_some(QuantifiedChange::$variant {
+                    old: self.$field,
+                    new: new.$field,
+                })),*]
+            };
+        }
+
+        diff!(
+            temperature => Temperature,
+            moisture => Moisture,
+            metallicity => Metallicity,
+            illuminance => Illuminance,
+            atmospheric_density => AtmosphericDensity,
+            pollution => Pollution,
+            biomass => Biomass
+        )
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// How suitable the body is for living and farming, in `[0, 1]`.
+    pub fn habitability(&self) -> f64 {
+        let temperature = match self.temperature {
+            Temperature::Habitable => 1.,
+            Temperature::Cold | Temperature::Hot => 0.5,
+            Temperature::Freezing | Temperature::Boiling => 0.,
+        };
+        let moisture = match self.moisture {
+            Moisture::Moist => 1.,
+            Moisture::Dry | Moisture::Saturated => 0.6,
+            Moisture::Parched => 0.2,
+        };
+        let atmosphere = match self.atmospheric_density {
+            AtmosphericDensity::Moderate => 1.,
+            AtmosphericDensity::Thick => 0.6,
+            AtmosphericDensity::Sparse => 0.3,
+        };
+        let pollution = match self.pollution {
+            Pollution::Clean => 1.,
+            Pollution::Slight => 0.6,
+            Pollution::Heavy => 0.1,
+        };
+
+        temperature * moisture * atmosphere * pollution
+    }
+}
+
+/// A quantified parameter of a body falls into another bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantifiedChange {
+    Temperature {
+        old: Temperature,
+        new: Temperature,
+    },
+    Moisture {
+        old: Moisture,
+        new: Moisture,
+    },
+    Metallicity {
+        old: Metallicity,
+        new: Metallicity,
+    },
+    Illuminance {
+        old: Illuminance,
+        new: Illuminance,
+    },
+    AtmosphericDensity {
+        old: AtmosphericDensity,
+        new: AtmosphericDensity,
+    },
+    Pollution {
+        old: Pollution,
+        new: Pollution,
+    },
+    Biomass {
+        old: Biomass,
+        new: Biomass,
+    },
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BodyQuantifiedChanged {
+    pub body: BodyIndex,
+    pub change: QuantifiedChange,
+}
+
+impl BodyQuantifiedChanged {
+    /// Quantify the parameters of `body` again, and return what changed.
+    ///
+    /// Bodies that aren't quantifiable, like stars, are left untouched.
+    pub fn requantify(
+        body: BodyIndex,
+        parameterized: &ParameterizedBody,
+        quantified: &mut QuantifiedBody,
+    ) -> Vec<Self> {
+        if !parameterized.is_quantifiable() {
+            return Vec::new();
+        }
+
+        let new = parameterized.quantify();
+        let changes = quantified.changes(&new);
+        *quantified = new;
+        changes
+            .into_iter()
+            .map(|change| Self { body, change })
+            .collect()
+    }
+}
+
+/// Local deviations of the environment on a tile, relative to the parameters of
+/// the body, like those caused by irrigation.
+///
+/// Stored in [`TileDataStorage`](crate::map::data::TileDataStorage) on the root
+/// tilemap of the body. Tiles without it just follow the body.
+#[derive(Encode, Decode, Debug, Default, Clone, Copy)]
+pub struct TileEnvironment {
+    pub temperature: f64,
+    pub moisture: f64,
+    pub illuminance: f64,
+}
+
+impl SerializableTileData for TileEnvironment {
+    const KEY: &'static str = "environment";
+}
+
+impl Add for TileEnvironment {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            temperature: self.temperature + rhs.temperature,
+            moisture: self.moisture + rhs.moisture,
+            illuminance: self.illuminance + rhs.illuminance,
+        }
+    }
+}
+
+/// The quantified environment on a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalEnvironment {
+    pub temperature: Temperature,
+    pub moisture: Moisture,
+    pub illuminance: Illuminance,
+}
+
+impl LocalEnvironment {
+    pub fn new(body: &ParameterizedBody, tile: Option<&TileEnvironment>) -> Self {
+        let tile = tile.copied().unwrap_or_default();
+        Self {
+            temperature: Temperature::quantify(body.temperature + tile.temperature),
+            // Moisture is a ratio, and quantifying panics on values out of range.
+            moisture: Moisture::quantify(
+                (body.moisture + tile.moisture).clamp(0., 1. - f64::EPSILON),
+            ),
+            illuminance: Illuminance::quantify(body.illuminance + tile.illuminance),
         }
     }
 }
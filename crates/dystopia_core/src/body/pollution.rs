@@ -0,0 +1,193 @@
+//! Pollution produced by facilities, measured in DPI.
+//!
+//! Sources pollute the tiles they're on, and pollution spreads to neighbouring
+//! tiles and slowly decays. The pollution of the body is the average over all
+//! tiles, which affects its [`habitability`](crate::body::QuantifiedBody::habitability).
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin},
+    math::IVec2,
+    prelude::{
+        in_state, Commands, Component, Entity, EventWriter, IntoSystemConfigs, Query, Res, ResMut,
+    },
+    utils::{HashMap, HashSet},
+};
+use bincode::{Decode, Encode};
+use rayon::iter::ParallelIterator;
+
+use crate::{
+    body::{quantify::Pollution, BodyQuantifiedChanged},
+    cosmos::celestial::{BodyIndex, BodyTilemap, Cosmos},
+    map::{
+        data::{SerializableTileData, TileDataAppExt, TileDataStorage},
+        query::TileNeighbourhood,
+        tilemap::TilemapStorage,
+    },
+    schedule::state::GameState,
+    sci::Quantified,
+    sim::{global_clock, Ticker},
+};
+
+/// Pollution spreads once per this many ticks.
+pub const POLLUTION_SPREAD_INTERVAL: u64 = 10;
+/// Portion of the difference exchanged with each neighbour in one spread. Must
+/// be less than `0.25` to keep stable.
+pub const POLLUTION_DIFFUSION_RATE: f64 = 0.1;
+/// Portion of pollution removed in one spread.
+pub const POLLUTION_DECAY_RATE: f64 = 0.002;
+/// Tiles with less pollution are considered clean.
+const POLLUTION_EPSILON: f64 = 0.01;
+
+pub(super) struct PollutionPlugin;
+
+impl Plugin for PollutionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_serializable_tile_data::<TilePollution>()
+            .add_systems(
+                FixedUpdate,
+                (emit_pollution, spread_pollution)
+                    .chain()
+                    .after(global_clock)
+                    .run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+/// Pollution on a tile, in DPI.
+#[derive(Encode, Decode, Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct TilePollution(pub f64);
+
+impl SerializableTileData for TilePollution {
+    const KEY: &'static str = "pollution";
+}
+
+impl TilePollution {
+    #[inline]
+    pub fn quantified(self) -> Pollution {
+        Pollution::quantify(self.0.clamp(0., 100. - f64::EPSILON))
+    }
+
+    /// Multiplier of crop yields on this tile.
+    pub fn crop_yield_factor(self) -> f64 {
+        match self.quantified() {
+            Pollution::Clean => 1.,
+            // From 1 down to 0.5.
+            Pollution::Slight => 1. - (self.0 - 20.) / 60. * 0.5,
+            Pollution::Heavy => 0.2,
+        }
+    }
+}
+
+/// Facilities that pollute the tile they're on.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PollutionSource {
+    /// The root tilemap of the body.
+    pub tilemap: Entity,
+    pub index: IVec2,
+    /// DPI per tick.
+    pub rate: f64,
+}
+
+fn emit_pollution(
+    mut commands: Commands,
+    sources_query: Query<&PollutionSource>,
+    mut tilemaps_query: Query<(&TilemapStorage, Option<&mut TileDataStorage<TilePollution>>)>,
+) {
+    let mut created = HashMap::<Entity, TileDataStorage<TilePollution>>::default();
+
+    for source in &sources_query {
+        let Ok((tiles, pollution)) = tilemaps_query.get_mut(source.tilemap) else {
+            continue;
+        };
+        let pollution = match pollution {
+            Some(pollution) => pollution.into_inner(),
+            None => created
+                .entry(source.tilemap)
+                .or_insert_with(|| TileDataStorage::new(tiles.chunk_size())),
+        };
+
+        let cur = pollution.get(source.index).copied().unwrap_or_default();
+        pollution.set(
+            source.index,
+            TilePollution((cur.0 + source.rate).clamp(0., 100.)),
+        );
+    }
+
+    for (tilemap, pollution) in created {
+        commands.entity(tilemap).insert(pollution);
+    }
+}
+
+fn spread_pollution(
+    bodies_query: Query<(&BodyIndex, &BodyTilemap)>,
+    mut tilemaps_query: Query<(&TilemapStorage, &mut TileDataStorage<TilePollution>)>,
+    mut quantified_changed: EventWriter<BodyQuantifiedChanged>,
+    mut cosmos: ResMut<Cosmos>,
+    ticker: Res<Ticker>,
+) {
+    if **ticker % POLLUTION_SPREAD_INTERVAL != 0 {
+        return;
+    }
+
+    for (body_index, body_tilemap) in &bodies_query {
+        let Ok((tiles, mut pollution)) = tilemaps_query.get_mut(**body_tilemap) else {
+            continue;
+        };
+
+        let chunk_size = pollution.chunk_size();
+        let cur = pollution
+            .par_iter()
+            .map(|(index, p)| (index.to_direct(chunk_size), p.0))
+            .collect::<HashMap<_, _>>();
+        if cur.is_empty() {
+            continue;
+        }
+
+        // Polluted tiles and their neighbours, pollution only exists on tiles.
+        let affected = cur
+            .keys()
+            .flat_map(|index| {
+                std::iter::once(*index).chain(TileNeighbourhood::VonNeumann.neighbours(*index))
+            })
+            .filter(|index| tiles.get(*index).is_some())
+            .collect::<HashSet<_>>();
+
+        let mut total = 0.;
+        for index in affected {
+            let p = cur.get(&index).copied().unwrap_or_default();
+            let flow = TileNeighbourhood::VonNeumann
+                .neighbours(index)
+                .filter(|n| tiles.get(*n).is_some())
+                .map(|n| cur.get(&n).copied().unwrap_or_default() - p)
+                .sum::<f64>();
+            let new = ((p + flow * POLLUTION_DIFFUSION_RATE) * (1. - POLLUTION_DECAY_RATE))
+                .clamp(0., 100.);
+
+            if new < POLLUTION_EPSILON {
+                if cur.contains_key(&index) {
+                    pollution.remove(index);
+                }
+            } else if new != p {
+                pollution.set(index, TilePollution(new));
+                total += new;
+            } else {
+                total += new;
+            }
+        }
+
+        let num_tiles = tiles.chunked_storage().iter_direct().count().max(1);
+        let Cosmos {
+            parameterized,
+            quantified,
+            ..
+        } = &mut *cosmos;
+        let body = &mut parameterized[**body_index];
+        body.pollution = total / num_tiles as f64;
+        body.clamp();
+        quantified_changed.send_batch(BodyQuantifiedChanged::requantify(
+            *body_index,
+            body,
+            &mut quantified[**body_index],
+        ));
+    }
+}
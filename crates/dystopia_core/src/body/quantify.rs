@@ -1,8 +1,20 @@
 use dystopia_derive::Quantified;
 use num_enum::TryFromPrimitive;
+use serde::{Deserialize, Serialize};
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, TryFromPrimitive, Quantified,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    TryFromPrimitive,
+    Quantified,
+    Serialize,
+    Deserialize,
 )]
 #[repr(usize)]
 #[quantify(f64)]
@@ -20,7 +32,18 @@ pub enum Temperature {
 }
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, TryFromPrimitive, Quantified,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    TryFromPrimitive,
+    Quantified,
+    Serialize,
+    Deserialize,
 )]
 #[repr(usize)]
 #[quantify(f64)]
@@ -37,7 +60,18 @@ pub enum Moisture {
 }
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, TryFromPrimitive, Quantified,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    TryFromPrimitive,
+    Quantified,
+    Serialize,
+    Deserialize,
 )]
 #[repr(usize)]
 #[quantify(f64)]
@@ -56,7 +90,18 @@ pub enum Metallicity {
 }
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, TryFromPrimitive, Quantified,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    TryFromPrimitive,
+    Quantified,
+    Serialize,
+    Deserialize,
 )]
 #[repr(usize)]
 #[quantify(f64)]
@@ -74,7 +119,18 @@ pub enum Density {
 }
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, TryFromPrimitive, Quantified,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    TryFromPrimitive,
+    Quantified,
+    Serialize,
+    Deserialize,
 )]
 #[repr(usize)]
 #[quantify(f64)]
@@ -88,7 +144,18 @@ pub enum Illuminance {
 }
 
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, TryFromPrimitive, Quantified,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    TryFromPrimitive,
+    Quantified,
+    Serialize,
+    Deserialize,
 )]
 #[repr(usize)]
 #[quantify(f64)]
@@ -101,3 +168,95 @@ pub enum AtmosphericDensity {
     #[boundary(3.)]
     Thick,
 }
+
+/// Mass of living things per area, in g/m².
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    TryFromPrimitive,
+    Quantified,
+    Serialize,
+    Deserialize,
+)]
+#[repr(usize)]
+#[quantify(f64)]
+#[min(0.)]
+#[max(10000.)]
+pub enum Biomass {
+    Barren,
+    #[boundary(50.)]
+    Sparse,
+    #[boundary(200.)]
+    Clustered,
+    #[boundary(1000.)]
+    Abundant,
+}
+
+/// The Dystopian Pollution Index, DPI for short.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    TryFromPrimitive,
+    Quantified,
+    Serialize,
+    Deserialize,
+)]
+#[repr(usize)]
+#[quantify(f64)]
+#[min(0.)]
+#[max(100.)]
+pub enum Pollution {
+    Clean,
+    #[boundary(20.)]
+    Slight,
+    #[boundary(80.)]
+    Heavy,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        body::quantify::{Moisture, Temperature},
+        sci::Quantified,
+    };
+
+    #[test]
+    fn boundaries() {
+        // Each variant covers `[lower, upper)`.
+        assert_eq!(Temperature::quantify(0.), Temperature::Freezing);
+        assert_eq!(Temperature::quantify(99.9), Temperature::Freezing);
+        assert_eq!(Temperature::quantify(100.), Temperature::Cold);
+        assert_eq!(Temperature::quantify(150.), Temperature::Cold);
+        assert_eq!(Temperature::quantify(200.), Temperature::Habitable);
+        assert_eq!(Temperature::quantify(399.9), Temperature::Habitable);
+        assert_eq!(Temperature::quantify(400.), Temperature::Hot);
+        assert_eq!(Temperature::quantify(500.), Temperature::Boiling);
+        // The last variant is unbounded without `max`.
+        assert_eq!(Temperature::quantify(1e5), Temperature::Boiling);
+
+        assert_eq!(Moisture::quantify(0.), Moisture::Parched);
+        assert_eq!(Moisture::quantify(0.05), Moisture::Dry);
+        assert_eq!(Moisture::quantify(0.1), Moisture::Dry);
+        assert_eq!(Moisture::quantify(0.15), Moisture::Moist);
+        assert_eq!(Moisture::quantify(0.9), Moisture::Saturated);
+        assert_eq!(Moisture::quantify(0.99), Moisture::Saturated);
+    }
+
+    #[test]
+    #[should_panic(expected = "Value out of range.")]
+    fn beyond_max() {
+        Moisture::quantify(1.);
+    }
+}
@@ -0,0 +1,177 @@
+//! Terraforming projects, which change parameters of bodies over ticks.
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin},
+    prelude::{
+        in_state, Commands, Component, DespawnRecursiveExt, Entity, Event, EventWriter,
+        IntoSystemConfigs, Query, ResMut,
+    },
+};
+
+use crate::{
+    body::BodyQuantifiedChanged,
+    cosmos::celestial::{BodyIndex, Cosmos},
+    schedule::state::GameState,
+    sim::global_clock,
+};
+
+pub(super) struct TerraformPlugin;
+
+impl Plugin for TerraformPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TerraformCompleted>().add_systems(
+            FixedUpdate,
+            advance_terraform_projects
+                .after(global_clock)
+                .run_if(in_state(GameState::Simulate)),
+        );
+    }
+}
+
+/// Changes of body parameters per tick.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BodyParameterDelta {
+    pub temperature: f64,
+    pub moisture: f64,
+    pub metallicity: f64,
+    pub illuminance: f64,
+    pub atmospheric_density: f64,
+}
+
+impl std::ops::Mul<f64> for BodyParameterDelta {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self {
+            temperature: self.temperature * rhs,
+            moisture: self.moisture * rhs,
+            metallicity: self.metallicity * rhs,
+            illuminance: self.illuminance * rhs,
+            atmospheric_density: self.atmospheric_density * rhs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerraformKind {
+    /// Release gas to thicken the atmosphere, which also traps heat.
+    AtmosphereThickening,
+    /// Bring water onto the surface.
+    Irrigation,
+    /// Reflect more starlight onto the body, making it brighter and warmer.
+    OrbitalMirror,
+    /// Scatter metal-rich asteroid dust over the body.
+    AsteroidSeeding,
+}
+
+impl TerraformKind {
+    /// Effect per tick with strength `1`.
+    pub fn delta(self) -> BodyParameterDelta {
+        match self {
+            TerraformKind::AtmosphereThickening => BodyParameterDelta {
+                atmospheric_density: 1e-4,
+                temperature: 5e-3,
+                ..Default::default()
+            },
+            TerraformKind::Irrigation => BodyParameterDelta {
+                moisture: 5e-5,
+                ..Default::default()
+            },
+            TerraformKind::OrbitalMirror => BodyParameterDelta {
+                illuminance: 1.,
+                temperature: 1e-2,
+                ..Default::default()
+            },
+            TerraformKind::AsteroidSeeding => BodyParameterDelta {
+                metallicity: 2e-5,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// A terraforming project on a body. Spawn it as an entity, and it will be
+/// despawned when finished.
+#[derive(Component, Debug, Clone)]
+pub struct TerraformProject {
+    pub body: BodyIndex,
+    pub kind: TerraformKind,
+    /// Multiplier of [`TerraformKind::delta`]. Use negative values to reverse
+    /// the effect.
+    pub strength: f64,
+    pub duration: u64,
+    pub elapsed: u64,
+}
+
+impl TerraformProject {
+    pub fn new(body: BodyIndex, kind: TerraformKind, strength: f64, duration: u64) -> Self {
+        Self {
+            body,
+            kind,
+            strength,
+            duration,
+            elapsed: 0,
+        }
+    }
+
+    #[inline]
+    pub fn progress(&self) -> f64 {
+        self.elapsed as f64 / self.duration.max(1) as f64
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TerraformCompleted {
+    pub body: BodyIndex,
+    pub kind: TerraformKind,
+}
+
+fn advance_terraform_projects(
+    mut commands: Commands,
+    mut projects_query: Query<(Entity, &mut TerraformProject)>,
+    mut cosmos: ResMut<Cosmos>,
+    mut quantified_changed: EventWriter<BodyQuantifiedChanged>,
+    mut completed: EventWriter<TerraformCompleted>,
+) {
+    if projects_query.is_empty() {
+        return;
+    }
+
+    let mut touched = Vec::new();
+    for (entity, mut project) in &mut projects_query {
+        let delta = project.kind.delta() * project.strength;
+        let body = &mut cosmos.parameterized[*project.body];
+        body.temperature += delta.temperature;
+        body.moisture += delta.moisture;
+        body.metallicity += delta.metallicity;
+        body.illuminance += delta.illuminance;
+        body.atmospheric_density += delta.atmospheric_density;
+        body.clamp();
+        touched.push(project.body);
+
+        project.elapsed += 1;
+        if project.elapsed >= project.duration {
+            commands.entity(entity).despawn_recursive();
+            completed.send(TerraformCompleted {
+                body: project.body,
+                kind: project.kind,
+            });
+        }
+    }
+
+    touched.sort_unstable_by_key(|b| **b);
+    touched.dedup_by_key(|b| **b);
+
+    for body in touched {
+        let Cosmos {
+            parameterized,
+            quantified,
+            ..
+        } = &mut *cosmos;
+        quantified_changed.send_batch(BodyQuantifiedChanged::requantify(
+            body,
+            &parameterized[*body],
+            &mut quantified[*body],
+        ));
+    }
+}
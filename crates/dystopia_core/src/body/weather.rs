@@ -0,0 +1,361 @@
+//! Weather on landable bodies.
+//!
+//! Every [`WEATHER_ROLL_INTERVAL`] ticks, bodies without weather roll for a new
+//! one, with chances depending on the [`QuantifiedBody`] and the parent star.
+//! Weather offsets the environment of all tiles while lasting, and slowly
+//! changes the [`TileWetness`] of tiles, which outlasts the weather itself.
+
+use std::ops::Range;
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin},
+    prelude::{
+        in_state, Commands, Component, Entity, Event, EventWriter, IntoSystemConfigs, Query, Res,
+        ResMut, With, Without,
+    },
+    utils::HashMap,
+};
+use bincode::{Decode, Encode};
+use rand::Rng;
+
+use crate::{
+    body::{
+        quantify::{AtmosphericDensity, Illuminance, Moisture, Temperature},
+        QuantifiedBody, TileEnvironment,
+    },
+    cosmos::celestial::{
+        BodyIndex, BodyTilemap, Cosmos, Landable, Star, StarLuminosity, StarType, System,
+    },
+    map::{
+        data::{SerializableTileData, TileDataAppExt, TileDataStorage},
+        tilemap::TilemapStorage,
+    },
+    schedule::state::GameState,
+    sci::unit::{RadiantFlux, Unit},
+    sim::{global_clock, GlobalRng, Ticker},
+};
+
+/// Bodies without weather roll for a new one once per this many ticks.
+pub const WEATHER_ROLL_INTERVAL: u64 = 600;
+/// Weather changes the wetness of tiles once per this many ticks.
+pub const WEATHER_EFFECT_INTERVAL: u64 = 60;
+/// Portion of wetness lost in one effect interval.
+pub const WETNESS_DECAY_RATE: f64 = 0.05;
+/// Moisture added to a tile when it's fully wet.
+pub const WETNESS_MOISTURE: f64 = 0.3;
+/// Tiles with less wetness are considered dry.
+const WETNESS_EPSILON: f64 = 0.001;
+
+pub(super) struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_serializable_tile_data::<TileWetness>()
+            .add_event::<WeatherStarted>()
+            .add_event::<WeatherEnded>()
+            .add_systems(
+                FixedUpdate,
+                (tick_weather, roll_weather, apply_weather)
+                    .chain()
+                    .after(global_clock)
+                    .run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WeatherKind {
+    Rain,
+    DustStorm,
+    HeatWave,
+    /// Caused by the parent star, regardless of the atmosphere.
+    SolarFlare,
+}
+
+impl WeatherKind {
+    pub const ALL: [WeatherKind; 4] = [
+        WeatherKind::Rain,
+        WeatherKind::DustStorm,
+        WeatherKind::HeatWave,
+        WeatherKind::SolarFlare,
+    ];
+
+    /// Range of ticks the weather lasts.
+    pub fn duration(self) -> Range<u64> {
+        match self {
+            WeatherKind::Rain => 600..3000,
+            WeatherKind::DustStorm => 1200..6000,
+            WeatherKind::HeatWave => 3000..9000,
+            WeatherKind::SolarFlare => 300..1200,
+        }
+    }
+
+    /// Offset applied to the environment of all tiles while lasting.
+    pub fn environment_offset(self, intensity: f64) -> TileEnvironment {
+        let (temperature, moisture, illuminance) = match self {
+            WeatherKind::Rain => (-10., 0.1, -3000.),
+            WeatherKind::DustStorm => (0., -0.05, -6000.),
+            WeatherKind::HeatWave => (60., -0.05, 0.),
+            WeatherKind::SolarFlare => (20., 0., 5000.),
+        };
+
+        TileEnvironment {
+            temperature: temperature * intensity,
+            moisture: moisture * intensity,
+            illuminance: illuminance * intensity,
+        }
+    }
+
+    /// Multiplier of crop growth speed while lasting.
+    pub fn crop_growth_factor(self, intensity: f64) -> f64 {
+        match self {
+            WeatherKind::Rain => 1.,
+            WeatherKind::DustStorm => 1. - 0.4 * intensity,
+            WeatherKind::HeatWave => 1. - 0.3 * intensity,
+            WeatherKind::SolarFlare => 1. - 0.5 * intensity,
+        }
+    }
+
+    /// Wetness added to tiles in one effect interval.
+    fn wetness_delta(self, intensity: f64) -> f64 {
+        match self {
+            WeatherKind::Rain => 0.1 * intensity,
+            WeatherKind::DustStorm => -0.03 * intensity,
+            WeatherKind::HeatWave => -0.05 * intensity,
+            WeatherKind::SolarFlare => 0.,
+        }
+    }
+
+    /// Chance of happening in one roll.
+    fn chance(self, body: &QuantifiedBody, star: Option<(StarType, f64)>) -> f64 {
+        let atmosphere = match body.atmospheric_density {
+            AtmosphericDensity::Sparse => 0.,
+            AtmosphericDensity::Moderate => 1.,
+            AtmosphericDensity::Thick => 1.5,
+        };
+
+        match self {
+            WeatherKind::Rain => {
+                let moisture = match body.moisture {
+                    Moisture::Parched => 0.,
+                    Moisture::Dry => 0.05,
+                    Moisture::Moist => 0.15,
+                    Moisture::Saturated => 0.3,
+                };
+                // Water freezes or boils away.
+                let temperature = match body.temperature {
+                    Temperature::Freezing | Temperature::Boiling => 0.,
+                    _ => 1.,
+                };
+                moisture * temperature * atmosphere
+            }
+            WeatherKind::DustStorm => {
+                let dryness = match body.moisture {
+                    Moisture::Parched => 0.15,
+                    Moisture::Dry => 0.08,
+                    Moisture::Moist => 0.02,
+                    Moisture::Saturated => 0.,
+                };
+                dryness * atmosphere
+            }
+            WeatherKind::HeatWave => {
+                let temperature = match body.temperature {
+                    Temperature::Freezing | Temperature::Cold => 0.,
+                    Temperature::Habitable => 0.05,
+                    Temperature::Hot => 0.12,
+                    Temperature::Boiling => 0.15,
+                };
+                let illuminance = match body.illuminance {
+                    Illuminance::Faint => 0.5,
+                    Illuminance::Moderate => 1.,
+                    Illuminance::Bright => 1.5,
+                };
+                temperature * illuminance
+            }
+            WeatherKind::SolarFlare => {
+                let Some((ty, luminosity)) = star else {
+                    return 0.;
+                };
+                // Hot stars are violent, and red dwarfs are notorious flare stars.
+                let base = match ty {
+                    StarType::O => 0.12,
+                    StarType::B => 0.09,
+                    StarType::A => 0.06,
+                    StarType::F => 0.04,
+                    StarType::G => 0.03,
+                    StarType::K => 0.03,
+                    StarType::M => 0.08,
+                };
+                let relative = luminosity / RadiantFlux::SolarLuminosity(1.).to_si();
+                base * (1. + relative.max(f64::MIN_POSITIVE).log10().clamp(-1., 2.) * 0.25)
+            }
+        }
+    }
+}
+
+/// The weather currently on a body.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Weather {
+    pub kind: WeatherKind,
+    /// In `(0, 1]`.
+    pub intensity: f64,
+    /// Ticks left.
+    pub remaining: u64,
+}
+
+impl Weather {
+    #[inline]
+    pub fn environment_offset(&self) -> TileEnvironment {
+        self.kind.environment_offset(self.intensity)
+    }
+
+    #[inline]
+    pub fn crop_growth_factor(&self) -> f64 {
+        self.kind.crop_growth_factor(self.intensity)
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WeatherStarted {
+    pub body: Entity,
+    pub kind: WeatherKind,
+    pub intensity: f64,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WeatherEnded {
+    pub body: Entity,
+    pub kind: WeatherKind,
+}
+
+/// Water left on a tile by weather, in `[-1, 1]`. Negative for tiles dried out.
+#[derive(Encode, Decode, Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct TileWetness(pub f64);
+
+impl SerializableTileData for TileWetness {
+    const KEY: &'static str = "wetness";
+}
+
+impl TileWetness {
+    /// Offset to the environment of the tile.
+    #[inline]
+    pub fn environment_offset(self) -> TileEnvironment {
+        TileEnvironment {
+            moisture: self.0 * WETNESS_MOISTURE,
+            ..Default::default()
+        }
+    }
+}
+
+fn tick_weather(
+    mut commands: Commands,
+    mut bodies_query: Query<(Entity, &mut Weather)>,
+    mut ended: EventWriter<WeatherEnded>,
+) {
+    for (entity, mut weather) in &mut bodies_query {
+        weather.remaining = weather.remaining.saturating_sub(1);
+        if weather.remaining == 0 {
+            commands.entity(entity).remove::<Weather>();
+            ended.send(WeatherEnded {
+                body: entity,
+                kind: weather.kind,
+            });
+        }
+    }
+}
+
+fn roll_weather(
+    mut commands: Commands,
+    bodies_query: Query<(Entity, &BodyIndex), (With<Landable>, Without<Weather>)>,
+    stars_query: Query<(&System, &StarType, &StarLuminosity), With<Star>>,
+    mut started: EventWriter<WeatherStarted>,
+    rng: Option<ResMut<GlobalRng>>,
+    cosmos: Res<Cosmos>,
+    ticker: Res<Ticker>,
+) {
+    if **ticker % WEATHER_ROLL_INTERVAL != 0 {
+        return;
+    }
+    let Some(mut rng) = rng else {
+        return;
+    };
+
+    let stars = stars_query
+        .iter()
+        .flat_map(|(system, ty, luminosity)| {
+            system.iter().map(move |body| (**body, (*ty, **luminosity)))
+        })
+        .collect::<HashMap<_, _>>();
+
+    for (entity, body_index) in &bodies_query {
+        let body = &cosmos.quantified[**body_index];
+        let star = stars.get(&**body_index).copied();
+
+        let mut roll = rng.gen::<f64>();
+        let Some(kind) = WeatherKind::ALL.into_iter().find(|kind| {
+            roll -= kind.chance(body, star);
+            roll < 0.
+        }) else {
+            continue;
+        };
+
+        let weather = Weather {
+            kind,
+            intensity: rng.gen_range(0.2..=1.),
+            remaining: rng.gen_range(kind.duration()),
+        };
+        commands.entity(entity).insert(weather);
+        started.send(WeatherStarted {
+            body: entity,
+            kind,
+            intensity: weather.intensity,
+        });
+    }
+}
+
+fn apply_weather(
+    mut commands: Commands,
+    bodies_query: Query<(&BodyTilemap, Option<&Weather>)>,
+    mut tilemaps_query: Query<(&TilemapStorage, Option<&mut TileDataStorage<TileWetness>>)>,
+    ticker: Res<Ticker>,
+) {
+    if **ticker % WEATHER_EFFECT_INTERVAL != 0 {
+        return;
+    }
+
+    for (body_tilemap, weather) in &bodies_query {
+        let Ok((tiles, wetness)) = tilemaps_query.get_mut(**body_tilemap) else {
+            continue;
+        };
+        let delta = weather
+            .map(|w| w.kind.wetness_delta(w.intensity))
+            .unwrap_or_default();
+
+        let Some(mut wetness) = wetness else {
+            if delta != 0. {
+                let mut wetness = TileDataStorage::new(tiles.chunk_size());
+                for (index, _) in tiles.chunked_storage().iter_direct() {
+                    wetness.set(index, TileWetness(delta.clamp(-1., 1.)));
+                }
+                commands.entity(**body_tilemap).insert(wetness);
+            }
+            continue;
+        };
+
+        if delta == 0. {
+            wetness.par_for_each_mut(|_, w| {
+                if w.0.abs() < WETNESS_EPSILON {
+                    return false;
+                }
+                w.0 *= 1. - WETNESS_DECAY_RATE;
+                true
+            });
+        } else {
+            for (index, _) in tiles.chunked_storage().iter_direct() {
+                let cur = wetness.get(index).copied().unwrap_or_default();
+                let new = ((cur.0 + delta) * (1. - WETNESS_DECAY_RATE)).clamp(-1., 1.);
+                wetness.set(index, TileWetness(new));
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Building definitions.
+
+use bevy::{
+    asset::Asset,
+    log::warn,
+    math::IVec2,
+    prelude::{Deref, Resource},
+    reflect::TypePath,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::assets::config::RawConfig;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RawBuilding {
+    /// Width and height in tiles.
+    footprint: [u32; 2],
+    #[serde(default)]
+    cost: HashMap<String, u32>,
+    build_time: u64,
+    #[serde(default)]
+    power: f64,
+    #[serde(default)]
+    produces: HashMap<String, f64>,
+    #[serde(default)]
+    consumes: HashMap<String, f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildingDef {
+    /// Offsets of tiles occupied, relative to the anchor.
+    pub footprint: Vec<IVec2>,
+    /// Items taken from the inventory of the body when placed.
+    pub cost: HashMap<String, u32>,
+    /// Ticks the construction takes.
+    pub build_time: u64,
+    /// In W. Positive for generating and negative for consuming.
+    pub power: f64,
+    /// Items produced per tick.
+    pub produces: HashMap<String, f64>,
+    /// Items consumed per tick.
+    pub consumes: HashMap<String, f64>,
+}
+
+impl From<RawBuilding> for BuildingDef {
+    fn from(value: RawBuilding) -> Self {
+        let [w, h] = value.footprint;
+
+        Self {
+            footprint: (0..h as i32)
+                .flat_map(|y| (0..w as i32).map(move |x| IVec2::new(x, y)))
+                .collect(),
+            cost: value.cost,
+            build_time: value.build_time,
+            power: value.power,
+            produces: value.produces,
+            consumes: value.consumes,
+        }
+    }
+}
+
+impl BuildingDef {
+    /// Tiles occupied when placed at `anchor`.
+    #[inline]
+    pub fn tiles(&self, anchor: IVec2) -> impl Iterator<Item = IVec2> + '_ {
+        self.footprint.iter().map(move |offset| anchor + *offset)
+    }
+}
+
+#[derive(Asset, TypePath, Clone, Serialize, Deserialize)]
+pub struct RawBuildingRegistry(HashMap<String, RawBuilding>);
+
+impl RawConfig for RawBuildingRegistry {
+    type Processed = BuildingRegistry;
+
+    const PATH: &'static str = "configs/buildings.json";
+}
+
+/// All buildings, keyed by their ids.
+#[derive(Resource, Deref)]
+pub struct BuildingRegistry(HashMap<String, BuildingDef>);
+
+impl From<RawBuildingRegistry> for BuildingRegistry {
+    fn from(value: RawBuildingRegistry) -> Self {
+        Self(
+            value
+                .0
+                .into_iter()
+                .filter(|(id, building)| {
+                    let valid = building.footprint.iter().all(|l| *l > 0);
+                    if !valid {
+                        warn!("Building {} has empty footprint, skipped.", id);
+                    }
+                    valid
+                })
+                .map(|(id, building)| (id, building.into()))
+                .collect(),
+        )
+    }
+}
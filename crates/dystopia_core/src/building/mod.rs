@@ -0,0 +1,494 @@
+//! Facilities built on tiles of landable bodies.
+//!
+//! Buildings are stored in [`TileDataStorage`]s on the root tilemap of the body,
+//! so they are saved along with the tilemap. Each building also has an entity,
+//! as a child of the tilemap, linked to its tiles by [`TilemapBuildings`].
+//! Components on the entity, like [`ProductionBuffer`], are not saved.
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    core::Name,
+    log::warn,
+    math::IVec2,
+    prelude::{
+        in_state, BuildChildren, Commands, Component, Deref, Entity, Event, EventReader,
+        EventWriter, Has, IntoSystemConfigs, Query, Res, With, Without,
+    },
+    utils::HashMap,
+};
+use bincode::{Decode, Encode};
+use rayon::iter::ParallelIterator;
+use thiserror::Error;
+
+use crate::{
+    assets::app_ext::DystopiaAssetAppExt,
+    building::{
+        def::{BuildingRegistry, RawBuildingRegistry},
+        network::{Conduit, TilemapNetworks},
+    },
+    cosmos::celestial::{BodyTilemap, Landable},
+    farming::CropState,
+    inventory::{item::ItemRegistry, Inventory, InventoryError, BODY_INVENTORY_CAPACITY},
+    map::{
+        data::{SerializableTileData, TileDataAppExt, TileDataStorage},
+        tilemap::TilemapStorage,
+    },
+    schedule::state::GameState,
+    sim::global_clock,
+    tech::{
+        tree::{TechTree, TechUnlock},
+        ResearchState,
+    },
+};
+
+pub mod def;
+pub mod network;
+
+pub struct DystopiaBuildingPlugin;
+
+impl Plugin for DystopiaBuildingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(network::NetworkPlugin)
+            .add_config::<RawBuildingRegistry>()
+            .add_serializable_tile_data::<BuildingOccupancy>()
+            .add_serializable_tile_data::<BuildingState>()
+            .add_event::<PlaceBuilding>()
+            .add_event::<BuildingPlaced>()
+            .add_event::<BuildingCompleted>()
+            .add_systems(
+                Update,
+                (spawn_building_entities, place_buildings)
+                    .chain()
+                    .run_if(in_state(GameState::Simulate)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (construct_buildings, run_production)
+                    .chain()
+                    .after(global_clock)
+                    .run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+/// Marks the tile is occupied by the building at `anchor`.
+#[derive(Encode, Decode, Debug, Clone, Copy)]
+pub struct BuildingOccupancy {
+    pub anchor: [i32; 2],
+}
+
+impl SerializableTileData for BuildingOccupancy {
+    const KEY: &'static str = "building_occupancy";
+}
+
+/// The building anchored at the tile.
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct BuildingState {
+    /// Id in [`BuildingRegistry`].
+    pub building: String,
+    /// Ticks constructed.
+    pub progress: u64,
+}
+
+impl SerializableTileData for BuildingState {
+    const KEY: &'static str = "building";
+}
+
+/// The entity of a building.
+#[derive(Component, Debug, Clone)]
+pub struct Building {
+    /// The root tilemap of the body.
+    pub tilemap: Entity,
+    pub anchor: IVec2,
+    /// Id in [`BuildingRegistry`].
+    pub building: String,
+}
+
+/// Marks the construction of the building is finished.
+#[derive(Component)]
+pub struct Constructed;
+
+/// Fractional items produced or consumed but not moved yet.
+///
+/// Not saved, so less than one item of each kind is lost per building when the
+/// tilemap is reloaded. Saving it would rewrite [`BuildingState`] every tick.
+#[derive(Component, Debug, Default, Clone)]
+pub struct ProductionBuffer {
+    pub produced: HashMap<String, f64>,
+    pub consumed: HashMap<String, f64>,
+}
+
+/// Entities of buildings on the tilemap, keyed by their anchors.
+#[derive(Component, Default, Deref)]
+pub struct TilemapBuildings(HashMap<IVec2, Entity>);
+
+#[derive(Error, Debug)]
+pub enum PlacementError {
+    #[error("Unknown building.")]
+    UnknownBuilding,
+    #[error("The building is not unlocked yet.")]
+    Locked,
+    #[error("The tilemap of the body is not loaded.")]
+    NoTilemap,
+    #[error("The body is not landable.")]
+    NotLandable,
+    #[error("Tile {0} doesn't exist.")]
+    NoTile(IVec2),
+    #[error("Tile {0} is occupied.")]
+    Occupied(IVec2),
+    #[error("{0}")]
+    Inventory(InventoryError),
+}
+
+/// Request to place a building on a body, with `anchor` at the bottom left.
+#[derive(Event, Debug, Clone)]
+pub struct PlaceBuilding {
+    pub body: Entity,
+    pub anchor: IVec2,
+    pub building: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct BuildingPlaced {
+    pub body: Entity,
+    pub entity: Entity,
+    pub building: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct BuildingCompleted {
+    pub entity: Entity,
+    pub building: String,
+}
+
+/// Spawn entities for buildings loaded from saves.
+fn spawn_building_entities(
+    mut commands: Commands,
+    tilemaps_query: Query<(Entity, &TileDataStorage<BuildingState>), Without<TilemapBuildings>>,
+    registry: Res<BuildingRegistry>,
+) {
+    for (tilemap, states) in &tilemaps_query {
+        let chunk_size = states.chunk_size();
+        let loaded = states
+            .par_iter()
+            .map(|(index, state)| (index.to_direct(chunk_size), state.clone()))
+            .collect::<Vec<_>>();
+        let mut buildings = HashMap::default();
+
+        for (anchor, state) in loaded {
+            let mut entity = commands.spawn((
+                Name::new(format!("Building {}", state.building)),
+                Building {
+                    tilemap,
+                    anchor,
+                    building: state.building.clone(),
+                },
+                ProductionBuffer::default(),
+            ));
+            if registry
+                .get(&state.building)
+                .is_some_and(|def| state.progress >= def.build_time)
+            {
+                entity.insert(Constructed);
+            }
+
+            buildings.insert(anchor, entity.set_parent(tilemap).id());
+        }
+
+        commands.entity(tilemap).insert(TilemapBuildings(buildings));
+    }
+}
+
+type BuildingStorages = (
+    TileDataStorage<BuildingOccupancy>,
+    TileDataStorage<BuildingState>,
+    TilemapBuildings,
+);
+
+fn place_buildings(
+    mut commands: Commands,
+    mut requests: EventReader<PlaceBuilding>,
+    mut placed: EventWriter<BuildingPlaced>,
+    mut bodies_query: Query<(&BodyTilemap, Has<Landable>, Option<&mut Inventory>)>,
+    mut tilemaps_query: Query<(
+        &TilemapStorage,
+        Option<&TileDataStorage<CropState>>,
+        Option<&TileDataStorage<Conduit>>,
+        Option<(
+            &mut TileDataStorage<BuildingOccupancy>,
+            &mut TileDataStorage<BuildingState>,
+            &mut TilemapBuildings,
+        )>,
+    )>,
+    registry: Res<BuildingRegistry>,
+    items: Res<ItemRegistry>,
+    research: Res<ResearchState>,
+    tech_tree: Res<TechTree>,
+) {
+    // Storages created in this frame, as inserting them is deferred.
+    let mut created = HashMap::<Entity, BuildingStorages>::default();
+
+    for request in requests.read() {
+        let Some(def) = registry.get(&request.building) else {
+            warn!(
+                "Failed to place building {} at {}: {}",
+                request.building,
+                request.anchor,
+                PlacementError::UnknownBuilding
+            );
+            continue;
+        };
+
+        let result = (|| {
+            if !research.is_available(&tech_tree, &TechUnlock::Building(request.building.clone())) {
+                return Err(PlacementError::Locked);
+            }
+
+            let (tilemap, landable, mut inventory) = bodies_query
+                .get_mut(request.body)
+                .map_err(|_| PlacementError::NoTilemap)?;
+            if !landable {
+                return Err(PlacementError::NotLandable);
+            }
+
+            let (tiles, crops, conduits, storages) = tilemaps_query
+                .get_mut(**tilemap)
+                .map_err(|_| PlacementError::NoTilemap)?;
+            let (occupancy, states, buildings) = match storages {
+                Some((o, s, b)) => (o.into_inner(), s.into_inner(), b.into_inner()),
+                None => {
+                    let (o, s, b) = created.entry(**tilemap).or_insert_with(|| {
+                        (
+                            TileDataStorage::new(tiles.chunk_size()),
+                            TileDataStorage::new(tiles.chunk_size()),
+                            TilemapBuildings::default(),
+                        )
+                    });
+                    (o, s, b)
+                }
+            };
+
+            for tile in def.tiles(request.anchor) {
+                if tiles.get(tile).is_none() {
+                    return Err(PlacementError::NoTile(tile));
+                }
+                if occupancy.get(tile).is_some()
+                    || crops.is_some_and(|c| c.get(tile).is_some())
+                    || conduits.is_some_and(|c| c.get(tile).is_some())
+                {
+                    return Err(PlacementError::Occupied(tile));
+                }
+            }
+
+            for (item, amount) in &def.cost {
+                let available = inventory.as_ref().map(|i| i.count(item)).unwrap_or(0);
+                if available < *amount {
+                    return Err(PlacementError::Inventory(InventoryError::NotEnough {
+                        item: item.clone(),
+                        required: *amount,
+                        available,
+                    }));
+                }
+            }
+            match &mut inventory {
+                Some(inventory) => {
+                    for (item, amount) in &def.cost {
+                        inventory
+                            .remove(&items, item, *amount)
+                            .map_err(|e| PlacementError::Inventory(e))?;
+                    }
+                }
+                // Buildings produce into the inventory of the body.
+                None => {
+                    commands
+                        .entity(request.body)
+                        .insert(Inventory::new(BODY_INVENTORY_CAPACITY));
+                }
+            }
+
+            let anchor = request.anchor.to_array();
+            for tile in def.tiles(request.anchor) {
+                occupancy.set(tile, BuildingOccupancy { anchor });
+            }
+            states.set(
+                request.anchor,
+                BuildingState {
+                    building: request.building.clone(),
+                    progress: 0,
+                },
+            );
+
+            let entity = commands
+                .spawn((
+                    Name::new(format!("Building {}", request.building)),
+                    Building {
+                        tilemap: **tilemap,
+                        anchor: request.anchor,
+                        building: request.building.clone(),
+                    },
+                    ProductionBuffer::default(),
+                ))
+                .set_parent(**tilemap)
+                .id();
+            buildings.0.insert(request.anchor, entity);
+
+            Ok(entity)
+        })();
+
+        match result {
+            Ok(entity) => {
+                placed.send(BuildingPlaced {
+                    body: request.body,
+                    entity,
+                    building: request.building.clone(),
+                });
+            }
+            Err(err) => warn!(
+                "Failed to place building {} at {}: {}",
+                request.building, request.anchor, err
+            ),
+        }
+    }
+
+    for (tilemap, storages) in created {
+        commands.entity(tilemap).insert(storages);
+    }
+}
+
+fn construct_buildings(
+    mut commands: Commands,
+    mut tilemaps_query: Query<(&mut TileDataStorage<BuildingState>, &TilemapBuildings)>,
+    mut completed: EventWriter<BuildingCompleted>,
+    registry: Res<BuildingRegistry>,
+) {
+    for (mut states, buildings) in &mut tilemaps_query {
+        let finished = std::sync::Mutex::new(Vec::new());
+
+        states.par_for_each_mut(|index, state| {
+            let Some(def) = registry.get(&state.building) else {
+                return false;
+            };
+            if state.progress >= def.build_time {
+                return false;
+            }
+
+            state.progress += 1;
+            if state.progress >= def.build_time {
+                finished
+                    .lock()
+                    .unwrap()
+                    .push((index, state.building.clone()));
+            }
+            true
+        });
+
+        let chunk_size = states.chunk_size();
+        for (index, building) in finished.into_inner().unwrap() {
+            let Some(entity) = buildings.get(&index.to_direct(chunk_size)) else {
+                continue;
+            };
+
+            commands.entity(*entity).insert(Constructed);
+            completed.send(BuildingCompleted {
+                entity: *entity,
+                building,
+            });
+        }
+    }
+}
+
+/// Constructed buildings consume from and produce into the inventory of the
+/// body, through their networks. Rates are scaled by the power satisfaction for
+/// buildings consuming power. A building stalls if inputs are not enough,
+/// outputs can't fit or the network is busy.
+fn run_production(
+    mut bodies_query: Query<(&BodyTilemap, &mut Inventory)>,
+    mut buildings_query: Query<(&Building, &mut ProductionBuffer), With<Constructed>>,
+    mut networks_query: Query<&mut TilemapNetworks>,
+    registry: Res<BuildingRegistry>,
+    items: Res<ItemRegistry>,
+) {
+    let mut inventories = bodies_query
+        .iter_mut()
+        .map(|(tilemap, inventory)| (**tilemap, inventory))
+        .collect::<HashMap<_, _>>();
+
+    for (building, mut buffer) in &mut buildings_query {
+        let Some(def) = registry.get(&building.building) else {
+            continue;
+        };
+        if def.produces.is_empty() && def.consumes.is_empty() {
+            continue;
+        }
+        let Some(inventory) = inventories.get_mut(&building.tilemap) else {
+            continue;
+        };
+
+        let mut networks = networks_query.get_mut(building.tilemap).ok();
+        let mut network = networks
+            .as_mut()
+            .and_then(|n| n.network_at_mut(building.anchor));
+        let satisfaction = if def.power < 0. {
+            network
+                .as_ref()
+                .map(|n| n.stats().satisfaction)
+                .unwrap_or(0.)
+        } else {
+            1.
+        };
+        if satisfaction <= 0. {
+            continue;
+        }
+
+        let consumed = def
+            .consumes
+            .iter()
+            .map(|(item, rate)| {
+                let pending =
+                    buffer.consumed.get(item).copied().unwrap_or_default() + rate * satisfaction;
+                (item, pending, pending.floor() as u32)
+            })
+            .collect::<Vec<_>>();
+        let produced = def
+            .produces
+            .iter()
+            .map(|(item, rate)| {
+                let pending =
+                    buffer.produced.get(item).copied().unwrap_or_default() + rate * satisfaction;
+                (item, pending, pending.floor() as u32)
+            })
+            .collect::<Vec<_>>();
+
+        let has_inputs = consumed
+            .iter()
+            .all(|(item, _, whole)| inventory.count(item) >= *whole);
+        let has_space = produced.iter().all(|(item, _, whole)| {
+            inventory
+                .fits(&items, item)
+                .is_ok_and(|fits| fits >= *whole)
+        });
+        if !has_inputs || !has_space {
+            continue;
+        }
+
+        let moved = consumed
+            .iter()
+            .chain(produced.iter())
+            .map(|(_, _, whole)| *whole)
+            .sum::<u32>();
+        if network.as_mut().is_some_and(|n| !n.try_move(moved)) {
+            continue;
+        }
+
+        for (item, pending, whole) in consumed {
+            if inventory.remove(&items, item, whole).is_ok() {
+                buffer.consumed.insert(item.clone(), pending - whole as f64);
+            }
+        }
+        for (item, pending, whole) in produced {
+            if inventory.insert(&items, item, whole).is_ok() {
+                buffer.produced.insert(item.clone(), pending - whole as f64);
+            }
+        }
+    }
+}
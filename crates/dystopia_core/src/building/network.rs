@@ -0,0 +1,455 @@
+//! Networks of buildings connected by conduits, which share power and move
+//! items.
+//!
+//! Conduits and tiles occupied by buildings are connected to their
+//! [`VonNeumann`](TileNeighbourhood::VonNeumann) neighbours. Networks are only
+//! solved again around changed tiles.
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    log::warn,
+    math::IVec2,
+    prelude::{
+        in_state, Commands, Component, Entity, Event, EventReader, Has, IntoSystemConfigs, Or,
+        Query, Ref, Res, With,
+    },
+    utils::{HashMap, HashSet},
+};
+use bincode::{Decode, Encode};
+
+use crate::{
+    building::{
+        def::BuildingRegistry, place_buildings, run_production, Building, BuildingOccupancy,
+        Constructed, TilemapBuildings,
+    },
+    farming::CropState,
+    map::{
+        data::{SerializableTileData, TileData, TileDataAppExt, TileDataStorage},
+        query::TileNeighbourhood,
+        tilemap::{FlattenedTileIndex, TilemapStorage},
+    },
+    schedule::state::GameState,
+    util::chunking::{ChunkedStorage, DEFAULT_CHUNK_SIZE},
+};
+
+/// Items per tick a conduit can move, if not specified.
+pub const DEFAULT_CONDUIT_THROUGHPUT: u32 = 10;
+
+pub(super) struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_serializable_tile_data::<Conduit>()
+            .add_event::<PlaceConduit>()
+            .add_event::<RemoveConduit>()
+            .add_systems(
+                Update,
+                (edit_conduits, solve_networks)
+                    .chain()
+                    .after(place_buildings)
+                    .run_if(in_state(GameState::Simulate)),
+            )
+            .add_systems(
+                FixedUpdate,
+                update_network_power
+                    .before(run_production)
+                    .run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+/// A conduit on the tile.
+#[derive(Encode, Decode, Debug, Clone, Copy)]
+pub struct Conduit {
+    /// Items per tick.
+    pub throughput: u32,
+}
+
+impl SerializableTileData for Conduit {
+    const KEY: &'static str = "conduit";
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NetworkStats {
+    /// In W.
+    pub generation: f64,
+    /// In W.
+    pub consumption: f64,
+    /// Portion of consumption covered by generation, in `[0, 1]`.
+    pub satisfaction: f64,
+    /// Items per tick the network can move, limited by the slowest conduit.
+    pub throughput: u32,
+    /// Items moved in the last tick.
+    pub moved: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Network {
+    tiles: Vec<IVec2>,
+    anchors: HashSet<IVec2>,
+    throughput: u32,
+    stats: NetworkStats,
+}
+
+impl Network {
+    #[inline]
+    pub fn tiles(&self) -> &[IVec2] {
+        &self.tiles
+    }
+
+    /// Anchors of buildings in the network.
+    #[inline]
+    pub fn anchors(&self) -> &HashSet<IVec2> {
+        &self.anchors
+    }
+
+    #[inline]
+    pub fn stats(&self) -> &NetworkStats {
+        &self.stats
+    }
+
+    /// Try to move `amount` items through the network in this tick.
+    pub(super) fn try_move(&mut self, amount: u32) -> bool {
+        let moved = self.stats.moved.saturating_add(amount);
+        if moved > self.stats.throughput {
+            return false;
+        }
+
+        self.stats.moved = moved;
+        true
+    }
+}
+
+/// All networks on a tilemap.
+#[derive(Component, Debug, Default)]
+pub struct TilemapNetworks {
+    networks: HashMap<u32, Network>,
+    labels: HashMap<IVec2, u32>,
+    next_id: u32,
+}
+
+impl TilemapNetworks {
+    #[inline]
+    pub fn get(&self, id: u32) -> Option<&Network> {
+        self.networks.get(&id)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Network)> {
+        self.networks.iter().map(|(id, n)| (*id, n))
+    }
+
+    /// The network the tile belongs to. Use the anchor to find the network of a
+    /// building.
+    #[inline]
+    pub fn network_at(&self, index: IVec2) -> Option<(u32, &Network)> {
+        let id = *self.labels.get(&index)?;
+        self.networks.get(&id).map(|n| (id, n))
+    }
+
+    #[inline]
+    pub(super) fn network_at_mut(&mut self, index: IVec2) -> Option<&mut Network> {
+        self.networks.get_mut(self.labels.get(&index)?)
+    }
+
+    /// Rebuild networks around `dirty` tiles. Networks touching them are
+    /// dissolved and flood filled again.
+    fn resolve(
+        &mut self,
+        dirty: impl IntoIterator<Item = IVec2>,
+        conduits: Option<&TileDataStorage<Conduit>>,
+        occupancy: Option<&TileDataStorage<BuildingOccupancy>>,
+    ) {
+        let connectable = connectable_tiles(conduits, occupancy);
+
+        let mut seeds = dirty.into_iter().collect::<HashSet<_>>();
+        let affected = seeds
+            .iter()
+            .flat_map(|index| {
+                std::iter::once(*index).chain(TileNeighbourhood::VonNeumann.neighbours(*index))
+            })
+            .filter_map(|index| self.labels.get(&index).copied())
+            .collect::<HashSet<_>>();
+
+        for id in affected {
+            if let Some(network) = self.networks.remove(&id) {
+                for tile in network.tiles {
+                    self.labels.remove(&tile);
+                    seeds.insert(tile);
+                }
+            }
+        }
+
+        for seed in seeds {
+            if self.labels.contains_key(&seed) {
+                continue;
+            }
+
+            let tiles = connectable.flood_fill(seed, TileNeighbourhood::VonNeumann, |_, _| true);
+            if tiles.is_empty() {
+                continue;
+            }
+
+            let id = self.next_id;
+            self.next_id += 1;
+
+            let mut network = Network {
+                tiles: Vec::with_capacity(tiles.len()),
+                anchors: HashSet::default(),
+                throughput: u32::MAX,
+                stats: NetworkStats::default(),
+            };
+
+            for index in tiles {
+                let (throughput, anchor) = connectable.get_direct(index).unwrap();
+                if let Some(throughput) = throughput {
+                    network.throughput = network.throughput.min(*throughput);
+                }
+                if let Some(anchor) = anchor {
+                    network.anchors.insert(*anchor);
+                }
+
+                self.labels.insert(index, id);
+                network.tiles.push(index);
+            }
+
+            network.stats.throughput = network.throughput;
+            self.networks.insert(id, network);
+        }
+    }
+}
+
+/// Tiles that are part of a network, with the throughput of the conduit and the
+/// anchor of the building on them.
+fn connectable_tiles(
+    conduits: Option<&TileDataStorage<Conduit>>,
+    occupancy: Option<&TileDataStorage<BuildingOccupancy>>,
+) -> ChunkedStorage<IVec2, (Option<u32>, Option<IVec2>)> {
+    let chunk_size = conduits
+        .map(|c| c.chunk_size())
+        .or(occupancy.map(|o| o.chunk_size()))
+        .unwrap_or(DEFAULT_CHUNK_SIZE);
+    let mut connectable = ChunkedStorage::new(chunk_size);
+
+    for (index, conduit) in conduits
+        .into_iter()
+        .flat_map(|c| c.chunked_storage().iter_direct())
+    {
+        connectable
+            .get_or_insert(
+                FlattenedTileIndex::from_direct(index, chunk_size),
+                (None, None),
+            )
+            .0 = Some(conduit.throughput);
+    }
+
+    for (index, occupancy) in occupancy
+        .into_iter()
+        .flat_map(|o| o.chunked_storage().iter_direct())
+    {
+        connectable
+            .get_or_insert(
+                FlattenedTileIndex::from_direct(index, chunk_size),
+                (None, None),
+            )
+            .1 = Some(IVec2::from_array(occupancy.anchor));
+    }
+
+    connectable
+}
+
+/// Request to place a conduit on a tile without buildings or crops.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlaceConduit {
+    /// The root tilemap of the body.
+    pub tilemap: Entity,
+    pub index: IVec2,
+    pub throughput: u32,
+}
+
+impl PlaceConduit {
+    pub fn new(tilemap: Entity, index: IVec2) -> Self {
+        Self {
+            tilemap,
+            index,
+            throughput: DEFAULT_CONDUIT_THROUGHPUT,
+        }
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RemoveConduit {
+    pub tilemap: Entity,
+    pub index: IVec2,
+}
+
+fn edit_conduits(
+    mut commands: Commands,
+    mut place_requests: EventReader<PlaceConduit>,
+    mut remove_requests: EventReader<RemoveConduit>,
+    mut tilemaps_query: Query<(
+        &TilemapStorage,
+        Option<&mut TileDataStorage<Conduit>>,
+        Option<&TileDataStorage<BuildingOccupancy>>,
+        Option<&TileDataStorage<CropState>>,
+    )>,
+) {
+    // Storages created in this frame, as inserting them is deferred.
+    let mut created = HashMap::<Entity, TileDataStorage<Conduit>>::default();
+
+    for request in place_requests.read() {
+        let Ok((tiles, conduits, occupancy, crops)) = tilemaps_query.get_mut(request.tilemap)
+        else {
+            warn!("Tilemap {} not found.", request.tilemap);
+            continue;
+        };
+
+        if tiles.get(request.index).is_none() {
+            warn!("Can't place conduit on empty tile {}.", request.index);
+            continue;
+        }
+        if occupancy.is_some_and(|o| o.get(request.index).is_some())
+            || crops.is_some_and(|c| c.get(request.index).is_some())
+        {
+            warn!("Tile {} is occupied.", request.index);
+            continue;
+        }
+
+        let conduits = match conduits {
+            Some(conduits) => conduits.into_inner(),
+            None => created
+                .entry(request.tilemap)
+                .or_insert_with(|| TileDataStorage::new(tiles.chunk_size())),
+        };
+        conduits.set(
+            request.index,
+            Conduit {
+                throughput: request.throughput,
+            },
+        );
+    }
+
+    for request in remove_requests.read() {
+        let removed = match tilemaps_query.get_mut(request.tilemap) {
+            Ok((_, Some(mut conduits), ..)) => conduits.remove(request.index),
+            _ => created
+                .get_mut(&request.tilemap)
+                .and_then(|c| c.remove(request.index)),
+        };
+        if removed.is_none() {
+            warn!("No conduit on tile {}.", request.index);
+        }
+    }
+
+    for (tilemap, conduits) in created {
+        commands.entity(tilemap).insert(conduits);
+    }
+}
+
+fn solve_networks(
+    mut commands: Commands,
+    mut tilemaps_query: Query<
+        (
+            Entity,
+            Option<Ref<TileDataStorage<Conduit>>>,
+            Option<Ref<TileDataStorage<BuildingOccupancy>>>,
+            Option<&mut TilemapNetworks>,
+        ),
+        Or<(
+            With<TileDataStorage<Conduit>>,
+            With<TileDataStorage<BuildingOccupancy>>,
+        )>,
+    >,
+) {
+    for (tilemap, conduits, occupancy, networks) in &mut tilemaps_query {
+        // Changes in newly inserted storages may have been cleared, so solve
+        // them from scratch.
+        let full = networks.is_none()
+            || conduits.as_ref().is_some_and(|c| c.is_added())
+            || occupancy.as_ref().is_some_and(|o| o.is_added());
+
+        let conduits = conduits.as_deref();
+        let occupancy = occupancy.as_deref();
+        let dirty = if full {
+            conduits
+                .into_iter()
+                .flat_map(|c| c.chunked_storage().iter_direct().map(|(i, _)| i))
+                .chain(
+                    occupancy
+                        .into_iter()
+                        .flat_map(|o| o.chunked_storage().iter_direct().map(|(i, _)| i)),
+                )
+                .collect::<Vec<_>>()
+        } else {
+            conduits
+                .map(|c| changed_indices(c))
+                .unwrap_or_default()
+                .into_iter()
+                .chain(occupancy.map(|o| changed_indices(o)).unwrap_or_default())
+                .collect()
+        };
+
+        match networks {
+            Some(mut networks) => {
+                if full {
+                    *networks = TilemapNetworks::default();
+                }
+                if !dirty.is_empty() {
+                    networks.resolve(dirty, conduits, occupancy);
+                }
+            }
+            None => {
+                let mut networks = TilemapNetworks::default();
+                networks.resolve(dirty, conduits, occupancy);
+                commands.entity(tilemap).insert(networks);
+            }
+        }
+    }
+}
+
+fn changed_indices<T: TileData>(storage: &TileDataStorage<T>) -> Vec<IVec2> {
+    let chunk_size = storage.chunk_size();
+    storage
+        .changed_tiles()
+        .iter()
+        .map(|index| index.to_direct(chunk_size))
+        .collect()
+}
+
+/// Sum up power of constructed buildings, and reset items moved, before
+/// buildings run.
+fn update_network_power(
+    mut tilemaps_query: Query<(&mut TilemapNetworks, &TilemapBuildings)>,
+    buildings_query: Query<(&Building, Has<Constructed>)>,
+    registry: Res<BuildingRegistry>,
+) {
+    for (mut networks, buildings) in &mut tilemaps_query {
+        for network in networks.networks.values_mut() {
+            let (generation, consumption) = network
+                .anchors
+                .iter()
+                .filter_map(|anchor| buildings_query.get(*buildings.get(anchor)?).ok())
+                .filter(|(_, constructed)| *constructed)
+                .filter_map(|(building, _)| registry.get(&building.building))
+                .fold((0., 0.), |(g, c), def| {
+                    if def.power > 0. {
+                        (g + def.power, c)
+                    } else {
+                        (g, c - def.power)
+                    }
+                });
+
+            network.stats = NetworkStats {
+                generation,
+                consumption,
+                satisfaction: if consumption > 0. {
+                    (generation / consumption).min(1.)
+                } else {
+                    1.
+                },
+                throughput: network.throughput,
+                moved: 0,
+            };
+        }
+    }
+}
@@ -10,7 +10,7 @@ use bevy::{
 
 use crate::{
     cosmos::{
-        celestial::{BodyColor, BodyIndex, BodyType, Star, StarType, System},
+        celestial::{BodyColor, BodyIndex, BodyType, Star, StarLuminosity, StarType, System},
         mesh::{GiantBodyMaterial, RockyBodyMaterial, StarMaterial},
     },
     scene::transition::CameraRecoverTransform,
@@ -20,6 +20,7 @@ use crate::{
 pub struct StarBundle {
     pub star: Star,
     pub star_ty: StarType,
+    pub luminosity: StarLuminosity,
     pub name: Name,
     pub body_index: BodyIndex,
     pub system: System,
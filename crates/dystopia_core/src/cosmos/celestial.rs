@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     body::{ParameterizedBody, QuantifiedBody},
+    map::serde::TilemapFormat,
     tuple_struct_new,
 };
 
@@ -54,6 +55,11 @@ tuple_struct_new!(OrbitIndex, usize);
 #[derive(Component, Debug, Default)]
 pub struct Star;
 
+/// Luminosity of a star, in W.
+#[derive(Component, Debug, Default, Clone, Copy, Deref)]
+pub struct StarLuminosity(f64);
+tuple_struct_new!(StarLuminosity, f64);
+
 /// All bodies in this system.
 #[derive(Component, Default, Deref, DerefMut)]
 pub struct System(Vec<BodyIndex>);
@@ -97,7 +103,7 @@ pub struct StarClass {
 }
 
 /// The type of a planet.
-#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum BodyType {
     #[default]
     Rocky,
@@ -108,8 +114,9 @@ pub enum BodyType {
 /// Mark a body as landable, which means players can land on that body and
 /// build facilities.
 ///
-/// Stars, gas/ice giants and small asteroids are generally not landable.
-// TODO maybe support landing on these bodies after some technologies.
+/// Stars, gas/ice giants and small asteroids are generally not landable. Giants
+/// become landable after researching technologies that unlock
+/// [`TechUnlock::Landable`](crate::tech::tree::TechUnlock::Landable).
 #[derive(Component)]
 pub struct Landable;
 
@@ -136,6 +143,7 @@ pub struct ToLoadTilemap;
 pub struct ToSaveTilemap {
     /// Whether to remove tilemap from the entity.
     pub remove_after_done: bool,
+    pub format: TilemapFormat,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -24,14 +24,17 @@ use rand_distr::{num_traits::Float, Distribution, Normal, StandardNormal, Weight
 
 use crate::{
     body::{
-        quantify::{AtmosphericDensity, Density, Illuminance, Metallicity, Moisture, Temperature},
+        quantify::{
+            AtmosphericDensity, Biomass, Density, Illuminance, Metallicity, Moisture, Pollution,
+            Temperature,
+        },
         ParameterizedBody, QuantifiedBody,
     },
     cosmos::{
         bundle::{GiantBodyBundle, RockyBodyBundle, StarBundle},
         celestial::{
-            BodyColor, BodyIndex, BodyType, CelestialBodyData, Cosmos, Moon, Orbit, OrbitIndex,
-            Planet, StarClass, System,
+            BodyColor, BodyIndex, BodyType, CelestialBodyData, Cosmos, Landable, Moon, Orbit,
+            OrbitIndex, Planet, StarClass, StarLuminosity, System,
         },
         config::{CosmosStarNamesConfig, CosmosStarPropertiesConfig},
         gen::distr::*,
@@ -687,6 +690,18 @@ fn generate_parameterized_and_quantified_body(
 
     let atmospheric_density: AtmosphericDensity = sample_enum_weighted(rng, vec![60, 20, 10], 0);
 
+    // Life only thrives on bodies warm and wet enough.
+    let biomass: Biomass = match (temperature, moisture) {
+        (Temperature::Habitable, Moisture::Moist | Moisture::Saturated) => {
+            sample_enum_weighted(rng, vec![20, 30, 35, 15], 0)
+        }
+        (Temperature::Habitable, Moisture::Dry)
+        | (Temperature::Cold | Temperature::Hot, Moisture::Moist | Moisture::Saturated) => {
+            sample_enum_weighted(rng, vec![50, 35, 15], 0)
+        }
+        _ => Biomass::Barren,
+    };
+
     (
         ParameterizedBody {
             temperature: simple.temperature,
@@ -695,6 +710,8 @@ fn generate_parameterized_and_quantified_body(
             density: simple.density,
             illuminance: simple.illuminance,
             atmospheric_density: atmospheric_density.sample(rng),
+            pollution: 0.,
+            biomass: biomass.sample(rng),
         },
         QuantifiedBody {
             temperature,
@@ -703,6 +720,8 @@ fn generate_parameterized_and_quantified_body(
             density,
             illuminance,
             atmospheric_density,
+            pollution: Pollution::Clean,
+            biomass,
         },
     )
 }
@@ -725,6 +744,7 @@ fn spawn_bodies(
             commands
                 .spawn(StarBundle {
                     star_ty: star.class.ty,
+                    luminosity: StarLuminosity::new(star.luminosity),
                     name: Name::new(star.name.clone()),
                     body_index: BodyIndex::new(bodies.len()),
                     system: System::new(
@@ -778,6 +798,7 @@ fn spawn_bodies(
                                 ..Default::default()
                             },
                             Planet,
+                            Landable,
                         ))
                         .id(),
                 );
@@ -835,6 +856,7 @@ fn spawn_bodies(
                                 ..Default::default()
                             },
                             Moon,
+                            Landable,
                         ))
                         .id(),
                 );
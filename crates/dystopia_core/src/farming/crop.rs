@@ -0,0 +1,110 @@
+//! Crop definitions.
+
+use std::ops::RangeInclusive;
+
+use bevy::{
+    asset::Asset,
+    log::warn,
+    prelude::{Deref, Resource},
+    reflect::TypePath,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::config::RawConfig,
+    body::{
+        quantify::{Illuminance, Moisture, Temperature},
+        LocalEnvironment,
+    },
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RawCrop {
+    stages: Vec<u64>,
+    temperature: [Temperature; 2],
+    moisture: [Moisture; 2],
+    illuminance: [Illuminance; 2],
+    #[serde(rename = "yield")]
+    harvest: CropYield,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CropYield {
+    /// Id of the item.
+    pub item: String,
+    pub amount: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CropDef {
+    /// Ticks each growth stage takes. The crop is mature after the last stage.
+    pub stages: Vec<u64>,
+    pub temperature: RangeInclusive<Temperature>,
+    pub moisture: RangeInclusive<Moisture>,
+    pub illuminance: RangeInclusive<Illuminance>,
+    pub harvest: CropYield,
+}
+
+impl From<RawCrop> for CropDef {
+    fn from(value: RawCrop) -> Self {
+        let [t0, t1] = value.temperature;
+        let [m0, m1] = value.moisture;
+        let [i0, i1] = value.illuminance;
+
+        Self {
+            stages: value.stages,
+            temperature: t0.min(t1)..=t0.max(t1),
+            moisture: m0.min(m1)..=m0.max(m1),
+            illuminance: i0.min(i1)..=i0.max(i1),
+            harvest: value.harvest,
+        }
+    }
+}
+
+impl CropDef {
+    /// Number of stages before the crop is mature.
+    #[inline]
+    pub fn num_stages(&self) -> u32 {
+        self.stages.len() as u32
+    }
+
+    /// Crops only grow when all conditions are preferred.
+    pub fn prefers(&self, env: &LocalEnvironment) -> bool {
+        self.temperature.contains(&env.temperature)
+            && self.moisture.contains(&env.moisture)
+            && self.illuminance.contains(&env.illuminance)
+    }
+}
+
+#[derive(Asset, TypePath, Clone, Serialize, Deserialize)]
+pub struct RawCropRegistry(HashMap<String, RawCrop>);
+
+impl RawConfig for RawCropRegistry {
+    type Processed = CropRegistry;
+
+    const PATH: &'static str = "configs/crops.json";
+}
+
+/// All crops, keyed by their ids.
+#[derive(Resource, Deref)]
+pub struct CropRegistry(HashMap<String, CropDef>);
+
+impl From<RawCropRegistry> for CropRegistry {
+    fn from(value: RawCropRegistry) -> Self {
+        Self(
+            value
+                .0
+                .into_iter()
+                .filter(|(id, crop)| {
+                    let valid = !crop.stages.is_empty();
+                    if !valid {
+                        warn!("Crop {} has no stages, skipped.", id);
+                    }
+                    valid
+                })
+                .map(|(id, crop)| (id, crop.into()))
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,324 @@
+//! Planting, growing and harvesting crops on body tilemaps.
+//!
+//! Crops are stored as [`CropState`]s in [`TileDataStorage`] on the root
+//! tilemap of the body, so they are saved along with the tilemap.
+
+use std::sync::Mutex;
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    log::warn,
+    math::IVec2,
+    prelude::{
+        in_state, Commands, Entity, Event, EventReader, EventWriter, IntoSystemConfigs, Query, Res,
+    },
+    utils::HashMap,
+};
+use bincode::{Decode, Encode};
+
+use crate::{
+    assets::app_ext::DystopiaAssetAppExt,
+    body::{
+        pollution::TilePollution,
+        weather::{TileWetness, Weather},
+        LocalEnvironment, TileEnvironment,
+    },
+    building::{network::Conduit, BuildingOccupancy},
+    cosmos::celestial::{BodyIndex, BodyTilemap, Cosmos},
+    farming::crop::{CropRegistry, CropYield, RawCropRegistry},
+    map::{
+        data::{SerializableTileData, TileDataAppExt, TileDataStorage},
+        tilemap::{FlattenedTileIndex, TilemapStorage},
+    },
+    schedule::state::GameState,
+    sim::{global_clock, Ticker},
+    tech::{
+        tree::{TechTree, TechUnlock},
+        ResearchState,
+    },
+};
+
+pub mod crop;
+
+pub struct DystopiaFarmingPlugin;
+
+impl Plugin for DystopiaFarmingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_config::<RawCropRegistry>()
+            .add_serializable_tile_data::<CropState>()
+            .add_event::<PlantCrop>()
+            .add_event::<HarvestCrop>()
+            .add_event::<CropPlanted>()
+            .add_event::<CropStageChanged>()
+            .add_event::<CropHarvested>()
+            .add_systems(
+                FixedUpdate,
+                grow_crops
+                    .after(global_clock)
+                    .run_if(in_state(GameState::Simulate)),
+            )
+            .add_systems(
+                Update,
+                (plant_crops, harvest_crops).run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+/// The crop growing on a tile.
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct CropState {
+    /// Id in [`CropRegistry`].
+    pub crop: String,
+    /// The crop is mature if this equals to
+    /// [`CropDef::num_stages`](crop::CropDef::num_stages).
+    pub stage: u32,
+    /// Ticks grown in the current stage.
+    pub progress: u64,
+}
+
+impl SerializableTileData for CropState {
+    const KEY: &'static str = "crop";
+}
+
+impl CropState {
+    pub fn new(crop: String) -> Self {
+        Self {
+            crop,
+            stage: 0,
+            progress: 0,
+        }
+    }
+}
+
+/// Request to plant a crop on a tile. The tile must exist and have no crop,
+/// building or conduit, and the crop must be unlocked.
+#[derive(Event, Debug, Clone)]
+pub struct PlantCrop {
+    /// The root tilemap of the body.
+    pub tilemap: Entity,
+    pub index: IVec2,
+    pub crop: String,
+}
+
+/// Request to harvest the crop on a tile. Immature crops can't be harvested.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct HarvestCrop {
+    pub tilemap: Entity,
+    pub index: IVec2,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct CropPlanted {
+    pub tilemap: Entity,
+    pub index: IVec2,
+    pub crop: String,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CropStageChanged {
+    pub tilemap: Entity,
+    pub index: IVec2,
+    pub stage: u32,
+    pub is_mature: bool,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct CropHarvested {
+    pub tilemap: Entity,
+    pub index: IVec2,
+    pub crop: String,
+    pub harvest: CropYield,
+}
+
+fn plant_crops(
+    mut commands: Commands,
+    mut requests: EventReader<PlantCrop>,
+    mut planted: EventWriter<CropPlanted>,
+    mut tilemaps_query: Query<(
+        &TilemapStorage,
+        Option<&mut TileDataStorage<CropState>>,
+        Option<&TileDataStorage<BuildingOccupancy>>,
+        Option<&TileDataStorage<Conduit>>,
+    )>,
+    registry: Res<CropRegistry>,
+    research: Res<ResearchState>,
+    tech_tree: Res<TechTree>,
+) {
+    // Storages created in this frame, as inserting them is deferred.
+    let mut created = HashMap::<Entity, TileDataStorage<CropState>>::default();
+
+    for request in requests.read() {
+        if !registry.contains_key(&request.crop) {
+            warn!("Unknown crop {}.", request.crop);
+            continue;
+        }
+
+        if !research.is_available(&tech_tree, &TechUnlock::Crop(request.crop.clone())) {
+            warn!("Crop {} is not unlocked yet.", request.crop);
+            continue;
+        }
+
+        let Ok((tiles, crops, occupancy, conduits)) = tilemaps_query.get_mut(request.tilemap)
+        else {
+            warn!("Tilemap {} not found.", request.tilemap);
+            continue;
+        };
+
+        if tiles.get(request.index).is_none() {
+            warn!("Can't plant crop on empty tile {}.", request.index);
+            continue;
+        }
+
+        if occupancy.is_some_and(|o| o.get(request.index).is_some())
+            || conduits.is_some_and(|c| c.get(request.index).is_some())
+        {
+            warn!("Tile {} is occupied.", request.index);
+            continue;
+        }
+
+        let crops = match crops {
+            Some(crops) => crops.into_inner(),
+            None => created
+                .entry(request.tilemap)
+                .or_insert_with(|| TileDataStorage::new(tiles.chunk_size())),
+        };
+
+        if crops.get(request.index).is_some() {
+            warn!("Tile {} already has a crop.", request.index);
+            continue;
+        }
+
+        crops.set(request.index, CropState::new(request.crop.clone()));
+        planted.send(CropPlanted {
+            tilemap: request.tilemap,
+            index: request.index,
+            crop: request.crop.clone(),
+        });
+    }
+
+    for (tilemap, crops) in created {
+        commands.entity(tilemap).insert(crops);
+    }
+}
+
+fn grow_crops(
+    bodies_query: Query<(&BodyIndex, &BodyTilemap, Option<&Weather>)>,
+    mut crops_query: Query<(
+        &mut TileDataStorage<CropState>,
+        Option<&TileDataStorage<TileEnvironment>>,
+        Option<&TileDataStorage<TileWetness>>,
+    )>,
+    mut stage_changed: EventWriter<CropStageChanged>,
+    cosmos: Res<Cosmos>,
+    registry: Res<CropRegistry>,
+    ticker: Res<Ticker>,
+) {
+    for (body_index, body_tilemap, weather) in &bodies_query {
+        let Ok((mut crops, environment, wetness)) = crops_query.get_mut(**body_tilemap) else {
+            continue;
+        };
+        let body = &cosmos.parameterized[**body_index];
+        let weather_offset = weather.map(|w| w.environment_offset()).unwrap_or_default();
+        // Crops grow only in part of ticks when slowed down by weather.
+        let factor = weather.map(|w| w.crop_growth_factor()).unwrap_or(1.);
+        let tick = **ticker as f64;
+        if (tick * factor).floor() == ((tick - 1.) * factor).floor() {
+            continue;
+        }
+        let changed = Mutex::new(Vec::new());
+
+        crops.par_for_each_mut(|index, state| {
+            let Some(crop) = registry.get(&state.crop) else {
+                return false;
+            };
+            if state.stage >= crop.num_stages() {
+                return false;
+            }
+
+            let tile = environment
+                .and_then(|e| e.flattened_get(index))
+                .copied()
+                .unwrap_or_default()
+                + wetness
+                    .and_then(|w| w.flattened_get(index))
+                    .map(|w| w.environment_offset())
+                    .unwrap_or_default()
+                + weather_offset;
+            let env = LocalEnvironment::new(body, Some(&tile));
+            if !crop.prefers(&env) {
+                return false;
+            }
+
+            state.progress += 1;
+            if state.progress < crop.stages[state.stage as usize] {
+                return false;
+            }
+
+            state.stage += 1;
+            state.progress = 0;
+            changed
+                .lock()
+                .unwrap()
+                .push((index, state.stage, state.stage == crop.num_stages()));
+            true
+        });
+
+        let chunk_size = crops.chunk_size();
+        stage_changed.send_batch(changed.into_inner().unwrap().into_iter().map(
+            |(index, stage, is_mature)| CropStageChanged {
+                tilemap: **body_tilemap,
+                index: index.to_direct(chunk_size),
+                stage,
+                is_mature,
+            },
+        ));
+    }
+}
+
+fn harvest_crops(
+    mut requests: EventReader<HarvestCrop>,
+    mut harvested: EventWriter<CropHarvested>,
+    mut crops_query: Query<(
+        &mut TileDataStorage<CropState>,
+        Option<&TileDataStorage<TilePollution>>,
+    )>,
+    registry: Res<CropRegistry>,
+) {
+    for request in requests.read() {
+        let Ok((mut crops, pollution)) = crops_query.get_mut(request.tilemap) else {
+            continue;
+        };
+        let index = FlattenedTileIndex::from_direct(request.index, crops.chunk_size());
+
+        let Some(state) = crops.flattened_get(index) else {
+            warn!("No crop on tile {}.", request.index);
+            continue;
+        };
+        let Some(crop) = registry.get(&state.crop) else {
+            warn!("Unknown crop {}, removed.", state.crop);
+            crops.flattened_remove(index);
+            continue;
+        };
+        if state.stage < crop.num_stages() {
+            warn!("Crop on tile {} is not mature yet.", request.index);
+            continue;
+        }
+
+        // Pollution on the tile reduces the yield.
+        let factor = pollution
+            .and_then(|p| p.get(request.index))
+            .map(|p| p.crop_yield_factor())
+            .unwrap_or(1.);
+        let harvest = CropYield {
+            item: crop.harvest.item.clone(),
+            amount: (crop.harvest.amount as f64 * factor).round() as u32,
+        };
+        let state = crops.flattened_remove(index).unwrap();
+        harvested.send(CropHarvested {
+            tilemap: request.tilemap,
+            index: request.index,
+            crop: state.crop,
+            harvest,
+        });
+    }
+}
@@ -0,0 +1,146 @@
+//! Incident definitions.
+
+use bevy::{
+    asset::Asset,
+    log::warn,
+    prelude::{Deref, Resource},
+    reflect::TypePath,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::config::RawConfig,
+    body::{
+        quantify::{Moisture, Temperature},
+        ParameterizedBody, QuantifiedBody,
+    },
+    cosmos::celestial::BodyType,
+};
+
+/// Conditions for an incident to happen on a body. All conditions not given
+/// are ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IncidentTrigger {
+    /// The first tick it can happen.
+    pub after: u64,
+    /// Never happens since this tick.
+    pub before: Option<u64>,
+    pub body_types: Vec<BodyType>,
+    pub temperature: Vec<Temperature>,
+    pub moisture: Vec<Moisture>,
+    /// In DPI.
+    pub min_pollution: Option<f64>,
+    /// In DPI.
+    pub max_pollution: Option<f64>,
+}
+
+impl IncidentTrigger {
+    pub fn is_met(
+        &self,
+        tick: u64,
+        ty: BodyType,
+        parameterized: &ParameterizedBody,
+        quantified: &QuantifiedBody,
+    ) -> bool {
+        tick >= self.after
+            && !self.before.is_some_and(|before| tick >= before)
+            && (self.body_types.is_empty() || self.body_types.contains(&ty))
+            && (self.temperature.is_empty() || self.temperature.contains(&quantified.temperature))
+            && (self.moisture.is_empty() || self.moisture.contains(&quantified.moisture))
+            && !self
+                .min_pollution
+                .is_some_and(|min| parameterized.pollution < min)
+            && !self
+                .max_pollution
+                .is_some_and(|max| parameterized.pollution > max)
+    }
+}
+
+/// Parameters of bodies that incidents can modify. Pollution is not included,
+/// as it's averaged from tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BodyParameter {
+    Temperature,
+    Moisture,
+    AtmosphericDensity,
+    Biomass,
+}
+
+impl BodyParameter {
+    pub fn get_mut(self, body: &mut ParameterizedBody) -> &mut f64 {
+        match self {
+            BodyParameter::Temperature => &mut body.temperature,
+            BodyParameter::Moisture => &mut body.moisture,
+            BodyParameter::AtmosphericDensity => &mut body.atmospheric_density,
+            BodyParameter::Biomass => &mut body.biomass,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum IncidentEffect {
+    /// Each crop on the body is destroyed by this chance.
+    RemoveCrops { chance: f64 },
+    /// Hits a random tile, destroying crops and polluting tiles within
+    /// `radius`.
+    Impact { radius: u32, pollution: f64 },
+    /// Put items into the inventory of the body, the part exceeds the capacity
+    /// is dropped.
+    AddItems { item: String, amount: u32 },
+    /// Take items from the inventory of the body, as many as available.
+    RemoveItems { item: String, amount: u32 },
+    ModifyBody {
+        parameter: BodyParameter,
+        delta: f64,
+    },
+    /// Raise the quota of current sector demand period.
+    DemandItems { item: String, amount: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncidentDef {
+    /// Relative weight among all incidents that can happen.
+    pub weight: f64,
+    #[serde(default)]
+    pub trigger: IncidentTrigger,
+    /// Ticks before it can happen again, on any body.
+    #[serde(default)]
+    pub cooldown: u64,
+    /// Happens at most once in a game.
+    #[serde(default)]
+    pub once: bool,
+    pub effects: Vec<IncidentEffect>,
+}
+
+#[derive(Asset, TypePath, Clone, Serialize, Deserialize)]
+pub struct RawIncidentRegistry(HashMap<String, IncidentDef>);
+
+impl RawConfig for RawIncidentRegistry {
+    type Processed = IncidentRegistry;
+
+    const PATH: &'static str = "configs/incidents.json";
+}
+
+/// All incidents, keyed by their ids.
+#[derive(Resource, Deref)]
+pub struct IncidentRegistry(HashMap<String, IncidentDef>);
+
+impl From<RawIncidentRegistry> for IncidentRegistry {
+    fn from(value: RawIncidentRegistry) -> Self {
+        Self(
+            value
+                .0
+                .into_iter()
+                .filter(|(id, incident)| {
+                    let valid = incident.weight.is_finite() && incident.weight > 0.;
+                    if !valid {
+                        warn!("Incident {} has invalid weight, skipped.", id);
+                    }
+                    valid
+                })
+                .collect(),
+        )
+    }
+}
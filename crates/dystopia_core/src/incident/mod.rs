@@ -0,0 +1,407 @@
+//! Random incidents happening on colonized bodies, like meteor strikes, crop
+//! blights and supply requests from the sector.
+//!
+//! Incidents are defined in [`IncidentRegistry`]. Every
+//! [`INCIDENT_ROLL_INTERVAL`] ticks, one incident might be picked by weight
+//! among those whose triggers are met on some landable body with a tilemap.
+//! Titles and descriptions are localized by the `LIncidentTitle` and
+//! `LIncidentDescription` sections of the [`LangFile`], keyed by incident ids,
+//! and carried by [`IncidentFired`] for the UI.
+//!
+//! Fired incidents are recorded in [`IncidentHistory`], which is saved along
+//! with the game save.
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin},
+    log::{error, info, warn},
+    math::IVec2,
+    prelude::{
+        in_state, resource_exists, Commands, DetectChangesMut, Entity, Event, EventReader,
+        EventWriter, IntoSystemConfigs, OnTransition, Query, Res, ResMut, Resource, With,
+    },
+    utils::HashMap,
+};
+use bincode::{Decode, Encode};
+use rand::{seq::SliceRandom, Rng};
+use rayon::iter::ParallelIterator;
+
+use crate::{
+    assets::app_ext::DystopiaAssetAppExt,
+    body::{pollution::TilePollution, BodyQuantifiedChanged},
+    cosmos::celestial::{BodyIndex, BodyTilemap, BodyType, Cosmos, Landable},
+    farming::CropState,
+    incident::def::{IncidentEffect, IncidentRegistry, RawIncidentRegistry},
+    inventory::{item::ItemRegistry, terminal::SectorDemand, Inventory, BODY_INVENTORY_CAPACITY},
+    localization::LangFile,
+    map::{data::TileDataStorage, tilemap::TilemapStorage},
+    schedule::state::GameState,
+    serde::{load::read_save_file, save::write_save_file, save_file_path},
+    sim::{global_clock, GlobalRng, SaveName},
+};
+
+pub mod def;
+
+/// Incidents are rolled once per this many ticks.
+pub const INCIDENT_ROLL_INTERVAL: u64 = 3000;
+/// Chance of any incident happening in one roll.
+pub const INCIDENT_CHANCE: f64 = 0.25;
+
+pub struct DystopiaIncidentPlugin;
+
+impl Plugin for DystopiaIncidentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_config::<RawIncidentRegistry>()
+            .init_resource::<IncidentHistory>()
+            .add_event::<TriggerIncident>()
+            .add_event::<IncidentFired>()
+            .add_systems(
+                OnTransition {
+                    exited: GameState::Initialize,
+                    entered: GameState::Simulate,
+                },
+                load_incidents.run_if(resource_exists::<SaveName>),
+            )
+            .add_systems(
+                FixedUpdate,
+                (roll_incidents, fire_incidents, save_incidents)
+                    .chain()
+                    .after(global_clock)
+                    .run_if(resource_exists::<IncidentRegistry>)
+                    .run_if(resource_exists::<GlobalRng>)
+                    .run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+/// Localized title and description of an incident. Falls back to the id and an
+/// empty description if missing.
+pub fn localize_incident(lang: &LangFile, incident: &str) -> (String, String) {
+    let get = |section: &str| lang.get(section).and_then(|s| s.get(incident)).cloned();
+    (
+        get("LIncidentTitle").unwrap_or_else(|| incident.to_owned()),
+        get("LIncidentDescription").unwrap_or_default(),
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct FiredIncident {
+    /// Id in [`IncidentRegistry`].
+    pub incident: String,
+    pub body: BodyIndex,
+    /// Measured by [`IncidentHistory::elapsed`].
+    pub tick: u64,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct IncidentHistory {
+    fired: Vec<FiredIncident>,
+    elapsed: u64,
+}
+
+impl IncidentHistory {
+    /// From the earliest to the latest.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &FiredIncident> {
+        self.fired.iter()
+    }
+
+    /// Ticks passed in this game, which triggers and cooldowns are measured by.
+    /// Not measured by [`Ticker`](crate::sim::Ticker), as it restarts with
+    /// every game.
+    #[inline]
+    pub fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    /// The tick `incident` fired last time.
+    pub fn last_fired(&self, incident: &str) -> Option<u64> {
+        self.fired
+            .iter()
+            .rev()
+            .find(|f| f.incident == incident)
+            .map(|f| f.tick)
+    }
+}
+
+/// Request to fire an incident on a body, regardless of its trigger.
+#[derive(Event, Debug, Clone)]
+pub struct TriggerIncident {
+    pub incident: String,
+    pub body: Entity,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct IncidentFired {
+    pub incident: String,
+    pub body: Entity,
+    /// Localized by [`localize_incident`].
+    pub title: String,
+    pub description: String,
+}
+
+fn roll_incidents(
+    mut requests: EventWriter<TriggerIncident>,
+    bodies_query: Query<(Entity, &BodyIndex, &BodyType), (With<Landable>, With<BodyTilemap>)>,
+    registry: Res<IncidentRegistry>,
+    mut history: ResMut<IncidentHistory>,
+    cosmos: Res<Cosmos>,
+    mut rng: ResMut<GlobalRng>,
+) {
+    // Not a change worth saving immediately, see `save_incidents`.
+    history.bypass_change_detection().elapsed += 1;
+    let tick = history.elapsed;
+    if tick % INCIDENT_ROLL_INTERVAL != 0 || !rng.gen_bool(INCIDENT_CHANCE) {
+        return;
+    }
+
+    let mut candidates = Vec::new();
+    for (id, def) in registry.iter() {
+        let last_fired = history.last_fired(id);
+        if last_fired.is_some_and(|last| def.once || tick < last + def.cooldown) {
+            continue;
+        }
+
+        candidates.extend(
+            bodies_query
+                .iter()
+                .filter(|(_, body_index, ty)| {
+                    def.trigger.is_met(
+                        tick,
+                        **ty,
+                        &cosmos.parameterized[***body_index],
+                        &cosmos.quantified[***body_index],
+                    )
+                })
+                .map(|(entity, ..)| (id, entity, def.weight)),
+        );
+    }
+
+    if let Ok((id, entity, _)) = candidates.choose_weighted(&mut **rng, |c| c.2) {
+        requests.send(TriggerIncident {
+            incident: (*id).clone(),
+            body: *entity,
+        });
+    }
+}
+
+fn fire_incidents(
+    mut commands: Commands,
+    mut requests: EventReader<TriggerIncident>,
+    mut fired: EventWriter<IncidentFired>,
+    mut quantified_changed: EventWriter<BodyQuantifiedChanged>,
+    mut bodies_query: Query<(&BodyIndex, &BodyTilemap, Option<&mut Inventory>)>,
+    mut tilemaps_query: Query<(
+        &TilemapStorage,
+        Option<&mut TileDataStorage<CropState>>,
+        Option<&mut TileDataStorage<TilePollution>>,
+    )>,
+    mut history: ResMut<IncidentHistory>,
+    mut cosmos: ResMut<Cosmos>,
+    mut demand: Option<ResMut<SectorDemand>>,
+    mut rng: ResMut<GlobalRng>,
+    registry: Res<IncidentRegistry>,
+    items: Res<ItemRegistry>,
+    lang: Option<Res<LangFile>>,
+) {
+    // Components created in this frame, as inserting them is deferred.
+    let mut created_inventories = HashMap::<Entity, Inventory>::default();
+    let mut created_pollution = HashMap::<Entity, TileDataStorage<TilePollution>>::default();
+
+    for request in requests.read() {
+        let Some(def) = registry.get(&request.incident) else {
+            warn!("Unknown incident {}.", request.incident);
+            continue;
+        };
+        let Ok((body_index, body_tilemap, mut inventory)) = bodies_query.get_mut(request.body)
+        else {
+            warn!(
+                "Incident {} can't happen on {}.",
+                request.incident, request.body
+            );
+            continue;
+        };
+        let Ok((tiles, mut crops, mut pollution)) = tilemaps_query.get_mut(**body_tilemap) else {
+            continue;
+        };
+
+        for effect in &def.effects {
+            match effect {
+                IncidentEffect::RemoveCrops { chance } => {
+                    let Some(crops) = &mut crops else {
+                        continue;
+                    };
+                    let indices = crops.par_iter().map(|(index, _)| index).collect::<Vec<_>>();
+                    for index in indices {
+                        if rng.gen_bool(chance.clamp(0., 1.)) {
+                            crops.flattened_remove(index);
+                        }
+                    }
+                }
+                IncidentEffect::Impact {
+                    radius,
+                    pollution: amount,
+                } => {
+                    let indices = tiles
+                        .chunked_storage()
+                        .iter_direct()
+                        .map(|(index, _)| index)
+                        .collect::<Vec<_>>();
+                    let Some(center) = indices.choose(&mut **rng).copied() else {
+                        continue;
+                    };
+
+                    let r = *radius as i32;
+                    let pollution = match &mut pollution {
+                        Some(pollution) => &mut **pollution,
+                        None => created_pollution
+                            .entry(**body_tilemap)
+                            .or_insert_with(|| TileDataStorage::new(tiles.chunk_size())),
+                    };
+                    for index in (-r..=r)
+                        .flat_map(|y| (-r..=r).map(move |x| IVec2::new(x, y)))
+                        .filter(|offset| offset.length_squared() <= r * r)
+                        .map(|offset| center + offset)
+                        .filter(|index| tiles.get(*index).is_some())
+                    {
+                        if let Some(crops) = &mut crops {
+                            crops.remove(index);
+                        }
+                        let cur = pollution.get(index).copied().unwrap_or_default();
+                        pollution.set(index, TilePollution((cur.0 + amount).clamp(0., 100.)));
+                    }
+                }
+                IncidentEffect::AddItems { item, amount } => {
+                    let inventory = match &mut inventory {
+                        Some(inventory) => &mut **inventory,
+                        None => created_inventories
+                            .entry(request.body)
+                            .or_insert_with(|| Inventory::new(BODY_INVENTORY_CAPACITY)),
+                    };
+                    match inventory.fits(&items, item) {
+                        Ok(fits) => inventory.insert(&items, item, fits.min(*amount)).unwrap(),
+                        Err(err) => warn!("Failed to add items: {}", err),
+                    }
+                }
+                IncidentEffect::RemoveItems { item, amount } => {
+                    let Some(inventory) = &mut inventory else {
+                        continue;
+                    };
+                    let amount = inventory.count(item).min(*amount);
+                    if let Err(err) = inventory.remove(&items, item, amount) {
+                        warn!("Failed to remove items: {}", err);
+                    }
+                }
+                IncidentEffect::ModifyBody { parameter, delta } => {
+                    let Cosmos {
+                        parameterized,
+                        quantified,
+                        ..
+                    } = &mut *cosmos;
+                    let body = &mut parameterized[**body_index];
+                    *parameter.get_mut(body) += delta;
+                    body.clamp();
+                    quantified_changed.send_batch(BodyQuantifiedChanged::requantify(
+                        *body_index,
+                        body,
+                        &mut quantified[**body_index],
+                    ));
+                }
+                IncidentEffect::DemandItems { item, amount } => {
+                    if let Some(demand) = &mut demand {
+                        *demand.quotas.entry(item.clone()).or_default() += amount;
+                    }
+                }
+            }
+        }
+
+        history.fired.push(FiredIncident {
+            incident: request.incident.clone(),
+            body: *body_index,
+            tick: history.elapsed,
+        });
+        let (title, description) = match &lang {
+            Some(lang) => localize_incident(lang, &request.incident),
+            None => (request.incident.clone(), String::new()),
+        };
+        info!("Incident {} fired on {}.", title, request.body);
+        fired.send(IncidentFired {
+            incident: request.incident.clone(),
+            body: request.body,
+            title,
+            description,
+        });
+    }
+
+    for (body, inventory) in created_inventories {
+        commands.entity(body).insert(inventory);
+    }
+    for (tilemap, pollution) in created_pollution {
+        commands.entity(tilemap).insert(pollution);
+    }
+}
+
+/// The incident history in save.
+#[derive(Encode, Decode)]
+struct BinaryIncidentHistory {
+    /// Incident ids, body indices and ticks.
+    fired: Vec<(String, usize, u64)>,
+    elapsed: u64,
+}
+
+fn load_incidents(mut history: ResMut<IncidentHistory>, save_name: Res<SaveName>) {
+    *history = IncidentHistory::default();
+    let path = save_file_path(&save_name, "incidents.bin");
+    if !path.exists() {
+        return;
+    }
+
+    let binary = match read_save_file::<BinaryIncidentHistory>(&path) {
+        Ok(binary) => binary,
+        Err(err) => {
+            error!("Failed to load incident history: {}", err);
+            return;
+        }
+    };
+
+    history.fired = binary
+        .fired
+        .into_iter()
+        .map(|(incident, body, tick)| FiredIncident {
+            incident,
+            body: BodyIndex::new(body),
+            tick,
+        })
+        .collect();
+    history.elapsed = binary.elapsed;
+
+    info!(
+        "Incident history loaded, {} incidents fired.",
+        history.fired.len()
+    );
+}
+
+/// Saved when incidents fired, and once per roll for the elapsed ticks.
+fn save_incidents(
+    mut fired: EventReader<IncidentFired>,
+    history: Res<IncidentHistory>,
+    save_name: Option<Res<SaveName>>,
+) {
+    let changed = fired.read().count() > 0;
+    let autosave = history.elapsed % INCIDENT_ROLL_INTERVAL == 0;
+    let Some(save_name) = save_name.filter(|_| changed || autosave) else {
+        return;
+    };
+
+    let binary = BinaryIncidentHistory {
+        fired: history
+            .fired
+            .iter()
+            .map(|f| (f.incident.clone(), *f.body, f.tick))
+            .collect(),
+        elapsed: history.elapsed,
+    };
+
+    if let Err(err) = write_save_file(&save_file_path(&save_name, "incidents.bin"), binary) {
+        error!("Failed to save incident history: {}", err);
+    }
+}
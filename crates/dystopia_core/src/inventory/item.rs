@@ -0,0 +1,42 @@
+//! Item definitions.
+
+use bevy::{
+    asset::Asset,
+    prelude::{Deref, Resource},
+    reflect::TypePath,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::assets::config::RawConfig;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ItemCategory {
+    /// Harvested from crops, which can be uploaded to the terminal.
+    Crop,
+    #[default]
+    Material,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemDef {
+    #[serde(default)]
+    pub category: ItemCategory,
+    /// Slots one unit takes in an [`Inventory`](crate::inventory::Inventory).
+    #[serde(default = "default_volume")]
+    pub volume: u32,
+}
+
+fn default_volume() -> u32 {
+    1
+}
+
+/// All items, keyed by their ids.
+#[derive(Asset, TypePath, Resource, Deref, Clone, Serialize, Deserialize)]
+pub struct ItemRegistry(HashMap<String, ItemDef>);
+
+impl RawConfig for ItemRegistry {
+    type Processed = Self;
+
+    const PATH: &'static str = "configs/items.json";
+}
@@ -0,0 +1,387 @@
+//! Items stored on bodies.
+//!
+//! Harvested crops are stored into the [`Inventory`] of the body, and can be
+//! moved between inventories, or uploaded to the [`terminal`] to meet the sector
+//! demand. Inventories of bodies are saved along with the game save.
+//!
+//! Ships don't exist yet, so storage on ships is deferred. [`Inventory`] is a
+//! plain component, and ships can carry one once they're added.
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    log::{error, info, warn},
+    prelude::{
+        in_state, resource_exists, Commands, Component, Entity, Event, EventReader, EventWriter,
+        IntoSystemConfigs, Local, OnTransition, Query, Ref, Res,
+    },
+    utils::HashMap,
+};
+use bincode::{Decode, Encode};
+use thiserror::Error;
+
+use crate::{
+    assets::app_ext::DystopiaAssetAppExt,
+    cosmos::celestial::{BodyIndex, BodyTilemap},
+    farming::CropHarvested,
+    inventory::item::ItemRegistry,
+    schedule::state::GameState,
+    serde::{load::read_save_file, save::write_save_file, save_file_path},
+    sim::{global_clock, SaveName, Ticker},
+};
+
+pub mod item;
+pub mod terminal;
+
+/// Capacity of inventories created on bodies for harvests.
+pub const BODY_INVENTORY_CAPACITY: u32 = 10000;
+/// Changed inventories are saved once per this many ticks.
+pub const INVENTORY_AUTOSAVE_INTERVAL: u64 = 600;
+
+pub struct DystopiaInventoryPlugin;
+
+impl Plugin for DystopiaInventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(terminal::TerminalPlugin)
+            .add_config::<ItemRegistry>()
+            .add_event::<TransferItems>()
+            .add_event::<ItemsTransferred>()
+            .add_systems(
+                OnTransition {
+                    exited: GameState::Initialize,
+                    entered: GameState::Simulate,
+                },
+                load_inventories
+                    .run_if(resource_exists::<SaveName>)
+                    .run_if(resource_exists::<ItemRegistry>),
+            )
+            .add_systems(
+                Update,
+                (store_harvests, transfer_items).run_if(in_state(GameState::Simulate)),
+            )
+            .add_systems(
+                FixedUpdate,
+                save_inventories
+                    .after(global_clock)
+                    .run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum InventoryError {
+    #[error("Unknown item {0}.")]
+    UnknownItem(String),
+    #[error("Not enough {item}: {required} required but only {available} available.")]
+    NotEnough {
+        item: String,
+        required: u32,
+        available: u32,
+    },
+    #[error("No space for {amount} {item}.")]
+    Full { item: String, amount: u32 },
+}
+
+/// Items stored on a body, limited by the total volume.
+#[derive(Component, Debug, Clone)]
+pub struct Inventory {
+    items: HashMap<String, u32>,
+    capacity: u32,
+    used: u32,
+}
+
+impl Inventory {
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            items: Default::default(),
+            capacity,
+            used: 0,
+        }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    #[inline]
+    pub fn used(&self) -> u32 {
+        self.used
+    }
+
+    #[inline]
+    pub fn free(&self) -> u32 {
+        self.capacity - self.used
+    }
+
+    #[inline]
+    pub fn count(&self, item: &str) -> u32 {
+        self.items.get(item).copied().unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&String, u32)> {
+        self.items.iter().map(|(item, amount)| (item, *amount))
+    }
+
+    /// How many of `item` can still be inserted.
+    pub fn fits(&self, registry: &ItemRegistry, item: &str) -> Result<u32, InventoryError> {
+        let def = registry
+            .get(item)
+            .ok_or_else(|| InventoryError::UnknownItem(item.to_owned()))?;
+        Ok(self.free().checked_div(def.volume).unwrap_or(u32::MAX))
+    }
+
+    /// Insert all or nothing.
+    pub fn insert(
+        &mut self,
+        registry: &ItemRegistry,
+        item: &str,
+        amount: u32,
+    ) -> Result<(), InventoryError> {
+        if self.fits(registry, item)? < amount {
+            return Err(InventoryError::Full {
+                item: item.to_owned(),
+                amount,
+            });
+        }
+
+        *self.items.entry(item.to_owned()).or_default() += amount;
+        self.used += registry[item].volume * amount;
+        Ok(())
+    }
+
+    /// Remove all or nothing.
+    pub fn remove(
+        &mut self,
+        registry: &ItemRegistry,
+        item: &str,
+        amount: u32,
+    ) -> Result<(), InventoryError> {
+        let def = registry
+            .get(item)
+            .ok_or_else(|| InventoryError::UnknownItem(item.to_owned()))?;
+        let available = self.count(item);
+        if available < amount {
+            return Err(InventoryError::NotEnough {
+                item: item.to_owned(),
+                required: amount,
+                available,
+            });
+        }
+
+        if available == amount {
+            self.items.remove(item);
+        } else {
+            *self.items.get_mut(item).unwrap() -= amount;
+        }
+        self.used -= def.volume * amount;
+        Ok(())
+    }
+
+    /// Move items from `self` to `to`. Nothing changes on failure.
+    pub fn transfer(
+        &mut self,
+        to: &mut Inventory,
+        registry: &ItemRegistry,
+        item: &str,
+        amount: u32,
+    ) -> Result<(), InventoryError> {
+        if to.fits(registry, item)? < amount {
+            return Err(InventoryError::Full {
+                item: item.to_owned(),
+                amount,
+            });
+        }
+
+        self.remove(registry, item, amount)?;
+        to.insert(registry, item, amount)
+    }
+}
+
+/// Request to move items between two entities with [`Inventory`].
+#[derive(Event, Debug, Clone)]
+pub struct TransferItems {
+    pub from: Entity,
+    pub to: Entity,
+    pub item: String,
+    pub amount: u32,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct ItemsTransferred {
+    pub from: Entity,
+    pub to: Entity,
+    pub item: String,
+    pub amount: u32,
+}
+
+fn transfer_items(
+    mut requests: EventReader<TransferItems>,
+    mut transferred: EventWriter<ItemsTransferred>,
+    mut inventories_query: Query<&mut Inventory>,
+    registry: Res<ItemRegistry>,
+) {
+    for request in requests.read() {
+        let Ok([mut from, mut to]) = inventories_query.get_many_mut([request.from, request.to])
+        else {
+            warn!(
+                "Can't transfer items from {} to {}.",
+                request.from, request.to
+            );
+            continue;
+        };
+
+        if let Err(err) = from.transfer(&mut to, &registry, &request.item, request.amount) {
+            warn!("Failed to transfer items: {}", err);
+            continue;
+        }
+
+        transferred.send(ItemsTransferred {
+            from: request.from,
+            to: request.to,
+            item: request.item.clone(),
+            amount: request.amount,
+        });
+    }
+}
+
+/// Put harvests into the inventory of the body, the part exceeds the capacity
+/// is dropped.
+fn store_harvests(
+    mut commands: Commands,
+    mut harvested: EventReader<CropHarvested>,
+    mut bodies_query: Query<(Entity, &BodyTilemap, Option<&mut Inventory>)>,
+    registry: Res<ItemRegistry>,
+) {
+    if harvested.is_empty() {
+        return;
+    }
+
+    let mut bodies = bodies_query
+        .iter_mut()
+        .map(|(entity, tilemap, inventory)| (**tilemap, (entity, inventory)))
+        .collect::<HashMap<_, _>>();
+    // Inventories created in this frame, as inserting them is deferred.
+    let mut created = HashMap::<Entity, Inventory>::default();
+
+    for event in harvested.read() {
+        let Some((body, inventory)) = bodies.get_mut(&event.tilemap) else {
+            continue;
+        };
+        let inventory = match inventory {
+            Some(inventory) => &mut **inventory,
+            None => created
+                .entry(*body)
+                .or_insert_with(|| Inventory::new(BODY_INVENTORY_CAPACITY)),
+        };
+
+        let item = &event.harvest.item;
+        let amount = match inventory.fits(&registry, item) {
+            Ok(fits) => fits.min(event.harvest.amount),
+            Err(err) => {
+                warn!("Failed to store harvest: {}", err);
+                continue;
+            }
+        };
+        if amount < event.harvest.amount {
+            warn!(
+                "Inventory is full, {} {} dropped.",
+                event.harvest.amount - amount,
+                item
+            );
+        }
+
+        inventory.insert(&registry, item, amount).unwrap();
+    }
+
+    for (body, inventory) in created {
+        commands.entity(body).insert(inventory);
+    }
+}
+
+/// Inventories of bodies in save.
+#[derive(Encode, Decode)]
+struct BinaryInventories {
+    /// Body indices, capacities and items.
+    bodies: Vec<(usize, u32, Vec<(String, u32)>)>,
+}
+
+fn load_inventories(
+    mut commands: Commands,
+    bodies_query: Query<(Entity, &BodyIndex)>,
+    registry: Res<ItemRegistry>,
+    save_name: Res<SaveName>,
+) {
+    let path = save_file_path(&save_name, "inventories.bin");
+    if !path.exists() {
+        return;
+    }
+
+    let binary = match read_save_file::<BinaryInventories>(&path) {
+        Ok(binary) => binary,
+        Err(err) => {
+            error!("Failed to load inventories: {}", err);
+            return;
+        }
+    };
+
+    let mut saved = binary
+        .bodies
+        .into_iter()
+        .map(|(body, capacity, items)| (body, (capacity, items)))
+        .collect::<HashMap<_, _>>();
+
+    for (entity, body_index) in &bodies_query {
+        let Some((capacity, items)) = saved.remove(&**body_index) else {
+            continue;
+        };
+
+        let mut inventory = Inventory::new(capacity);
+        for (item, amount) in items {
+            if let Err(err) = inventory.insert(&registry, &item, amount) {
+                warn!("Failed to load items: {}", err);
+            }
+        }
+        commands.entity(entity).insert(inventory);
+    }
+
+    info!("Inventories loaded.");
+}
+
+fn save_inventories(
+    inventories_query: Query<(&BodyIndex, Ref<Inventory>)>,
+    save_name: Option<Res<SaveName>>,
+    ticker: Res<Ticker>,
+    mut changed: Local<bool>,
+) {
+    // Changes are only visible to the next run, so they're accumulated until
+    // the next autosave.
+    *changed |= inventories_query.iter().any(|(_, i)| i.is_changed());
+    if **ticker % INVENTORY_AUTOSAVE_INTERVAL != 0 || !*changed {
+        return;
+    }
+    let Some(save_name) = save_name else {
+        return;
+    };
+    *changed = false;
+
+    let binary = BinaryInventories {
+        bodies: inventories_query
+            .iter()
+            .map(|(body_index, inventory)| {
+                (
+                    **body_index,
+                    inventory.capacity,
+                    inventory
+                        .iter()
+                        .map(|(item, amount)| (item.clone(), amount))
+                        .collect(),
+                )
+            })
+            .collect(),
+    };
+
+    if let Err(err) = write_save_file(&save_file_path(&save_name, "inventories.bin"), binary) {
+        error!("Failed to save inventories: {}", err);
+    }
+}
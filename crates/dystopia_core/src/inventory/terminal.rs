@@ -0,0 +1,294 @@
+//! The terminal that collects crops to feed the sector.
+//!
+//! The sector demands crops by periods. At the end of each period, the player
+//! is scored by how much of the quotas are fulfilled, and quotas grow for the
+//! next period. The demand and the score are saved along with the game save.
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    asset::Asset,
+    log::{error, info, warn},
+    prelude::{
+        in_state, not, resource_exists, Commands, DetectChangesMut, Entity, Event, EventReader,
+        EventWriter, IntoSystemConfigs, OnTransition, Query, Res, ResMut, Resource,
+    },
+    reflect::TypePath,
+    utils::HashMap,
+};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{app_ext::DystopiaAssetAppExt, config::RawConfig},
+    inventory::{
+        item::{ItemCategory, ItemRegistry},
+        Inventory, INVENTORY_AUTOSAVE_INTERVAL,
+    },
+    schedule::state::GameState,
+    serde::{load::read_save_file, save::write_save_file, save_file_path},
+    sim::{global_clock, SaveName},
+};
+
+pub(super) struct TerminalPlugin;
+
+impl Plugin for TerminalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_config::<SectorDemandConfig>()
+            .add_event::<UploadToTerminal>()
+            .add_event::<CropsUploaded>()
+            .add_event::<DemandPeriodEnded>()
+            .add_systems(
+                OnTransition {
+                    exited: GameState::Initialize,
+                    entered: GameState::Simulate,
+                },
+                load_sector_demand.run_if(resource_exists::<SaveName>),
+            )
+            .add_systems(
+                Update,
+                (
+                    init_sector_demand.run_if(not(resource_exists::<SectorDemand>)),
+                    upload_to_terminal.run_if(resource_exists::<SectorDemand>),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Simulate)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (settle_demand_period, save_sector_demand)
+                    .chain()
+                    .after(global_clock)
+                    .run_if(resource_exists::<SectorDemand>)
+                    .run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+#[derive(Asset, TypePath, Resource, Clone, Serialize, Deserialize)]
+pub struct SectorDemandConfig {
+    /// Ticks a period lasts.
+    pub period: u64,
+    /// Quotas are multiplied by this after each period.
+    pub growth: f64,
+    /// Score gained when all quotas are fulfilled.
+    pub reward: f64,
+    /// Score lost when nothing is delivered.
+    pub penalty: f64,
+    /// Quotas of the first period, keyed by item ids.
+    pub quotas: HashMap<String, u32>,
+}
+
+impl RawConfig for SectorDemandConfig {
+    type Processed = Self;
+
+    const PATH: &'static str = "configs/sector_demand.json";
+}
+
+#[derive(Resource, Debug)]
+pub struct SectorDemand {
+    /// Starts from 0.
+    pub period: u32,
+    /// Ticks passed in current period. Not measured by [`Ticker`](crate::sim::Ticker),
+    /// as it restarts with every game.
+    pub elapsed: u64,
+    pub quotas: HashMap<String, u32>,
+    pub delivered: HashMap<String, u32>,
+    pub score: i64,
+}
+
+impl SectorDemand {
+    /// Portion of quotas fulfilled in current period, in `[0, 1]`.
+    pub fn fulfillment(&self) -> f64 {
+        if self.quotas.is_empty() {
+            return 1.;
+        }
+
+        self.quotas
+            .iter()
+            .map(|(item, required)| {
+                let delivered = self.delivered.get(item).copied().unwrap_or_default();
+                (delivered as f64 / (*required).max(1) as f64).min(1.)
+            })
+            .sum::<f64>()
+            / self.quotas.len() as f64
+    }
+}
+
+/// Request to upload crops from an entity with [`Inventory`] to the terminal.
+/// Only crops demanded by the sector are accepted.
+#[derive(Event, Debug, Clone)]
+pub struct UploadToTerminal {
+    pub from: Entity,
+    pub item: String,
+    pub amount: u32,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct CropsUploaded {
+    pub from: Entity,
+    pub item: String,
+    pub amount: u32,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DemandPeriodEnded {
+    pub period: u32,
+    pub fulfillment: f64,
+    pub score_delta: i64,
+}
+
+fn init_sector_demand(mut commands: Commands, config: Option<Res<SectorDemandConfig>>) {
+    let Some(config) = config else {
+        return;
+    };
+
+    commands.insert_resource(SectorDemand {
+        period: 0,
+        elapsed: 0,
+        quotas: config.quotas.clone(),
+        delivered: Default::default(),
+        score: 0,
+    });
+}
+
+fn upload_to_terminal(
+    mut requests: EventReader<UploadToTerminal>,
+    mut uploaded: EventWriter<CropsUploaded>,
+    mut inventories_query: Query<&mut Inventory>,
+    mut demand: ResMut<SectorDemand>,
+    registry: Res<ItemRegistry>,
+) {
+    for request in requests.read() {
+        if !registry
+            .get(&request.item)
+            .is_some_and(|def| def.category == ItemCategory::Crop)
+        {
+            warn!("{} is not a crop.", request.item);
+            continue;
+        }
+
+        if !demand.quotas.contains_key(&request.item) {
+            warn!("{} is not demanded by the sector.", request.item);
+            continue;
+        }
+
+        let Ok(mut inventory) = inventories_query.get_mut(request.from) else {
+            warn!("Inventory {} not found.", request.from);
+            continue;
+        };
+
+        if let Err(err) = inventory.remove(&registry, &request.item, request.amount) {
+            warn!("Failed to upload crops: {}", err);
+            continue;
+        }
+
+        *demand.delivered.entry(request.item.clone()).or_default() += request.amount;
+        uploaded.send(CropsUploaded {
+            from: request.from,
+            item: request.item.clone(),
+            amount: request.amount,
+        });
+    }
+}
+
+fn settle_demand_period(
+    mut ended: EventWriter<DemandPeriodEnded>,
+    mut demand: ResMut<SectorDemand>,
+    config: Res<SectorDemandConfig>,
+) {
+    // Not a change worth saving immediately, see `save_sector_demand`.
+    demand.bypass_change_detection().elapsed += 1;
+    if demand.elapsed < config.period {
+        return;
+    }
+
+    let fulfillment = demand.fulfillment();
+    let score_delta =
+        (config.reward * fulfillment - config.penalty * (1. - fulfillment)).round() as i64;
+
+    let period = demand.period;
+    demand.score += score_delta;
+    demand.period += 1;
+    demand.elapsed = 0;
+    demand.delivered.clear();
+    demand
+        .quotas
+        .values_mut()
+        .for_each(|q| *q = (*q as f64 * config.growth).ceil() as u32);
+
+    info!(
+        "Sector demand period {} ended, {:.0}% fulfilled, score {:+}.",
+        period,
+        fulfillment * 100.,
+        score_delta
+    );
+    ended.send(DemandPeriodEnded {
+        period,
+        fulfillment,
+        score_delta,
+    });
+}
+
+/// The sector demand in save.
+#[derive(Encode, Decode)]
+struct BinarySectorDemand {
+    period: u32,
+    elapsed: u64,
+    quotas: Vec<(String, u32)>,
+    delivered: Vec<(String, u32)>,
+    score: i64,
+}
+
+fn load_sector_demand(mut commands: Commands, save_name: Res<SaveName>) {
+    // Demand of the previous game is dropped, and re-initialized if not saved.
+    commands.remove_resource::<SectorDemand>();
+
+    let path = save_file_path(&save_name, "sector_demand.bin");
+    if !path.exists() {
+        return;
+    }
+
+    let binary = match read_save_file::<BinarySectorDemand>(&path) {
+        Ok(binary) => binary,
+        Err(err) => {
+            error!("Failed to load sector demand: {}", err);
+            return;
+        }
+    };
+
+    info!(
+        "Sector demand loaded, period {}, score {}.",
+        binary.period, binary.score
+    );
+    commands.insert_resource(SectorDemand {
+        period: binary.period,
+        elapsed: binary.elapsed,
+        quotas: binary.quotas.into_iter().collect(),
+        delivered: binary.delivered.into_iter().collect(),
+        score: binary.score,
+    });
+}
+
+/// Saved when changed, and periodically for the progress of the period.
+fn save_sector_demand(demand: Res<SectorDemand>, save_name: Option<Res<SaveName>>) {
+    let autosave = demand.elapsed % INVENTORY_AUTOSAVE_INTERVAL == 0;
+    let Some(save_name) = save_name.filter(|_| demand.is_changed() || autosave) else {
+        return;
+    };
+
+    let binary = BinarySectorDemand {
+        period: demand.period,
+        elapsed: demand.elapsed,
+        quotas: demand.quotas.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+        delivered: demand
+            .delivered
+            .iter()
+            .map(|(k, v)| (k.clone(), *v))
+            .collect(),
+        score: demand.score,
+    };
+
+    if let Err(err) = write_save_file(&save_file_path(&save_name, "sector_demand.bin"), binary) {
+        error!("Failed to save sector demand: {}", err);
+    }
+}
@@ -4,9 +4,13 @@ use bevy::app::{App, Plugin};
 
 pub mod assets;
 pub mod body;
+pub mod building;
 pub mod character;
 pub mod cosmos;
+pub mod farming;
+pub mod incident;
 pub mod input;
+pub mod inventory;
 pub mod localization;
 pub mod map;
 pub mod math;
@@ -15,6 +19,7 @@ pub mod schedule;
 pub mod sci;
 pub mod serde;
 pub mod sim;
+pub mod tech;
 pub mod ui;
 pub mod util;
 
@@ -29,19 +34,30 @@ impl Plugin for DystopiaCorePlugin {
             }
         }
 
+        // Tuples of plugins are limited to 15 elements.
         app.add_plugins((
-            assets::DystopiaAssetsPlugin,
-            character::DystopiaCharacterPlugin,
-            cosmos::DystopiaCosmosPlugin,
-            input::DystopiaInputPlugin,
-            localization::DystopiaLocalizationPlugin,
-            map::DystopiaMapPlugin,
-            serde::DystopiaSerdePlugin,
-            scene::DystopiaScenePlugin,
-            schedule::DystopiaSchedulePlugin,
-            sim::DystopiaSimulationPlugin,
-            ui::DystopiaUiPlugin,
-            util::DystopiaUtilPlugin,
+            (
+                assets::DystopiaAssetsPlugin,
+                body::DystopiaBodyPlugin,
+                building::DystopiaBuildingPlugin,
+                character::DystopiaCharacterPlugin,
+                cosmos::DystopiaCosmosPlugin,
+                farming::DystopiaFarmingPlugin,
+                incident::DystopiaIncidentPlugin,
+                input::DystopiaInputPlugin,
+                inventory::DystopiaInventoryPlugin,
+                localization::DystopiaLocalizationPlugin,
+            ),
+            (
+                map::DystopiaMapPlugin,
+                serde::DystopiaSerdePlugin,
+                scene::DystopiaScenePlugin,
+                schedule::DystopiaSchedulePlugin,
+                sim::DystopiaSimulationPlugin,
+                tech::DystopiaTechPlugin,
+                ui::DystopiaUiPlugin,
+                util::DystopiaUtilPlugin,
+            ),
         ));
     }
 }
@@ -0,0 +1,111 @@
+//! Named tile animations defined in config, which can be referenced by name.
+
+use bevy::{
+    app::{App, Plugin},
+    asset::Asset,
+    log::warn,
+    prelude::{Deref, Resource},
+    reflect::TypePath,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{app_ext::DystopiaAssetAppExt, config::RawConfig},
+    map::tilemap::{
+        TileAnimation, TileAnimationMode, TileFlip, TileStaticAtlas, TilemapAnimations,
+        TilemapTilesets,
+    },
+};
+
+pub(super) struct TileAnimationRegistryPlugin;
+
+impl Plugin for TileAnimationRegistryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_config::<TileAnimationRegistry>();
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileAnimationFrameDef {
+    /// Id in [`TilesetRegistry`](crate::map::tileset::TilesetRegistry).
+    pub tileset: String,
+    pub atlas: u32,
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileAnimationDef {
+    pub frames: Vec<TileAnimationFrameDef>,
+    pub fps: u32,
+    #[serde(default)]
+    pub mode: TileAnimationMode,
+}
+
+/// All named animations, keyed by their names.
+#[derive(Asset, TypePath, Resource, Deref, Clone, Serialize, Deserialize)]
+pub struct TileAnimationRegistry(HashMap<String, TileAnimationDef>);
+
+impl RawConfig for TileAnimationRegistry {
+    type Processed = Self;
+
+    const PATH: &'static str = "configs/tile_animations.json";
+}
+
+impl TileAnimationRegistry {
+    /// Register the animation onto the tilemap, or get the existing one.
+    ///
+    /// Returns `None` if the animation doesn't exist, has no frames, or any of
+    /// its tilesets is not used by the tilemap.
+    pub fn register(
+        &self,
+        name: &str,
+        animations: &mut TilemapAnimations,
+        tilesets: &TilemapTilesets,
+    ) -> Option<TileAnimation> {
+        if let Some(anim) = animations.get(name) {
+            return Some(anim);
+        }
+
+        let Some(def) = self.get(name) else {
+            warn!("Tile animation {} not found.", name);
+            return None;
+        };
+        if def.frames.is_empty() {
+            warn!("Tile animation {} has no frames, skipped.", name);
+            return None;
+        }
+
+        let frames = def
+            .frames
+            .iter()
+            .map(|frame| {
+                let texture = tilesets
+                    .textures()
+                    .iter()
+                    .position(|t| t.id.as_deref() == Some(frame.tileset.as_str()));
+                if texture.is_none() {
+                    warn!(
+                        "Tileset {} of animation {} is not used by the tilemap.",
+                        frame.tileset, name
+                    );
+                }
+
+                let mut flip = TileFlip::NONE;
+                flip.set(TileFlip::HORIZONTAL, frame.flip_x);
+                flip.set(TileFlip::VERTICAL, frame.flip_y);
+
+                texture.map(|texture| TileStaticAtlas {
+                    texture: texture as u32,
+                    atlas: frame.atlas,
+                    flip,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        animations.register_named(name, frames, def.fps, def.mode)
+    }
+}
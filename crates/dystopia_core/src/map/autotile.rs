@@ -0,0 +1,249 @@
+//! Automatic transitions between terrain kinds.
+//!
+//! Tiles on a layer with [`TilemapAutotile`] are recognized as terrains by their
+//! atlases. Whenever tiles are changed, atlases of them and their neighbours
+//! are picked again according to which neighbours share the same terrain. So to
+//! paint a terrain, just set any tile of it, like [`TilemapAutotile::tile_of`].
+
+use bevy::{
+    app::{App, Last, Plugin},
+    math::IVec2,
+    prelude::{Component, IntoSystemConfigs, Query},
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::map::{
+    event::TilemapChangeSystems,
+    query::TileNeighbourhood,
+    tilemap::{
+        FlattenedTileIndex, Tile, TileAtlasIndex, TileFlip, TileStaticAtlas, TilemapStorage,
+    },
+};
+
+pub(super) struct TilemapAutotilePlugin;
+
+impl Plugin for TilemapAutotilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Last, autotile.before(TilemapChangeSystems::Notify));
+    }
+}
+
+/// All masks of the 47-tile blob set, in the order of atlases in
+/// [`AutotileRule::atlases`].
+const BLOB_MASKS: [u8; 47] = [
+    0, 1, 4, 5, 7, 16, 17, 20, 21, 23, 28, 29, 31, 64, 65, 68, 69, 71, 80, 81, 84, 85, 87, 92, 93,
+    95, 112, 113, 116, 117, 119, 124, 125, 127, 193, 197, 199, 209, 213, 215, 221, 223, 241, 245,
+    247, 253, 255,
+];
+
+/// How neighbours are taken into account.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutotileMode {
+    /// 8 neighbours, with corners only counted if both adjacent edges are
+    /// connected. Requires 47 atlases, ordered by the reduced mask.
+    #[default]
+    Blob,
+    /// 4 neighbours sharing an edge. Requires 16 atlases, indexed by the mask.
+    Wang,
+    /// 3 neighbours on a triangular grid. Requires 16 atlases, 8 for tiles
+    /// pointing up then 8 for those pointing down, indexed by the mask.
+    Triangle,
+}
+
+impl AutotileMode {
+    /// Number of atlases a rule of this mode requires.
+    pub fn num_atlases(self) -> usize {
+        match self {
+            AutotileMode::Blob => BLOB_MASKS.len(),
+            AutotileMode::Wang | AutotileMode::Triangle => 16,
+        }
+    }
+
+    #[inline]
+    pub fn neighbourhood(self) -> TileNeighbourhood {
+        match self {
+            AutotileMode::Blob => TileNeighbourhood::Moore,
+            AutotileMode::Wang => TileNeighbourhood::VonNeumann,
+            AutotileMode::Triangle => TileNeighbourhood::Triangular,
+        }
+    }
+
+    /// Which atlas to use, given whether each neighbour is connected. Bits are
+    /// in the order of [`TileNeighbourhood::offsets`].
+    pub fn atlas_index(self, index: IVec2, mask: u8) -> usize {
+        match self {
+            AutotileMode::Blob => {
+                // Drop corners that are not surrounded by connected edges.
+                let reduced = [1, 3, 5, 7].into_iter().fold(mask, |reduced, corner| {
+                    let edges = (1 << (corner - 1)) | (1 << ((corner + 1) % 8));
+                    if mask & edges == edges {
+                        reduced
+                    } else {
+                        reduced & !(1 << corner)
+                    }
+                });
+                BLOB_MASKS.binary_search(&reduced).unwrap()
+            }
+            AutotileMode::Wang => mask as usize,
+            AutotileMode::Triangle => {
+                if (index.x + index.y).rem_euclid(2) == 0 {
+                    mask as usize
+                } else {
+                    mask as usize + 8
+                }
+            }
+        }
+    }
+}
+
+/// Atlases of a terrain kind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutotileRule {
+    pub terrain: u32,
+    /// Index of the texture in [`TilemapTilesets`](crate::map::tilemap::TilemapTilesets).
+    pub texture: u32,
+    pub mode: AutotileMode,
+    /// See [`AutotileMode`] for the order.
+    pub atlases: Vec<u32>,
+    /// Other terrains that this one blends into, i.e. treated as the same kind
+    /// when picking atlases.
+    #[serde(default)]
+    pub connects_to: Vec<u32>,
+}
+
+/// Enables autotiling on a tilemap layer.
+#[derive(Component, Debug, Clone)]
+pub struct TilemapAutotile {
+    rules: HashMap<u32, AutotileRule>,
+    terrains: HashMap<(u32, u32), u32>,
+}
+
+impl TilemapAutotile {
+    pub fn new(rules: impl IntoIterator<Item = AutotileRule>) -> Self {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                assert_eq!(
+                    rule.atlases.len(),
+                    rule.mode.num_atlases(),
+                    "Invalid autotile rule for terrain {}: Expected {} atlases.",
+                    rule.terrain,
+                    rule.mode.num_atlases()
+                );
+                (rule.terrain, rule)
+            })
+            .collect::<HashMap<_, _>>();
+
+        let terrains = rules
+            .values()
+            .flat_map(|rule| {
+                rule.atlases
+                    .iter()
+                    .map(|atlas| ((rule.texture, *atlas), rule.terrain))
+            })
+            .collect();
+
+        Self { rules, terrains }
+    }
+
+    #[inline]
+    pub fn rule(&self, terrain: u32) -> Option<&AutotileRule> {
+        self.rules.get(&terrain)
+    }
+
+    /// The terrain of the tile. Animated tiles and tiles not covered by any
+    /// rule have no terrain.
+    pub fn terrain_of(&self, tile: &Tile) -> Option<u32> {
+        match tile.atlas_index {
+            TileAtlasIndex::Static(atlas) => {
+                self.terrains.get(&(atlas.texture, atlas.atlas)).copied()
+            }
+            TileAtlasIndex::Animated { .. } => None,
+        }
+    }
+
+    /// An isolated tile of the terrain, which is good for painting.
+    pub fn tile_of(&self, terrain: u32) -> Option<TileStaticAtlas> {
+        self.rule(terrain).map(|rule| TileStaticAtlas {
+            texture: rule.texture,
+            atlas: rule.atlases[0],
+            flip: TileFlip::NONE,
+        })
+    }
+
+    /// Pick the atlas for the tile at `index` according to its neighbours.
+    /// Returns `None` if it has no terrain.
+    pub fn resolve(&self, storage: &TilemapStorage, index: IVec2) -> Option<TileStaticAtlas> {
+        let terrain = self.terrain_of(storage.get(index)?)?;
+        let rule = self.rule(terrain)?;
+
+        let mask = rule
+            .mode
+            .neighbourhood()
+            .neighbours(index)
+            .enumerate()
+            .filter(|(_, n)| {
+                storage
+                    .get(*n)
+                    .and_then(|t| self.terrain_of(t))
+                    .is_some_and(|t| t == terrain || rule.connects_to.contains(&t))
+            })
+            .fold(0u8, |mask, (bit, _)| mask | (1 << bit));
+
+        Some(TileStaticAtlas {
+            texture: rule.texture,
+            atlas: rule.atlases[rule.mode.atlas_index(index, mask)],
+            flip: TileFlip::NONE,
+        })
+    }
+}
+
+fn autotile(mut tilemaps_query: Query<(&TilemapAutotile, &mut TilemapStorage)>) {
+    for (autotile, mut storage) in &mut tilemaps_query {
+        if storage.changed_tiles().is_empty() && storage.changed_chunks().is_empty() {
+            continue;
+        }
+
+        let chunk_size = storage.chunk_size();
+        let mut changed = storage
+            .changed_tiles()
+            .keys()
+            .map(|index| index.to_direct(chunk_size))
+            .collect::<HashSet<_>>();
+        for chunk_index in storage.changed_chunks() {
+            let min = *chunk_index * chunk_size as i32;
+            for y in 0..chunk_size as i32 {
+                for x in 0..chunk_size as i32 {
+                    changed.insert(min + IVec2::new(x, y));
+                }
+            }
+        }
+
+        // Moore neighbourhood covers neighbours of all modes.
+        let dirty = changed
+            .iter()
+            .flat_map(|index| {
+                std::iter::once(*index).chain(TileNeighbourhood::Moore.neighbours(*index))
+            })
+            .collect::<HashSet<_>>();
+
+        // Resolve all first, so the result doesn't depend on the order.
+        let resolved = dirty
+            .into_iter()
+            .filter_map(|index| {
+                let atlas = autotile.resolve(&storage, index)?;
+                let tile = storage.get(index)?;
+                (!matches!(tile.atlas_index, TileAtlasIndex::Static(a) if a == atlas))
+                    .then_some((index, atlas))
+            })
+            .collect::<Vec<_>>();
+
+        for (index, atlas) in resolved {
+            let index = FlattenedTileIndex::from_direct(index, chunk_size);
+            if let Some(tile) = storage.flattened_get_mut(index) {
+                tile.atlas_index = TileAtlasIndex::Static(atlas);
+            }
+        }
+    }
+}
@@ -4,8 +4,9 @@ use bevy::{
     transform::components::{GlobalTransform, Transform},
 };
 
-use crate::map::tilemap::{
-    TileRenderSize, TilemapAnimations, TilemapStorage, TilemapTilesets, TilemapTint,
+use crate::map::{
+    layer::TilemapLayer,
+    tilemap::{TileRenderSize, TilemapAnimations, TilemapStorage, TilemapTilesets, TilemapTint},
 };
 
 #[derive(Bundle, Default)]
@@ -15,6 +16,7 @@ pub struct TilemapBundle {
     pub tint: TilemapTint,
     pub tilesets: TilemapTilesets,
     pub animations: TilemapAnimations,
+    pub layer: TilemapLayer,
     pub transform: Transform,
     pub global_transform: GlobalTransform,
     pub visibility: Visibility,
@@ -0,0 +1,315 @@
+//! Typed per-tile data living alongside [`Tile`](crate::map::tilemap::Tile)s,
+//! like soil fertility, moisture or crop growth.
+//!
+//! Data is stored in [`TileDataStorage`] on the root tilemap entity of a body,
+//! and is keyed by the same [`FlattenedTileIndex`] as tiles.
+
+use bevy::{
+    app::{App, First, Plugin},
+    ecs::{system::EntityCommands, world::EntityRef},
+    log::warn,
+    math::IVec2,
+    prelude::{Component, IntoSystemConfigs, Query, Resource},
+    utils::HashSet,
+};
+use bincode::{
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::{
+    map::{event::TilemapChangeSystems, serde::ENCDEC_CONFIG, tilemap::FlattenedTileIndex},
+    util::chunking::{Chunk, ChunkedStorage, DEFAULT_CHUNK_SIZE},
+};
+
+pub(super) struct TileDataPlugin;
+
+impl Plugin for TileDataPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileDataSerializers>();
+    }
+}
+
+/// Data that can be stored per tile.
+pub trait TileData: Clone + Send + Sync + 'static {}
+
+impl<T: Clone + Send + Sync + 'static> TileData for T {}
+
+/// Tile data that will be saved along with the tilemap.
+pub trait SerializableTileData: TileData + Encode + Decode {
+    /// The unique key of this data in the tilemap save. Changing this will make
+    /// existing saves lose the data.
+    const KEY: &'static str;
+}
+
+/// Stores data of type `T` for tiles.
+#[derive(Component)]
+pub struct TileDataStorage<T: TileData> {
+    internal: ChunkedStorage<IVec2, T>,
+    changed_tiles: HashSet<FlattenedTileIndex>,
+}
+
+impl<T: TileData> Default for TileDataStorage<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl<T: TileData> From<ChunkedStorage<IVec2, T>> for TileDataStorage<T> {
+    fn from(value: ChunkedStorage<IVec2, T>) -> Self {
+        Self {
+            internal: value,
+            changed_tiles: Default::default(),
+        }
+    }
+}
+
+impl<T: TileData> TileDataStorage<T> {
+    /// `chunk_size` should be the same as the one of the tilemap.
+    pub fn new(chunk_size: u32) -> Self {
+        Self {
+            internal: ChunkedStorage::new(chunk_size),
+            changed_tiles: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn chunk_size(&self) -> u32 {
+        self.internal.chunk_size()
+    }
+
+    #[inline]
+    pub fn chunked_storage(&self) -> &ChunkedStorage<IVec2, T> {
+        &self.internal
+    }
+
+    #[inline]
+    pub fn changed_tiles(&self) -> &HashSet<FlattenedTileIndex> {
+        &self.changed_tiles
+    }
+
+    #[inline]
+    pub fn get(&self, index: IVec2) -> Option<&T> {
+        self.flattened_get(FlattenedTileIndex::from_direct(index, self.chunk_size()))
+    }
+
+    #[inline]
+    pub fn flattened_get(&self, index: FlattenedTileIndex) -> Option<&T> {
+        self.internal.get(&index)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, index: IVec2) -> Option<&mut T> {
+        self.flattened_get_mut(FlattenedTileIndex::from_direct(index, self.chunk_size()))
+    }
+
+    #[inline]
+    pub fn flattened_get_mut(&mut self, index: FlattenedTileIndex) -> Option<&mut T> {
+        let data = self.internal.get_mut(&index)?;
+        self.changed_tiles.insert(index);
+        Some(data)
+    }
+
+    #[inline]
+    pub fn set(&mut self, index: IVec2, data: T) -> Option<T> {
+        self.flattened_set(
+            FlattenedTileIndex::from_direct(index, self.chunk_size()),
+            data,
+        )
+    }
+
+    #[inline]
+    pub fn flattened_set(&mut self, index: FlattenedTileIndex, data: T) -> Option<T> {
+        self.changed_tiles.insert(index);
+        self.internal.set(index, data)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, index: IVec2) -> Option<T> {
+        self.flattened_remove(FlattenedTileIndex::from_direct(index, self.chunk_size()))
+    }
+
+    #[inline]
+    pub fn flattened_remove(&mut self, index: FlattenedTileIndex) -> Option<T> {
+        if !self.internal.contains(&index) {
+            return None;
+        }
+
+        self.changed_tiles.insert(index);
+        self.internal.remove(&index)
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        let chunk_size = self.chunk_size();
+        self.changed_tiles
+            .extend(self.internal.iter().flat_map(|(ci, c)| {
+                c.iter().enumerate().filter_map(|(at, d)| {
+                    d.as_ref().map(|_| FlattenedTileIndex {
+                        in_chunk: *ci,
+                        in_chunk_at: at,
+                    })
+                })
+            }));
+        self.internal = ChunkedStorage::new(chunk_size);
+    }
+
+    /// Iterate over all data in parallel.
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = (FlattenedTileIndex, &T)> {
+        self.internal.par_iter().flat_map_iter(|(ci, c)| {
+            c.iter().enumerate().filter_map(move |(at, d)| {
+                d.as_ref().map(|d| {
+                    (
+                        FlattenedTileIndex {
+                            in_chunk: *ci,
+                            in_chunk_at: at,
+                        },
+                        d,
+                    )
+                })
+            })
+        })
+    }
+
+    /// Mutate all data in parallel. Return `true` in `f` to mark the tile as changed.
+    pub fn par_for_each_mut(&mut self, f: impl Fn(FlattenedTileIndex, &mut T) -> bool + Sync) {
+        let changed = self
+            .internal
+            .par_iter_mut()
+            .flat_map_iter(|(ci, c)| {
+                c.iter_mut()
+                    .enumerate()
+                    .filter_map(|(at, d)| {
+                        let index = FlattenedTileIndex {
+                            in_chunk: *ci,
+                            in_chunk_at: at,
+                        };
+                        d.as_mut().and_then(|d| f(index, d).then_some(index))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        self.changed_tiles.extend(changed);
+    }
+}
+
+pub fn clear_tile_data_changes<T: TileData>(mut storages_query: Query<&mut TileDataStorage<T>>) {
+    storages_query
+        .iter_mut()
+        .for_each(|mut s| s.changed_tiles.clear());
+}
+
+type BinaryTileData<T> = (u32, Vec<([i32; 2], Vec<Option<T>>)>);
+
+pub(super) struct TileDataSerializer {
+    pub key: &'static str,
+    pub encode: fn(&EntityRef) -> Option<Result<Vec<u8>, EncodeError>>,
+    pub insert: fn(&mut EntityCommands, &[u8]) -> Result<(), DecodeError>,
+}
+
+/// All registered [`SerializableTileData`]s.
+#[derive(Resource, Default)]
+pub struct TileDataSerializers(pub(super) Vec<TileDataSerializer>);
+
+impl TileDataSerializers {
+    pub(super) fn encode_all(&self, entity: &EntityRef) -> Vec<(String, Vec<u8>)> {
+        self.0
+            .iter()
+            .filter_map(|s| match (s.encode)(entity)? {
+                Ok(data) => Some((s.key.to_string(), data)),
+                Err(err) => {
+                    warn!("Failed to encode tile data {}: {}", s.key, err);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub(super) fn insert_all(&self, entity: &mut EntityCommands, data: Vec<(String, Vec<u8>)>) {
+        for (key, data) in data {
+            let Some(serializer) = self.0.iter().find(|s| s.key == key) else {
+                warn!("Unknown tile data {} found in save, skipped.", key);
+                continue;
+            };
+
+            if let Err(err) = (serializer.insert)(entity, &data) {
+                warn!("Failed to decode tile data {}: {}", key, err);
+            }
+        }
+    }
+}
+
+fn encode_tile_data<T: SerializableTileData>(
+    entity: &EntityRef,
+) -> Option<Result<Vec<u8>, EncodeError>> {
+    let storage = entity.get::<TileDataStorage<T>>()?;
+
+    Some(bincode::encode_to_vec(
+        (
+            storage.chunk_size(),
+            storage
+                .internal
+                .iter()
+                .map(|(ci, c)| (ci.to_array(), c.as_slice()))
+                .collect::<Vec<_>>(),
+        ),
+        ENCDEC_CONFIG,
+    ))
+}
+
+fn insert_tile_data<T: SerializableTileData>(
+    entity: &mut EntityCommands,
+    data: &[u8],
+) -> Result<(), DecodeError> {
+    let ((chunk_size, storage), _): (BinaryTileData<T>, _) =
+        bincode::decode_from_slice(data, ENCDEC_CONFIG)?;
+
+    entity.insert(TileDataStorage::from(ChunkedStorage::new_init(
+        chunk_size,
+        storage
+            .into_iter()
+            .map(|(ci, c)| (IVec2::from(ci), Chunk::from(c)))
+            .collect(),
+    )));
+
+    Ok(())
+}
+
+pub trait TileDataAppExt {
+    /// Register a kind of tile data, so its changes will be cleared each frame.
+    fn add_tile_data<T: TileData>(&mut self) -> &mut Self;
+
+    /// Register a kind of tile data, and save it along with the tilemap.
+    fn add_serializable_tile_data<T: SerializableTileData>(&mut self) -> &mut Self;
+}
+
+impl TileDataAppExt for App {
+    fn add_tile_data<T: TileData>(&mut self) -> &mut Self {
+        self.add_systems(
+            First,
+            clear_tile_data_changes::<T>.in_set(TilemapChangeSystems::Clear),
+        )
+    }
+
+    fn add_serializable_tile_data<T: SerializableTileData>(&mut self) -> &mut Self {
+        self.add_tile_data::<T>();
+
+        let mut serializers = self
+            .world_mut()
+            .get_resource_or_insert_with(TileDataSerializers::default);
+        assert!(
+            serializers.0.iter().all(|s| s.key != T::KEY),
+            "Tile data key {} is already registered.",
+            T::KEY
+        );
+        serializers.0.push(TileDataSerializer {
+            key: T::KEY,
+            encode: encode_tile_data::<T>,
+            insert: insert_tile_data::<T>,
+        });
+
+        self
+    }
+}
@@ -0,0 +1,221 @@
+//! Reversible edits on tilemaps, with undo/redo history.
+//!
+//! All edits go through [`TilemapStorage`], so the renderer and
+//! [`TileChanged`](crate::map::event::TileChanged) listeners will notice them
+//! just like normal edits.
+
+use std::collections::VecDeque;
+
+use bevy::{math::IVec2, prelude::Component};
+
+use crate::{
+    map::tilemap::{FlattenedTileIndex, Tile, TileIndex, TilemapStorage},
+    util::chunking::Chunk,
+};
+
+pub const DEFAULT_EDIT_HISTORY_CAPACITY: usize = 128;
+
+/// An edit on a tilemap. Applying an edit returns another edit that reverts it.
+#[derive(Clone)]
+pub enum TileEdit {
+    /// Set or remove (if `None`) tiles. Applied in order.
+    Tiles(Vec<(FlattenedTileIndex, Option<Tile>)>),
+    /// Replace or remove (if `None`) a whole chunk.
+    Chunk(IVec2, Option<Chunk<Tile>>),
+}
+
+impl TileEdit {
+    pub fn set(tile: Tile) -> Self {
+        Self::Tiles(vec![(tile.index.flattened(), Some(tile))])
+    }
+
+    pub fn remove(index: IVec2, chunk_size: u32) -> Self {
+        Self::Tiles(vec![(
+            FlattenedTileIndex::from_direct(index, chunk_size),
+            None,
+        )])
+    }
+
+    /// Fill tiles in `[min, max)` with `template`, or remove them if `None`.
+    pub fn rect(min: IVec2, max: IVec2, template: Option<&Tile>, chunk_size: u32) -> Self {
+        Self::Tiles(
+            (min.y..max.y)
+                .flat_map(|y| (min.x..max.x).map(move |x| IVec2 { x, y }))
+                .map(|direct| {
+                    let index = TileIndex::from_direct(direct, chunk_size);
+                    (
+                        index.flattened(),
+                        template.map(|t| Tile { index, ..t.clone() }),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    pub fn chunk(index: IVec2, chunk: Option<Chunk<Tile>>) -> Self {
+        Self::Chunk(index, chunk)
+    }
+
+    /// Apply this edit and return the reverting one.
+    pub fn apply(self, storage: &mut TilemapStorage) -> Self {
+        match self {
+            TileEdit::Tiles(tiles) => {
+                let mut reverting = tiles
+                    .into_iter()
+                    .map(|(index, tile)| {
+                        let old = match tile {
+                            Some(tile) => storage.set(tile),
+                            None => {
+                                if storage.flattened_get(index).is_some() {
+                                    storage.flattened_remove(index)
+                                } else {
+                                    None
+                                }
+                            }
+                        };
+                        (index, old)
+                    })
+                    .collect::<Vec<_>>();
+                // Revert in the opposite order, so editing the same tile multiple
+                // times can be reverted correctly.
+                reverting.reverse();
+                TileEdit::Tiles(reverting)
+            }
+            TileEdit::Chunk(index, chunk) => {
+                let old = match chunk {
+                    Some(chunk) => storage.set_chunk(index, chunk),
+                    None => storage.remove_chunk(index),
+                };
+                TileEdit::Chunk(index, old)
+            }
+        }
+    }
+
+    /// Merge `later`, which is applied after `self`, into `self`. Both should be
+    /// reverting edits. Only tile edits can be merged.
+    fn merge_reverting(&mut self, later: TileEdit) -> Result<(), TileEdit> {
+        match (self, later) {
+            (TileEdit::Tiles(earlier), TileEdit::Tiles(mut later)) => {
+                // The later one should be reverted first.
+                later.append(earlier);
+                *earlier = later;
+                Ok(())
+            }
+            (_, later) => Err(later),
+        }
+    }
+}
+
+/// Undo/redo history of a tilemap. Insert this onto tilemaps that can be edited.
+#[derive(Component)]
+pub struct TilemapEditHistory {
+    capacity: usize,
+    undo: VecDeque<TileEdit>,
+    redo: Vec<TileEdit>,
+    stroke: StrokeState,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StrokeState {
+    None,
+    /// The stroke began but nothing is edited yet.
+    Began,
+    /// The latest edit in undo stack belongs to this stroke.
+    Recording,
+}
+
+impl Default for TilemapEditHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_EDIT_HISTORY_CAPACITY)
+    }
+}
+
+impl TilemapEditHistory {
+    /// Only the latest `capacity` edits can be undone.
+    pub fn new(capacity: usize) -> Self {
+        assert_ne!(capacity, 0, "Invalid capacity: Must be larger than 0.");
+
+        Self {
+            capacity,
+            undo: Default::default(),
+            redo: Default::default(),
+            stroke: StrokeState::None,
+        }
+    }
+
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    #[inline]
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Start a brush stroke. All edits until [`Self::end_stroke`] will be merged
+    /// into one, so they can be undone at once.
+    pub fn begin_stroke(&mut self) {
+        self.stroke = StrokeState::Began;
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.stroke = StrokeState::None;
+    }
+
+    /// Apply the edit to `storage` and record it.
+    pub fn apply(&mut self, storage: &mut TilemapStorage, edit: TileEdit) {
+        let reverting = edit.apply(storage);
+        self.redo.clear();
+
+        let reverting = match (self.stroke, self.undo.back_mut()) {
+            (StrokeState::Recording, Some(last)) => match last.merge_reverting(reverting) {
+                Ok(_) => return,
+                Err(reverting) => reverting,
+            },
+            _ => reverting,
+        };
+
+        self.push_undo(reverting);
+        if self.stroke == StrokeState::Began {
+            self.stroke = StrokeState::Recording;
+        }
+    }
+
+    /// Revert the latest edit. Returns `false` if there's nothing to undo.
+    pub fn undo(&mut self, storage: &mut TilemapStorage) -> bool {
+        self.end_stroke();
+
+        let Some(reverting) = self.undo.pop_back() else {
+            return false;
+        };
+
+        self.redo.push(reverting.apply(storage));
+        true
+    }
+
+    /// Re-apply the latest undone edit. Returns `false` if there's nothing to redo.
+    pub fn redo(&mut self, storage: &mut TilemapStorage) -> bool {
+        self.end_stroke();
+
+        let Some(edit) = self.redo.pop() else {
+            return false;
+        };
+
+        self.push_undo(edit.apply(storage));
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.stroke = StrokeState::None;
+    }
+
+    fn push_undo(&mut self, reverting: TileEdit) {
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(reverting);
+    }
+}
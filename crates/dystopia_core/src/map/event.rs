@@ -0,0 +1,99 @@
+//! Notify other systems about changes on tilemaps.
+//!
+//! Changes are collected by [`TilemapStorage`] during the frame, turned into
+//! events in [`TilemapChangeSystems::Notify`] and forgotten in
+//! [`TilemapChangeSystems::Clear`] at the beginning of the next frame. So any
+//! edit on tilemaps should happen between these two sets, otherwise it won't be
+//! noticed by the renderer or other systems.
+
+use bevy::{
+    app::{App, First, Last, Plugin},
+    math::IVec2,
+    prelude::{Entity, Event, EventWriter, IntoSystemConfigs, Query, SystemSet},
+};
+
+use crate::map::tilemap::{FlattenedTileIndex, Tile, TilemapStorage};
+
+pub(super) struct TilemapEventPlugin;
+
+impl Plugin for TilemapEventPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TileChanged>()
+            .add_event::<TileChunkChanged>()
+            .add_systems(
+                First,
+                clear_tilemap_changes.in_set(TilemapChangeSystems::Clear),
+            )
+            .add_systems(
+                Last,
+                send_tilemap_change_events.in_set(TilemapChangeSystems::Notify),
+            );
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TilemapChangeSystems {
+    /// Changes recorded in the previous frame are forgotten. Runs in [`First`].
+    Clear,
+    /// [`TileChanged`] and [`TileChunkChanged`] are sent. Runs in [`Last`].
+    Notify,
+}
+
+/// A tile is set, removed or mutably accessed in this frame.
+///
+/// Multiple changes on the same tile in one frame are merged into one event.
+#[derive(Event, Debug, Clone)]
+pub struct TileChanged {
+    pub tilemap: Entity,
+    pub index: FlattenedTileIndex,
+    pub old: Option<Tile>,
+    pub new: Option<Tile>,
+}
+
+/// A whole chunk is set, removed or mutably accessed in this frame. Tiles in
+/// these chunks won't fire [`TileChanged`] individually.
+///
+/// Chunks loaded or unloaded by [`stream`](crate::map::stream) don't fire this.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TileChunkChanged {
+    pub tilemap: Entity,
+    pub index: IVec2,
+}
+
+fn clear_tilemap_changes(mut tilemaps_query: Query<&mut TilemapStorage>) {
+    tilemaps_query
+        .iter_mut()
+        .for_each(|mut t| t.clear_changes());
+}
+
+fn send_tilemap_change_events(
+    tilemaps_query: Query<(Entity, &TilemapStorage)>,
+    mut tile_changed: EventWriter<TileChanged>,
+    mut chunk_changed: EventWriter<TileChunkChanged>,
+) {
+    for (tilemap, storage) in &tilemaps_query {
+        tile_changed.send_batch(storage.changed_tiles().iter().filter_map(|(index, old)| {
+            let new = storage.flattened_get(*index).cloned();
+            if old.is_none() && new.is_none() {
+                return None;
+            }
+
+            Some(TileChanged {
+                tilemap,
+                index: *index,
+                old: old.clone(),
+                new,
+            })
+        }));
+
+        chunk_changed.send_batch(
+            storage
+                .changed_chunks()
+                .iter()
+                .map(|index| TileChunkChanged {
+                    tilemap,
+                    index: *index,
+                }),
+        );
+    }
+}
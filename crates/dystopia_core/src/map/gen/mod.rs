@@ -1,7 +1,7 @@
 use bevy::{
     app::{App, Plugin, Update},
     asset::AssetServer,
-    math::{UVec2, Vec2},
+    math::Vec2,
     prelude::{
         in_state, Commands, Component, Entity, Has, IntoSystemConfigs, Query, Res, Visibility,
     },
@@ -13,11 +13,12 @@ use crate::{
     cosmos::celestial::{BodyIndex, BodyTilemap},
     map::{
         bundle::TilemapBundle,
+        layer::{spawn_layered_tilemap, TilemapLayer},
         shape::rectangle,
         tilemap::{
-            Tile, TileAtlasIndex, TileIndex, TileRenderSize, TilemapStorage, TilemapTexture,
-            TilemapTextureDescriptor, TilemapTilesets,
+            Tile, TileAtlasIndex, TileIndex, TileRenderSize, TilemapStorage, TilemapTilesets,
         },
+        tileset::TilesetRegistry,
     },
     schedule::state::SceneState,
     util::chunking::DEFAULT_CHUNK_SIZE,
@@ -41,6 +42,7 @@ pub struct ToGenerateMap;
 pub fn generate_map(
     mut commands: Commands,
     bodies_query: Query<(Entity, &BodyIndex, &ToGenerateMap, Has<BodyTilemap>)>,
+    tileset_registry: Res<TilesetRegistry>,
     asset_server: Res<AssetServer>,
 ) {
     for (entity, index, _generation_cofig, has_tilemap) in &bodies_query {
@@ -48,35 +50,26 @@ pub fn generate_map(
             continue;
         }
 
-        let mut tilemap = TilemapBundle {
+        let tilesets = TilemapTilesets::new(
+            ["test_tileset_a", "test_tileset_b"]
+                .into_iter()
+                .map(|id| tileset_registry.texture_or_placeholder(id, None, &asset_server))
+                .collect(),
+            FilterMode::Nearest,
+        );
+
+        let mut ground = TilemapBundle {
             tile_render_size: TileRenderSize(Vec2 { x: 32., y: 16. }),
             storgae: TilemapStorage::default(),
-            tilesets: TilemapTilesets::new(
-                vec![
-                    TilemapTexture {
-                        handle: asset_server.load("images/test_tileset_a.png"),
-                        desc: TilemapTextureDescriptor {
-                            size: UVec2 { x: 32, y: 32 },
-                            tile_size: UVec2 { x: 32, y: 16 },
-                        },
-                    },
-                    TilemapTexture {
-                        handle: asset_server.load("images/test_tileset_b.png"),
-                        desc: TilemapTextureDescriptor {
-                            size: UVec2 { x: 32, y: 32 },
-                            tile_size: UVec2 { x: 32, y: 16 },
-                        },
-                    },
-                ],
-                FilterMode::Nearest,
-            ),
+            tilesets: tilesets.clone(),
+            layer: TilemapLayer::GROUND,
             visibility: Visibility::Hidden,
             ..Default::default()
         };
 
         let mut rng = rand::thread_rng();
         for index in rectangle(10, 10).into_iter() {
-            tilemap.storgae.set(Tile {
+            ground.storgae.set(Tile {
                 index: TileIndex::from_direct(index.as_ivec2(), DEFAULT_CHUNK_SIZE),
                 atlas_index: TileAtlasIndex::Static(
                     (rng.next_u32() % 2, rng.next_u32() % 2).into(),
@@ -85,7 +78,24 @@ pub fn generate_map(
             });
         }
 
-        let tilemap = commands.spawn(tilemap).id();
+        let tilemap = spawn_layered_tilemap(
+            &mut commands,
+            [
+                ground,
+                TilemapBundle {
+                    tile_render_size: TileRenderSize(Vec2 { x: 32., y: 16. }),
+                    tilesets: tilesets.clone(),
+                    layer: TilemapLayer::OBJECTS,
+                    ..Default::default()
+                },
+                TilemapBundle {
+                    tile_render_size: TileRenderSize(Vec2 { x: 32., y: 16. }),
+                    tilesets,
+                    layer: TilemapLayer::OVERLAYS,
+                    ..Default::default()
+                },
+            ],
+        );
         commands
             .entity(entity)
             .insert(BodyTilemap::new(tilemap))
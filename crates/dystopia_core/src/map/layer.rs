@@ -0,0 +1,79 @@
+//! Layered tilemaps. Each layer is a standalone tilemap entity with its own
+//! storage and tilesets, and all layers of a body are children of the root
+//! layer, so they share the same transform and visibility.
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::{BuildChildren, Commands, Component, Deref, Entity, Visibility};
+
+use crate::map::bundle::TilemapBundle;
+
+/// The z-order of a tilemap layer. Layers with larger z-order are drawn on top
+/// of those with smaller ones.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TilemapLayer {
+    pub z_order: u32,
+}
+
+impl TilemapLayer {
+    /// Terrain, soil and water.
+    pub const GROUND: Self = Self { z_order: 0 };
+    /// Crops, buildings and other things placed on the ground.
+    pub const OBJECTS: Self = Self { z_order: 1 };
+    /// Selections, hints and other indicators.
+    pub const OVERLAYS: Self = Self { z_order: 2 };
+}
+
+/// All layers of a tilemap, sorted by z-order. Only exists on the root layer,
+/// and the root layer itself is also included.
+#[derive(Component, Debug, Default, Clone, Deref)]
+pub struct TilemapLayers(BTreeMap<u32, Entity>);
+
+impl TilemapLayers {
+    #[inline]
+    pub fn get(&self, layer: TilemapLayer) -> Option<Entity> {
+        self.0.get(&layer.z_order).copied()
+    }
+
+    /// Layer entities from bottom to top.
+    #[inline]
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.0.values().copied()
+    }
+}
+
+/// Spawn all layers of a tilemap and return the root, which is the first layer
+/// in `layers`. Other layers are spawned as children of the root.
+///
+/// The root entity is what should be stored in
+/// [`BodyTilemap`](crate::cosmos::celestial::BodyTilemap).
+pub fn spawn_layered_tilemap(
+    commands: &mut Commands,
+    layers: impl IntoIterator<Item = TilemapBundle>,
+) -> Entity {
+    let mut layers = layers.into_iter();
+    let root_bundle = layers
+        .next()
+        .expect("Invalid layers: At least one layer is required.");
+
+    let mut stack = BTreeMap::new();
+    let root_layer = root_bundle.layer;
+    let root = commands.spawn(root_bundle).id();
+    stack.insert(root_layer.z_order, root);
+
+    for mut bundle in layers {
+        // Visibility is controlled by the root.
+        bundle.visibility = Visibility::Inherited;
+        let z_order = bundle.layer.z_order;
+        let layer = commands.spawn(bundle).set_parent(root).id();
+
+        assert!(
+            stack.insert(z_order, layer).is_none(),
+            "Invalid layers: Duplicated z-order {}.",
+            z_order
+        );
+    }
+
+    commands.entity(root).insert(TilemapLayers(stack));
+    root
+}
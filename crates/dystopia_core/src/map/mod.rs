@@ -1,11 +1,24 @@
 use bevy::app::{App, Plugin};
 
+pub mod animation;
+pub mod autotile;
 pub mod bundle;
+pub mod data;
+pub mod edit;
+pub mod event;
 pub mod gen;
+pub mod layer;
+pub mod picking;
+pub mod prefab;
+pub mod query;
 pub mod render;
 pub mod serde;
 pub mod shape;
+pub mod snapshot;
+pub mod stream;
+pub mod tiled;
 pub mod tilemap;
+pub mod tileset;
 
 pub struct DystopiaMapPlugin;
 
@@ -13,8 +26,17 @@ impl Plugin for DystopiaMapPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
             render::TilemapRenderPlugin,
+            data::TileDataPlugin,
+            event::TilemapEventPlugin,
             serde::TilemapSerdePlugin,
             gen::TilemapGenerationPlugin,
+            picking::TilemapPickingPlugin,
+            prefab::TilemapPrefabPlugin,
+            stream::TilemapStreamingPlugin,
+            tiled::TiledImportPlugin,
+            tileset::TilesetRegistryPlugin,
+            animation::TileAnimationRegistryPlugin,
+            autotile::TilemapAutotilePlugin,
         ));
     }
 }
@@ -0,0 +1,165 @@
+//! Convert between world positions and tiles, and pick tiles on the focused
+//! body using the cursor.
+
+use bevy::{
+    app::{App, Plugin, Update},
+    input::ButtonState,
+    math::{IVec2, Vec2, Vec3},
+    prelude::{
+        in_state, resource_exists, Camera, Entity, Event, EventReader, EventWriter,
+        GlobalTransform, IntoSystemConfigs, MouseButton, OnExit, Query, Res, ResMut, Resource,
+        With,
+    },
+};
+
+use crate::{
+    body::FocusingOn,
+    input::{SceneCursorPosition, SceneMouseInput},
+    map::tilemap::{TileIndex, TileRenderSize, TilemapStorage},
+    schedule::state::SceneState,
+    sim::MainCamera,
+};
+
+pub(super) struct TilemapPickingPlugin;
+
+impl Plugin for TilemapPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TileHovered>()
+            .add_event::<TilePicked>()
+            .init_resource::<HoveredTile>()
+            .add_systems(
+                Update,
+                (hover_tile, pick_tile)
+                    .chain()
+                    .run_if(resource_exists::<FocusingOn>)
+                    .run_if(in_state(SceneState::FocusingBody)),
+            )
+            .add_systems(
+                OnExit(SceneState::FocusingBody),
+                |mut hovered: ResMut<HoveredTile>| hovered.0 = None,
+            );
+    }
+}
+
+/// The tile the cursor is currently over. Only tiles that exist in the
+/// [`TilemapStorage`] can be hovered.
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct HoveredTile(pub Option<(Entity, TileIndex)>);
+
+/// Fired when [`HoveredTile`] changes to another tile.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TileHovered {
+    pub tilemap: Entity,
+    pub index: TileIndex,
+}
+
+/// Fired when player clicks on a tile that is not covered by UI.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TilePicked {
+    pub tilemap: Entity,
+    pub index: TileIndex,
+    pub button: MouseButton,
+    pub state: ButtonState,
+}
+
+/// World position of the center of the tile.
+pub fn tile_to_world(
+    index: IVec2,
+    tile_render_size: &TileRenderSize,
+    transform: &GlobalTransform,
+) -> Vec2 {
+    // Keep synced with `get_origin` in `tilemap.wgsl`.
+    let index = index.as_vec2();
+    let center = Vec2 {
+        x: (index.x - index.y) / 2. * tile_render_size.x,
+        y: (index.x + index.y + 1.) / 2. * tile_render_size.y,
+    };
+
+    transform.transform_point(center.extend(0.)).truncate()
+}
+
+/// The tile at the world position, no matter whether it exists or not.
+pub fn world_to_tile(
+    position: Vec2,
+    tile_render_size: &TileRenderSize,
+    transform: &GlobalTransform,
+) -> IVec2 {
+    let local = transform
+        .affine()
+        .inverse()
+        .transform_point3(Vec3::from((position, 0.)))
+        .truncate();
+
+    // `x - y` and `x + y` of the tile.
+    let diff = local.x / tile_render_size.x * 2.;
+    let sum = local.y / tile_render_size.y * 2. - 1.;
+
+    Vec2 {
+        x: (sum + diff) / 2.,
+        y: (sum - diff) / 2.,
+    }
+    .round()
+    .as_ivec2()
+}
+
+fn cursor_tile(
+    cursor_pos: &SceneCursorPosition,
+    main_camera: &Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    tilemaps_query: &Query<(&TileRenderSize, &GlobalTransform, &TilemapStorage)>,
+    focusing_on: &FocusingOn,
+) -> Option<(Entity, TileIndex)> {
+    let (camera, camera_transform) = main_camera.get_single().ok()?;
+    let cursor_pos =
+        (**cursor_pos).and_then(|p| camera.viewport_to_world_2d(camera_transform, p))?;
+
+    let tilemap = *focusing_on.tilemap;
+    let (tile_render_size, transform, storage) = tilemaps_query.get(tilemap).ok()?;
+    let index = world_to_tile(cursor_pos, tile_render_size, transform);
+
+    storage.get(index).map(|tile| (tilemap, tile.index))
+}
+
+fn hover_tile(
+    cursor_pos: Res<SceneCursorPosition>,
+    main_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    tilemaps_query: Query<(&TileRenderSize, &GlobalTransform, &TilemapStorage)>,
+    focusing_on: Res<FocusingOn>,
+    mut hovered: ResMut<HoveredTile>,
+    mut tile_hovered: EventWriter<TileHovered>,
+) {
+    let current = cursor_tile(&cursor_pos, &main_camera, &tilemaps_query, &focusing_on);
+
+    let is_same = match (hovered.0, current) {
+        (Some((lt, li)), Some((ct, ci))) => lt == ct && li.direct() == ci.direct(),
+        (None, None) => true,
+        _ => false,
+    };
+
+    if is_same {
+        return;
+    }
+
+    hovered.0 = current;
+    if let Some((tilemap, index)) = current {
+        tile_hovered.send(TileHovered { tilemap, index });
+    }
+}
+
+fn pick_tile(
+    hovered: Res<HoveredTile>,
+    mut scene_mouse_input: EventReader<SceneMouseInput>,
+    mut tile_picked: EventWriter<TilePicked>,
+) {
+    for input in scene_mouse_input.read() {
+        let Some((tilemap, index)) = hovered.0 else {
+            continue;
+        };
+
+        tile_picked.send(TilePicked {
+            tilemap,
+            index,
+            button: input.button,
+            state: input.state,
+        });
+    }
+}
@@ -0,0 +1,291 @@
+//! Pre-authored structures, like landing sites, greenhouses or ruins, that can
+//! be stamped onto tilemaps.
+
+use bevy::{
+    app::{App, Plugin},
+    asset::{io::Reader, Asset, AssetApp, AssetLoader, AsyncReadExt, LoadContext},
+    color::{ColorToComponents, LinearRgba},
+    math::IVec2,
+    reflect::TypePath,
+};
+use bincode::{
+    error::{DecodeError, EncodeError},
+    Decode, Encode,
+};
+use thiserror::Error;
+
+use crate::map::{
+    edit::TileEdit,
+    layer::TilemapLayer,
+    serde::ENCDEC_CONFIG,
+    tilemap::{Tile, TileAtlasIndex, TileFlip, TileIndex, TileStaticAtlas, TilemapStorage},
+};
+
+pub(super) struct TilemapPrefabPlugin;
+
+impl Plugin for TilemapPrefabPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<TilemapPrefab>()
+            .init_asset_loader::<TilemapPrefabLoader>();
+    }
+}
+
+/// A tile in a prefab. Only static tiles are supported, as animations are
+/// registered per tilemap.
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct PrefabTile {
+    /// Position relative to the anchor of the prefab.
+    pub offset: [i32; 2],
+    pub texture: u32,
+    pub atlas: u32,
+    pub flip: u32,
+    pub tint: [f32; 4],
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct PrefabLayer {
+    pub z_order: u32,
+    pub tiles: Vec<PrefabTile>,
+}
+
+/// A tile pattern with multiple layers. Tile positions are relative to the
+/// anchor, which will be placed at the target tile when stamping.
+#[derive(Encode, Decode, Asset, TypePath, Debug, Clone)]
+pub struct TilemapPrefab {
+    pub layers: Vec<PrefabLayer>,
+}
+
+/// Rotation of the prefab in tile space, clockwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PrefabRotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+/// What to do if a tile in the prefab overlaps an existing tile.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StampCollision {
+    /// Refuse to stamp at all.
+    #[default]
+    Reject,
+    /// Replace the existing tile.
+    Overwrite,
+    /// Keep the existing tile and skip the one in the prefab.
+    Skip,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StampOptions {
+    /// Applied after flipping.
+    pub rotation: PrefabRotation,
+    /// Mirror the prefab on screen. Tiles' own flips are toggled as well.
+    pub flip: TileFlip,
+    pub collision: StampCollision,
+}
+
+#[derive(Error, Debug)]
+pub enum PrefabStampError {
+    #[error("Prefab collides with existing tiles at {0:?}.")]
+    Collided(Vec<IVec2>),
+}
+
+impl TilemapPrefab {
+    #[inline]
+    pub fn layer(&self, layer: TilemapLayer) -> Option<&PrefabLayer> {
+        self.layers.iter().find(|l| l.z_order == layer.z_order)
+    }
+
+    /// Where a tile at `offset` in the prefab goes, if the anchor is at `at`.
+    pub fn transform_offset(offset: IVec2, at: IVec2, options: &StampOptions) -> IVec2 {
+        // Horizontal on screen is along `x - y`, so mirroring it swaps x and y.
+        let mut offset = offset;
+        if options.flip.contains(TileFlip::HORIZONTAL) {
+            offset = IVec2::new(offset.y, offset.x);
+        }
+        if options.flip.contains(TileFlip::VERTICAL) {
+            offset = IVec2::new(-offset.y, -offset.x);
+        }
+
+        let offset = match options.rotation {
+            PrefabRotation::None => offset,
+            PrefabRotation::Cw90 => IVec2::new(offset.y, -offset.x),
+            PrefabRotation::Cw180 => -offset,
+            PrefabRotation::Cw270 => IVec2::new(-offset.y, offset.x),
+        };
+
+        at + offset
+    }
+
+    /// Tiles the layer will cover if the anchor is at `at`.
+    pub fn footprint(
+        &self,
+        layer: TilemapLayer,
+        at: IVec2,
+        options: &StampOptions,
+    ) -> impl Iterator<Item = IVec2> + '_ {
+        let options = *options;
+        self.layer(layer).into_iter().flat_map(move |l| {
+            l.tiles
+                .iter()
+                .map(move |t| Self::transform_offset(t.offset.into(), at, &options))
+        })
+    }
+
+    /// Existing tiles in `storage` that the layer overlaps.
+    pub fn collisions(
+        &self,
+        layer: TilemapLayer,
+        storage: &TilemapStorage,
+        at: IVec2,
+        options: &StampOptions,
+    ) -> Vec<IVec2> {
+        self.footprint(layer, at, options)
+            .filter(|index| storage.get(*index).is_some())
+            .collect()
+    }
+
+    /// Build the edit that stamps the layer onto `storage`, without applying it.
+    pub fn edit(
+        &self,
+        layer: TilemapLayer,
+        storage: &TilemapStorage,
+        at: TileIndex,
+        options: &StampOptions,
+    ) -> Result<TileEdit, PrefabStampError> {
+        let at = at.direct();
+        if options.collision == StampCollision::Reject {
+            let collisions = self.collisions(layer, storage, at, options);
+            if !collisions.is_empty() {
+                return Err(PrefabStampError::Collided(collisions));
+            }
+        }
+
+        let chunk_size = storage.chunk_size();
+        let tiles = self
+            .layer(layer)
+            .into_iter()
+            .flat_map(|l| l.tiles.iter())
+            .filter_map(|t| {
+                let index = TileIndex::from_direct(
+                    Self::transform_offset(t.offset.into(), at, options),
+                    chunk_size,
+                );
+                if options.collision == StampCollision::Skip
+                    && storage.get(index.direct()).is_some()
+                {
+                    return None;
+                }
+
+                Some((
+                    index.flattened(),
+                    Some(Tile {
+                        index,
+                        atlas_index: TileAtlasIndex::Static(TileStaticAtlas {
+                            texture: t.texture,
+                            atlas: t.atlas,
+                            flip: TileFlip::from_bits_truncate(t.flip) ^ options.flip,
+                        }),
+                        tint: LinearRgba::from_f32_array(t.tint).into(),
+                        visible: true,
+                    }),
+                ))
+            })
+            .collect();
+
+        Ok(TileEdit::Tiles(tiles))
+    }
+
+    /// Stamp the layer onto `storage`, with the anchor at `at`. Returns the edit
+    /// that reverts it.
+    ///
+    /// To make it undoable, pass the result of [`Self::edit`] to
+    /// [`TilemapEditHistory::apply`](crate::map::edit::TilemapEditHistory::apply)
+    /// instead.
+    pub fn stamp(
+        &self,
+        layer: TilemapLayer,
+        storage: &mut TilemapStorage,
+        at: TileIndex,
+        options: &StampOptions,
+    ) -> Result<TileEdit, PrefabStampError> {
+        Ok(self.edit(layer, storage, at, options)?.apply(storage))
+    }
+
+    /// Capture tiles in `[min, max)` of all `layers` as a prefab. Animated tiles
+    /// are skipped.
+    pub fn capture<'a>(
+        layers: impl IntoIterator<Item = (TilemapLayer, &'a TilemapStorage)>,
+        min: IVec2,
+        max: IVec2,
+        anchor: IVec2,
+    ) -> Self {
+        Self {
+            layers: layers
+                .into_iter()
+                .map(|(layer, storage)| PrefabLayer {
+                    z_order: layer.z_order,
+                    tiles: storage
+                        .chunked_storage()
+                        .rect(min, max)
+                        .filter_map(|(index, tile)| match tile.atlas_index {
+                            TileAtlasIndex::Static(atlas) => Some(PrefabTile {
+                                offset: (index - anchor).to_array(),
+                                texture: atlas.texture,
+                                atlas: atlas.atlas,
+                                flip: atlas.flip.bits(),
+                                tint: tile.tint.to_linear().to_f32_array(),
+                            }),
+                            TileAtlasIndex::Animated { .. } => None,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        bincode::encode_to_vec(self, ENCDEC_CONFIG)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TilemapPrefabLoadError {
+    #[error("Io error: {0:?}")]
+    Io(std::io::Error),
+    #[error("Decode error: {0:?}")]
+    Decode(DecodeError),
+}
+
+#[derive(Default)]
+pub struct TilemapPrefabLoader;
+
+impl AssetLoader for TilemapPrefabLoader {
+    type Asset = TilemapPrefab;
+
+    type Settings = ();
+
+    type Error = TilemapPrefabLoadError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut buf = Vec::new();
+        reader
+            .read_to_end(&mut buf)
+            .await
+            .map_err(|e| TilemapPrefabLoadError::Io(e))?;
+        bincode::decode_from_slice(&buf, ENCDEC_CONFIG)
+            .map(|r| r.0)
+            .map_err(|e| TilemapPrefabLoadError::Decode(e))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tpf"]
+    }
+}
@@ -0,0 +1,231 @@
+//! Spatial queries over tiles, like neighbours, regions and lines.
+//!
+//! All queries work directly on [`ChunkedStorage`] by direct indices, and only
+//! hand out references, so no tile is cloned.
+
+use std::collections::VecDeque;
+
+use bevy::{
+    math::IVec2,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{map::tilemap::FlattenedTileIndex, util::chunking::ChunkedStorage};
+
+const VON_NEUMANN_OFFSETS: [IVec2; 4] = [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y];
+
+const MOORE_OFFSETS: [IVec2; 8] = [
+    IVec2::X,
+    IVec2::ONE,
+    IVec2::Y,
+    IVec2 { x: -1, y: 1 },
+    IVec2::NEG_X,
+    IVec2::NEG_ONE,
+    IVec2::NEG_Y,
+    IVec2 { x: 1, y: -1 },
+];
+
+const TRIANGULAR_UP_OFFSETS: [IVec2; 3] = [IVec2::NEG_X, IVec2::X, IVec2::NEG_Y];
+const TRIANGULAR_DOWN_OFFSETS: [IVec2; 3] = [IVec2::NEG_X, IVec2::X, IVec2::Y];
+
+/// Which tiles are considered to be adjacent to a tile.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TileNeighbourhood {
+    /// 4 tiles sharing an edge.
+    #[default]
+    VonNeumann,
+    /// 8 tiles sharing an edge or a corner.
+    Moore,
+    /// 3 tiles sharing an edge on a up-down triangular grid. Tiles with even
+    /// `x + y` points up, others point down.
+    Triangular,
+}
+
+impl TileNeighbourhood {
+    #[inline]
+    pub fn offsets(self, index: IVec2) -> &'static [IVec2] {
+        match self {
+            TileNeighbourhood::VonNeumann => &VON_NEUMANN_OFFSETS,
+            TileNeighbourhood::Moore => &MOORE_OFFSETS,
+            TileNeighbourhood::Triangular => {
+                if (index.x + index.y).rem_euclid(2) == 0 {
+                    &TRIANGULAR_UP_OFFSETS
+                } else {
+                    &TRIANGULAR_DOWN_OFFSETS
+                }
+            }
+        }
+    }
+
+    /// Indices of all neighbours, no matter whether they exist or not.
+    #[inline]
+    pub fn neighbours(self, index: IVec2) -> impl Iterator<Item = IVec2> {
+        self.offsets(index).iter().map(move |o| index + *o)
+    }
+}
+
+/// Result of [`ChunkedStorage::connected_components`].
+#[derive(Debug, Default, Clone)]
+pub struct TileComponents {
+    /// The component each tile belongs to.
+    pub labels: HashMap<IVec2, u32>,
+    /// Tiles in each component. The index of the vector is the label.
+    pub components: Vec<Vec<IVec2>>,
+}
+
+/// All tiles on the line from `from` to `to`, both ends included.
+pub fn bresenham_line(from: IVec2, to: IVec2) -> Vec<IVec2> {
+    let delta = (to - from).abs();
+    let step = (to - from).signum();
+    let mut cur = from;
+    let mut err = delta.x - delta.y;
+    let mut line = Vec::with_capacity(delta.max_element() as usize + 1);
+
+    loop {
+        line.push(cur);
+        if cur == to {
+            break;
+        }
+
+        let e2 = err * 2;
+        if e2 > -delta.y {
+            err -= delta.y;
+            cur.x += step.x;
+        }
+        if e2 < delta.x {
+            err += delta.x;
+            cur.y += step.y;
+        }
+    }
+
+    line
+}
+
+impl<T: Clone> ChunkedStorage<IVec2, T> {
+    #[inline]
+    pub fn get_direct(&self, index: IVec2) -> Option<&T> {
+        self.get(&FlattenedTileIndex::from_direct(index, self.chunk_size()))
+    }
+
+    #[inline]
+    pub fn contains_direct(&self, index: IVec2) -> bool {
+        self.contains(&FlattenedTileIndex::from_direct(index, self.chunk_size()))
+    }
+
+    /// Iterate over all existing items, along with their direct indices.
+    pub fn iter_direct(&self) -> impl Iterator<Item = (IVec2, &T)> {
+        let chunk_size = self.chunk_size();
+        self.iter().flat_map(move |(chunk_index, chunk)| {
+            chunk.iter().enumerate().filter_map(move |(at, item)| {
+                item.as_ref().map(|item| {
+                    (
+                        FlattenedTileIndex {
+                            in_chunk: *chunk_index,
+                            in_chunk_at: at,
+                        }
+                        .to_direct(chunk_size),
+                        item,
+                    )
+                })
+            })
+        })
+    }
+
+    /// Existing neighbours of `index`.
+    pub fn neighbours(
+        &self,
+        index: IVec2,
+        neighbourhood: TileNeighbourhood,
+    ) -> impl Iterator<Item = (IVec2, &T)> {
+        neighbourhood
+            .neighbours(index)
+            .filter_map(move |n| self.get_direct(n).map(|t| (n, t)))
+    }
+
+    /// Existing items in `[min, max)`.
+    pub fn rect(&self, min: IVec2, max: IVec2) -> impl Iterator<Item = (IVec2, &T)> {
+        (min.y..max.y)
+            .flat_map(move |y| (min.x..max.x).map(move |x| IVec2 { x, y }))
+            .filter_map(move |i| self.get_direct(i).map(|t| (i, t)))
+    }
+
+    /// Existing items whose distance to `center` is no more than `radius`.
+    pub fn circle(&self, center: IVec2, radius: u32) -> impl Iterator<Item = (IVec2, &T)> {
+        let r = radius as i32;
+        self.rect(center - r, center + r + 1)
+            .filter(move |(i, _)| (*i - center).length_squared() <= r * r)
+    }
+
+    /// Existing items on the line from `from` to `to`. See [`bresenham_line`].
+    pub fn line(&self, from: IVec2, to: IVec2) -> impl Iterator<Item = (IVec2, &T)> {
+        bresenham_line(from, to)
+            .into_iter()
+            .filter_map(move |i| self.get_direct(i).map(|t| (i, t)))
+    }
+
+    /// Collect all tiles that are reachable from `start` through tiles satisfying
+    /// `predicate`. `start` itself should also satisfy `predicate`, otherwise the
+    /// result is empty.
+    pub fn flood_fill(
+        &self,
+        start: IVec2,
+        neighbourhood: TileNeighbourhood,
+        predicate: impl Fn(IVec2, &T) -> bool,
+    ) -> HashSet<IVec2> {
+        let mut visited = HashSet::new();
+        if !self.get_direct(start).is_some_and(|t| predicate(start, t)) {
+            return visited;
+        }
+
+        let mut queue = VecDeque::from([start]);
+        visited.insert(start);
+
+        while let Some(cur) = queue.pop_front() {
+            for (n, t) in self.neighbours(cur, neighbourhood) {
+                if !visited.contains(&n) && predicate(n, t) {
+                    visited.insert(n);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        visited
+    }
+
+    /// Label all existing tiles, two adjacent tiles belong to the same component if
+    /// `connected` returns true for them.
+    pub fn connected_components(
+        &self,
+        neighbourhood: TileNeighbourhood,
+        connected: impl Fn(&T, &T) -> bool,
+    ) -> TileComponents {
+        let mut result = TileComponents::default();
+        let mut queue = VecDeque::new();
+
+        for (start, _) in self.iter_direct() {
+            if result.labels.contains_key(&start) {
+                continue;
+            }
+
+            let label = result.components.len() as u32;
+            let mut component = vec![start];
+            result.labels.insert(start, label);
+            queue.push_back(start);
+
+            while let Some(cur) = queue.pop_front() {
+                let cur_item = self.get_direct(cur).unwrap();
+                for (n, t) in self.neighbours(cur, neighbourhood) {
+                    if !result.labels.contains_key(&n) && connected(cur_item, t) {
+                        result.labels.insert(n, label);
+                        component.push(n);
+                        queue.push_back(n);
+                    }
+                }
+            }
+
+            result.components.push(component);
+        }
+
+        result
+    }
+}
@@ -149,7 +149,12 @@ fn update_tile_mesh(
         .entry(index.in_chunk)
         .or_insert_with(|| TilemapRenderChunk::new(chunks.chunk_size));
 
-    let data = tile.visible.then_some(TileMeshData {
+    chunk.set(index.in_chunk_at, tile_mesh_data(tile));
+}
+
+/// What the tile looks like in the mesh. Invisible tiles are not rendered.
+pub fn tile_mesh_data(tile: &Tile) -> Option<TileMeshData> {
+    tile.visible.then_some(TileMeshData {
         tint: tile.tint.to_linear().to_vec4(),
         atlas_index: match tile.atlas_index {
             TileAtlasIndex::Static(mut s) => {
@@ -162,9 +167,72 @@ fn update_tile_mesh(
             } => [anim.start as u32, anim.len as u32, 1, offset_milisec],
         },
         tile_index: tile.index.direct(),
-    });
+    })
+}
+
+/// Vertex attributes and indices of a chunk. Each tile is a quad with 4
+/// vertices, whose actual positions are calculated in the shader by the
+/// vertex index and the tile index.
+#[derive(Debug, Default, Clone)]
+pub struct ChunkMeshBuffers {
+    pub positions: Vec<Vec3>,
+    pub colors: Vec<Vec4>,
+    pub atlas_indices: Vec<[u32; 4]>,
+    pub tile_indices: Vec<IVec2>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMeshBuffers {
+    pub fn new(tiles: &[Option<TileMeshData>]) -> Self {
+        let n = tiles.iter().flatten().count();
+        let mut buffers = Self {
+            positions: Vec::with_capacity(n * 4),
+            colors: Vec::with_capacity(n * 4),
+            atlas_indices: Vec::with_capacity(n * 4),
+            tile_indices: Vec::with_capacity(n * 4),
+            indices: Vec::with_capacity(n * 6),
+        };
+
+        for (i_tile, tile) in tiles.iter().flatten().enumerate() {
+            buffers.positions.extend_from_slice(&[Vec3::ZERO; 4]);
+            buffers.colors.extend_from_slice(&[tile.tint; 4]);
+            buffers
+                .atlas_indices
+                .extend_from_slice(&[tile.atlas_index; 4]);
+            buffers
+                .tile_indices
+                .extend_from_slice(&[tile.tile_index; 4]);
+            buffers.indices.extend(quad_indices(i_tile as u32));
+        }
+
+        buffers
+    }
 
-    chunk.set(index.in_chunk_at, data);
+    #[inline]
+    pub fn num_tiles(&self) -> usize {
+        self.positions.len() / 4
+    }
+
+    pub fn into_mesh(self) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors)
+        .with_inserted_attribute(TILEMAP_MESH_ATLAS_INDEX_ATTR, self.atlas_indices)
+        .with_inserted_attribute(TILEMAP_MESH_TILE_INDEX_ATTR, self.tile_indices)
+        .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
+
+/// Indices of the 2 triangles of the `i_tile`th quad. The shader takes
+/// `vertex_index % 4` as the corner, so vertices of a quad must be consecutive
+/// and start at a multiple of 4.
+#[inline]
+pub fn quad_indices(i_tile: u32) -> [u32; 6] {
+    let base = i_tile * 4;
+    [base, base + 1, base + 3, base + 1, base + 2, base + 3]
 }
 
 pub fn prepare_tilemap_meshes(
@@ -177,66 +245,7 @@ pub fn prepare_tilemap_meshes(
             .par_iter_mut()
             .filter(|(_, c)| c.is_dirty)
             .for_each(|(index, chunk)| {
-                let n = chunk.tiles.len();
-                let mut vertex_indices = Vec::with_capacity(n * 6);
-
-                let mut vertex_position = Vec::with_capacity(n * 4);
-                let mut atlas_indices = Vec::with_capacity(n * 4);
-                let mut vertex_color = Vec::with_capacity(n * 4);
-                let mut tile_indices = Vec::with_capacity(n * 4);
-
-                // TODO if not adding 6 vertices, the rectangle mesh cannot be built
-                //      looks like the 3 at the back cannot be recognized.
-                //      maybe try again in next version of bevy.
-                for (i_tile, tile) in chunk.tiles.iter().filter_map(|t| t.as_ref()).enumerate() {
-                    vertex_position.extend_from_slice(&[
-                        Vec3::ZERO,
-                        Vec3::ZERO,
-                        Vec3::ZERO,
-                        Vec3::ZERO,
-                        Vec3::ZERO,
-                        Vec3::ZERO,
-                    ]);
-                    atlas_indices.extend_from_slice(&[
-                        tile.atlas_index,
-                        tile.atlas_index,
-                        tile.atlas_index,
-                        tile.atlas_index,
-                        tile.atlas_index,
-                        tile.atlas_index,
-                    ]);
-                    vertex_color.extend_from_slice(&[
-                        tile.tint, tile.tint, tile.tint, tile.tint, tile.tint, tile.tint,
-                    ]);
-                    tile_indices.extend_from_slice(&[
-                        tile.tile_index,
-                        tile.tile_index,
-                        tile.tile_index,
-                        tile.tile_index,
-                        tile.tile_index,
-                        tile.tile_index,
-                    ]);
-
-                    let base_index = i_tile as u32 * 6;
-                    vertex_indices.extend_from_slice(&[
-                        base_index,
-                        base_index + 1,
-                        base_index + 3,
-                        base_index + 1,
-                        base_index + 2,
-                        base_index + 3,
-                    ]);
-                }
-
-                let mesh = Mesh::new(
-                    PrimitiveTopology::TriangleList,
-                    RenderAssetUsages::RENDER_WORLD,
-                )
-                .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertex_position)
-                .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, vertex_color)
-                .with_inserted_attribute(TILEMAP_MESH_ATLAS_INDEX_ATTR, atlas_indices)
-                .with_inserted_attribute(TILEMAP_MESH_TILE_INDEX_ATTR, tile_indices)
-                .with_inserted_indices(Indices::U32(vertex_indices));
+                let mesh = ChunkMeshBuffers::new(&chunk.tiles).into_mesh();
 
                 let vertex_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
                     label: Some(&format!("tilemap_chunk_mesh_{}_{}", tilemap, index)),
@@ -244,6 +253,7 @@ pub fn prepare_tilemap_meshes(
                     usage: BufferUsages::VERTEX,
                 });
                 let vertex_count = mesh.count_vertices() as u32;
+                let index_count = mesh.indices().map_or(0, |i| i.len()) as u32;
                 let buffer_info =
                     mesh.get_index_buffer_bytes()
                         .map_or(GpuBufferInfo::NonIndexed, |data| GpuBufferInfo::Indexed {
@@ -252,7 +262,7 @@ pub fn prepare_tilemap_meshes(
                                 contents: data,
                                 usage: BufferUsages::INDEX,
                             }),
-                            count: vertex_count,
+                            count: index_count,
                             index_format: IndexFormat::Uint32,
                         });
 
@@ -277,3 +287,72 @@ pub fn prepare_tilemap_meshes(
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, Vec4};
+
+    use crate::map::{
+        render::mesh::{quad_indices, tile_mesh_data, ChunkMeshBuffers, TileMeshData},
+        tilemap::Tile,
+    };
+
+    fn mesh_data(x: i32) -> Option<TileMeshData> {
+        Some(TileMeshData {
+            tint: Vec4::ONE,
+            atlas_index: [0, x as u32, 0, 0],
+            tile_index: IVec2::new(x, 0),
+        })
+    }
+
+    #[test]
+    fn quad_layout() {
+        assert_eq!(quad_indices(0), [0, 1, 3, 1, 2, 3]);
+        assert_eq!(quad_indices(2), [8, 9, 11, 9, 10, 11]);
+    }
+
+    #[test]
+    fn chunk_with_holes() {
+        let tiles = [mesh_data(0), None, mesh_data(2), None, None, mesh_data(5)];
+        let buffers = ChunkMeshBuffers::new(&tiles);
+
+        assert_eq!(buffers.num_tiles(), 3);
+        assert_eq!(buffers.positions.len(), 12);
+        assert_eq!(buffers.colors.len(), 12);
+        assert_eq!(buffers.atlas_indices.len(), 12);
+        assert_eq!(buffers.tile_indices.len(), 12);
+        assert_eq!(buffers.indices.len(), 18);
+
+        for (i_quad, (quad, x)) in buffers.indices.chunks(6).zip([0, 2, 5]).enumerate() {
+            let base = *quad.iter().min().unwrap();
+            assert_eq!(base % 4, 0);
+            assert_eq!(base, i_quad as u32 * 4);
+            assert!(quad.iter().all(|i| (base..base + 4).contains(i)));
+
+            let vertices = base as usize..base as usize + 4;
+            assert!(buffers.tile_indices[vertices.clone()]
+                .iter()
+                .all(|i| *i == IVec2::new(x, 0)));
+            assert!(buffers.atlas_indices[vertices]
+                .iter()
+                .all(|a| *a == [0, x as u32, 0, 0]));
+        }
+    }
+
+    #[test]
+    fn empty_chunk() {
+        let buffers = ChunkMeshBuffers::new(&[None, None]);
+        assert_eq!(buffers.num_tiles(), 0);
+        assert!(buffers.indices.is_empty());
+    }
+
+    #[test]
+    fn invisible_tile() {
+        let tile = Tile {
+            visible: false,
+            ..Default::default()
+        };
+        assert!(tile_mesh_data(&tile).is_none());
+        assert!(tile_mesh_data(&Tile::default()).is_some());
+    }
+}
@@ -1,5 +1,5 @@
 use bevy::{
-    app::{App, Plugin, PreUpdate, Update},
+    app::{App, Plugin, Update},
     color::LinearRgba,
     core_pipeline::core_2d::Transparent2d,
     ecs::{
@@ -15,7 +15,7 @@ use bevy::{
         With,
     },
     render::{
-        extract_instances::{ExtractInstance, ExtractInstancesPlugin},
+        extract_instances::{ExtractInstance, ExtractInstancesPlugin, ExtractedInstances},
         mesh::GpuBufferInfo,
         render_phase::{
             AddRenderCommand, DrawFunctions, PhaseItemExtraIndex, RenderCommand,
@@ -31,6 +31,7 @@ use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
     map::{
+        layer::TilemapLayer,
         render::{
             mesh::TilemapMeshStorage,
             resource::{TilemapBindGroups, TilemapBuffers, TilemapPipeline, TilemapPipelineKey},
@@ -54,17 +55,7 @@ pub(super) struct TilemapRenderPlugin;
 impl Plugin for TilemapRenderPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(ExtractInstancesPlugin::<ExtractedTilemap>::new())
-            .add_systems(Update, texture::change_texture_usage)
-            .add_systems(
-                PreUpdate,
-                |mut tilemaps_query: Query<&mut TilemapStorage>| {
-                    tilemaps_query.iter_mut().for_each(|mut t| unsafe {
-                        let cell = t.as_unsafe_cell();
-                        (*cell.changed_tiles).clear();
-                        (*cell.changed_chunks).clear();
-                    });
-                },
-            );
+            .add_systems(Update, texture::change_texture_usage);
 
         let render_app = app.sub_app_mut(RenderApp);
 
@@ -141,6 +132,7 @@ pub fn extract_visible_tilemap_renderers(
 
 pub struct ExtractedTilemap {
     pub chunk_size: u32,
+    pub z_order: u32,
     pub tile_render_size: TileRenderSize,
     pub transform: GlobalTransform,
     pub tint: LinearRgba,
@@ -158,12 +150,13 @@ impl ExtractInstance for ExtractedTilemap {
         Read<TilemapTilesets>,
         Read<TilemapStorage>,
         Ref<'static, TilemapAnimations>,
+        Option<Read<TilemapLayer>>,
     );
 
     type QueryFilter = ();
 
     fn extract(
-        (tile_render_size, transform, tint, tilesets, storage, animations): QueryItem<
+        (tile_render_size, transform, tint, tilesets, storage, animations, layer): QueryItem<
             '_,
             Self::QueryData,
         >,
@@ -174,6 +167,7 @@ impl ExtractInstance for ExtractedTilemap {
             tint: tint.to_linear(),
             tilesets: tilesets.clone(),
             chunk_size: storage.chunk_size(),
+            z_order: layer.copied().unwrap_or_default().z_order,
             changed_animations: if animations.is_changed() {
                 Some(animations.clone())
             } else {
@@ -182,11 +176,11 @@ impl ExtractInstance for ExtractedTilemap {
             changed_tiles: storage
                 .changed_tiles()
                 .par_iter()
-                .map(|t| (*t, storage.flattened_get(*t).cloned()))
+                .map(|(t, _)| (*t, storage.flattened_get(*t).cloned()))
                 .collect(),
             changed_chunks: storage
                 .changed_chunks()
-                .iter()
+                .union(storage.streamed_chunks())
                 .map(|c| (*c, storage.get_chunk(*c).cloned()))
                 .collect(),
         })
@@ -195,6 +189,7 @@ impl ExtractInstance for ExtractedTilemap {
 
 pub fn queue_tilemaps(
     tilemaps_query: Query<Entity, With<TilemapRenderer>>,
+    tilemaps: Res<ExtractedInstances<ExtractedTilemap>>,
     mut render_phases: ResMut<ViewSortedRenderPhases<Transparent2d>>,
     main_view_query: Query<Entity, With<MainCamera>>,
     pipeline: Res<TilemapPipeline>,
@@ -221,8 +216,13 @@ pub fn queue_tilemaps(
         };
 
         for renderer in &tilemaps_query {
+            let Some(tilemap) = tilemaps.get(&renderer) else {
+                continue;
+            };
+
             render_phase.add(Transparent2d {
-                sort_key: FloatOrd(0.),
+                // Layers with larger z-order are drawn later.
+                sort_key: FloatOrd(tilemap.z_order as f32),
                 entity: renderer,
                 pipeline,
                 draw_function,
@@ -121,6 +121,8 @@ pub struct TilemapUniform {
 #[derive(ShaderType)]
 pub struct TilemapRenderTextureDescriptor {
     pub tile_count: UVec2,
+    /// Size of a tile in uv, relative to the texture array.
+    pub tile_uv_size: Vec2,
 }
 
 pub struct TilemapIndividualRenderData {
@@ -173,7 +175,8 @@ pub fn prepare_buffers(
             individual
                 .texture_desc
                 .push(TilemapRenderTextureDescriptor {
-                    tile_count: t.desc.size / t.desc.tile_size,
+                    tile_count: t.desc.tile_count(),
+                    tile_uv_size: t.desc.tile_size.as_vec2() / tilemap.tilesets.size().as_vec2(),
                 });
         });
         individual
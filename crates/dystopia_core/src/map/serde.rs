@@ -14,13 +14,16 @@ use bevy::{
         LoadContext,
     },
     color::{ColorToComponents, LinearRgba},
-    log::{error, info},
+    ecs::world::EntityRef,
+    log::{error, info, warn},
     math::IVec2,
     prelude::{
-        in_state, Commands, Entity, IntoSystemConfigs, OnInsert, Query, Res, ResMut, Trigger,
+        in_state, Commands, DespawnRecursiveExt, Entity, IntoSystemConfigs, OnInsert, Query, Res,
+        ResMut, Trigger,
     },
     reflect::TypePath,
     render::render_resource::FilterMode,
+    utils::HashMap,
 };
 use bincode::{
     config::Configuration,
@@ -28,25 +31,35 @@ use bincode::{
     Decode, Encode,
 };
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
     cosmos::celestial::{BodyIndex, BodyTilemap, ToLoadTilemap, ToSaveTilemap},
     map::{
+        animation::TileAnimationRegistry,
         bundle::TilemapBundle,
+        data::TileDataSerializers,
+        layer::{spawn_layered_tilemap, TilemapLayer, TilemapLayers},
+        stream::{
+            chunk_record_path, chunk_records_dir, merge_chunk, read_chunk_record, TilemapStreaming,
+        },
         tilemap::{
-            FlattenedTileIndex, Tile, TileAnimation, TileAtlasIndex, TileFlip, TileIndex,
-            TileRenderSize, TileStaticAtlas, TilemapAnimations, TilemapStorage, TilemapTexture,
-            TilemapTextureDescriptor, TilemapTilesets, TilemapTint,
+            FlattenedTileIndex, Tile, TileAnimation, TileAnimationMode, TileAtlasIndex, TileFlip,
+            TileIndex, TileRenderSize, TileStaticAtlas, TilemapAnimations, TilemapStorage,
+            TilemapTexture, TilemapTextureDescriptor, TilemapTilesets, TilemapTint,
         },
+        tileset::TilesetRegistry,
     },
     schedule::state::GameState,
     sim::SaveName,
-    util::chunking::ChunkedStorage,
+    util::chunking::{Chunk, ChunkedStorage},
 };
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const ENCDEC_CONFIG: Configuration = bincode::config::standard();
+/// Version of the [`BinaryTilemap`] layout. Saves made before tilemaps had
+/// layers store the major crate version, which is `0`, in the same field.
+const FORMAT_VERSION: u32 = 1;
+pub(super) const ENCDEC_CONFIG: Configuration = bincode::config::standard();
 
 pub(super) struct TilemapSerdePlugin;
 
@@ -58,11 +71,76 @@ impl Plugin for TilemapSerdePlugin {
             .register_asset_processor::<LoadAndSave<BinaryTilemapLoader, BinaryTilemapSaver>>(
                 BinaryTilemapSaver.into(),
             )
+            .init_asset_loader::<TextTilemapLoader>()
+            .register_asset_processor::<LoadAndSave<TextTilemapLoader, TextTilemapSaver>>(
+                TextTilemapSaver.into(),
+            )
             .observe(save_tilemap);
     }
 }
 
-#[derive(Encode, Decode)]
+/// Formats of tilemap files, told apart by their extensions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TilemapFormat {
+    /// `.tmb`, compact but opaque.
+    #[default]
+    Binary,
+    /// `.tm.json`, for reading and diffing. See [`BinaryTilemap::to_text`].
+    Text,
+}
+
+impl TilemapFormat {
+    pub const ALL: [Self; 2] = [Self::Binary, Self::Text];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TilemapFormat::Binary => "tmb",
+            TilemapFormat::Text => "tm.json",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy();
+        Self::ALL
+            .into_iter()
+            .find(|f| name.ends_with(&format!(".{}", f.extension())))
+    }
+
+    pub fn encode(self, tilemap: &BinaryTilemap) -> Result<Vec<u8>, TilemapFormatError> {
+        match self {
+            TilemapFormat::Binary => tilemap
+                .to_binary()
+                .map_err(|e| TilemapFormatError::Encode(e)),
+            TilemapFormat::Text => tilemap
+                .to_text()
+                .map(String::into_bytes)
+                .map_err(|e| TilemapFormatError::Json(e)),
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<BinaryTilemap, TilemapFormatError> {
+        match self {
+            TilemapFormat::Binary => {
+                BinaryTilemap::from_binary(bytes).map_err(|e| TilemapFormatError::Decode(e))
+            }
+            TilemapFormat::Text => {
+                BinaryTilemap::from_text(bytes).map_err(|e| TilemapFormatError::Json(e))
+            }
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TilemapFormatError {
+    #[error("Encode error: {0:?}")]
+    Encode(EncodeError),
+    #[error("Decode error: {0:?}")]
+    Decode(DecodeError),
+    #[error("Json error: {0:?}")]
+    Json(serde_json::Error),
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize)]
 enum BinaryAtlasIndex {
     Static {
         texture: u32,
@@ -70,20 +148,41 @@ enum BinaryAtlasIndex {
         flip: u32,
     },
     Animated {
-        start: usize,
-        len: usize,
+        /// Index in [`BinaryTilemapLayer::animations`].
+        animation: u32,
         offset_milisec: u32,
     },
 }
 
-#[derive(Encode, Decode)]
+#[derive(Encode, Decode, Serialize, Deserialize)]
+struct BinaryAnimation {
+    /// Named animations are looked up in [`TileAnimationRegistry`] first when
+    /// loading, so changes in config are applied to existing saves.
+    name: Option<String>,
+    fps: u32,
+    mode: u32,
+    /// `(texture, atlas, flip)`
+    frames: Vec<(u32, u32, u32)>,
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize)]
 struct BinaryTilesets {
     size: [u32; 2],
     filter_mode: u32,
-    textures: Vec<(String, [u32; 2], [u32; 2])>,
+    textures: Vec<BinaryTexture>,
 }
 
-#[derive(Encode, Decode)]
+#[derive(Encode, Decode, Serialize, Deserialize)]
+struct BinaryTexture {
+    /// Id in [`TilesetRegistry`].
+    id: Option<String>,
+    /// Asset path, only for textures without id.
+    path: Option<String>,
+    size: [u32; 2],
+    tile_size: [u32; 2],
+}
+
+#[derive(Encode, Decode, Serialize, Deserialize)]
 struct BinaryTile {
     indices: ([i32; 2], ([i32; 2], usize)),
     atlas: BinaryAtlasIndex,
@@ -91,19 +190,181 @@ struct BinaryTile {
     visible: bool,
 }
 
+#[derive(Encode, Decode, Serialize, Deserialize)]
+struct BinaryTilemapLayer {
+    z_order: u32,
+    chunk_size: u32,
+    storgae: Vec<([i32; 2], Vec<Option<BinaryTile>>)>,
+    tint: [f32; 4],
+    tilesets: BinaryTilesets,
+    animations: Vec<BinaryAnimation>,
+}
+
 // TODO replace `[number; dimension]`s with glam vectors.
-#[derive(Encode, Decode, Asset, TypePath)]
+#[derive(Encode, Decode, Serialize, Deserialize, Asset, TypePath)]
 pub struct BinaryTilemap {
     version: u32,
     target_body: usize,
     tile_render_size: [f32; 2],
+    /// The first one is the root layer, and the rest are sorted by z-order.
+    layers: Vec<BinaryTilemapLayer>,
+    /// Encoded [`SerializableTileData`](crate::map::data::SerializableTileData)s
+    /// of the root layer, keyed by their `KEY`.
+    data: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Decode)]
+enum LegacyAtlasIndex {
+    Static {
+        texture: u32,
+        atlas: u32,
+        flip: u32,
+    },
+    Animated {
+        start: usize,
+        len: usize,
+        offset_milisec: u32,
+    },
+}
+
+#[derive(Decode)]
+struct LegacyTile {
+    indices: ([i32; 2], ([i32; 2], usize)),
+    atlas: LegacyAtlasIndex,
+    tint: [f32; 4],
+    visible: bool,
+}
+
+#[derive(Decode)]
+struct LegacyTilesets {
+    size: [u32; 2],
+    filter_mode: u32,
+    /// `(path, size, tile_size)`
+    textures: Vec<(String, [u32; 2], [u32; 2])>,
+}
+
+/// Layout of version `0`, a single layer without tile data.
+#[derive(Decode)]
+struct LegacyBinaryTilemap {
+    _version: u32,
+    target_body: usize,
+    tile_render_size: [f32; 2],
     chunk_size: u32,
-    storgae: Vec<([i32; 2], Vec<Option<BinaryTile>>)>,
+    storgae: Vec<([i32; 2], Vec<Option<LegacyTile>>)>,
     tint: [f32; 4],
-    tilesets: BinaryTilesets,
+    tilesets: LegacyTilesets,
+    /// Layout: `[dummy, fps, frame_1_tex, frame_1_atl, ..., fps, frame_1_tex, ...]`
     animations: Vec<u32>,
 }
 
+impl From<LegacyBinaryTilemap> for BinaryTilemap {
+    fn from(legacy: LegacyBinaryTilemap) -> Self {
+        let buffer = legacy.animations;
+        let mut animations = Vec::new();
+        let mut animation_ids = HashMap::<(usize, usize), u32>::new();
+
+        let storgae = legacy
+            .storgae
+            .into_iter()
+            .map(|(ci, chunk)| {
+                let chunk = chunk
+                    .into_iter()
+                    .map(|t| {
+                        t.map(|t| BinaryTile {
+                            indices: t.indices,
+                            atlas: match t.atlas {
+                                LegacyAtlasIndex::Static {
+                                    texture,
+                                    atlas,
+                                    flip,
+                                } => BinaryAtlasIndex::Static {
+                                    texture,
+                                    atlas,
+                                    flip,
+                                },
+                                LegacyAtlasIndex::Animated {
+                                    start,
+                                    len,
+                                    offset_milisec,
+                                } => BinaryAtlasIndex::Animated {
+                                    animation: *animation_ids.entry((start, len)).or_insert_with(
+                                        || {
+                                            animations
+                                                .push(decode_legacy_animation(&buffer, start, len));
+                                            animations.len() as u32 - 1
+                                        },
+                                    ),
+                                    offset_milisec,
+                                },
+                            },
+                            tint: t.tint,
+                            visible: t.visible,
+                        })
+                    })
+                    .collect();
+                (ci, chunk)
+            })
+            .collect();
+
+        Self {
+            version: FORMAT_VERSION,
+            target_body: legacy.target_body,
+            tile_render_size: legacy.tile_render_size,
+            layers: vec![BinaryTilemapLayer {
+                z_order: TilemapLayer::GROUND.z_order,
+                chunk_size: legacy.chunk_size,
+                storgae,
+                tint: legacy.tint,
+                tilesets: BinaryTilesets {
+                    size: legacy.tilesets.size,
+                    filter_mode: legacy.tilesets.filter_mode,
+                    textures: legacy
+                        .tilesets
+                        .textures
+                        .into_iter()
+                        .map(|(path, size, tile_size)| BinaryTexture {
+                            id: None,
+                            path: Some(path),
+                            size,
+                            tile_size,
+                        })
+                        .collect(),
+                },
+                animations,
+            }],
+            data: Vec::new(),
+        }
+    }
+}
+
+/// Legacy animations are identified by the position of their first frame, and
+/// are always looping.
+fn decode_legacy_animation(buffer: &[u32], start: usize, len: usize) -> BinaryAnimation {
+    BinaryAnimation {
+        name: None,
+        fps: start
+            .checked_sub(1)
+            .and_then(|i| buffer.get(i))
+            .copied()
+            .unwrap_or_default(),
+        mode: TileAnimationMode::Loop as u32,
+        frames: buffer
+            .get(start..start + len * 2)
+            .unwrap_or_default()
+            .chunks_exact(2)
+            .map(|f| {
+                let frame = TileStaticAtlas {
+                    texture: f[0],
+                    atlas: f[1],
+                    flip: TileFlip::NONE,
+                }
+                .decode();
+                (frame.texture, frame.atlas, frame.flip.bits())
+            })
+            .collect(),
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum TilemapBinaryLoadError {
     #[error("Io error: {0:?}")]
@@ -133,9 +394,7 @@ impl AssetLoader for BinaryTilemapLoader {
             .read_to_end(&mut buf)
             .await
             .map_err(|e| TilemapBinaryLoadError::Io(e))?;
-        bincode::decode_from_slice(&buf, ENCDEC_CONFIG)
-            .map(|r| r.0)
-            .map_err(|e| TilemapBinaryLoadError::Decode(e))
+        BinaryTilemap::from_binary(&buf).map_err(|e| TilemapBinaryLoadError::Decode(e))
     }
 
     fn extensions(&self) -> &[&str] {
@@ -181,17 +440,221 @@ impl AssetSaver for BinaryTilemapSaver {
     }
 }
 
+impl BinaryTilemap {
+    /// Saves in the legacy layout are migrated.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, DecodeError> {
+        // `version` is always the first field.
+        let (version, _) = bincode::decode_from_slice::<u32, _>(bytes, ENCDEC_CONFIG)?;
+        match version {
+            0 => bincode::decode_from_slice::<LegacyBinaryTilemap, _>(bytes, ENCDEC_CONFIG)
+                .map(|r| r.0.into()),
+            FORMAT_VERSION => bincode::decode_from_slice(bytes, ENCDEC_CONFIG).map(|r| r.0),
+            _ => Err(DecodeError::OtherString(format!(
+                "Unsupported tilemap format version {}, the latest is {}.",
+                version, FORMAT_VERSION
+            ))),
+        }
+    }
+
+    pub fn to_binary(&self) -> Result<Vec<u8>, EncodeError> {
+        bincode::encode_to_vec(self, ENCDEC_CONFIG)
+    }
+
+    pub fn from_text(text: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(text)
+    }
+
+    /// Pretty printed, so it can be read and diffed.
+    pub fn to_text(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn stats(&self) -> TilemapStats {
+        TilemapStats {
+            version: self.version,
+            target_body: self.target_body,
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| {
+                    let tiles = layer.storgae.iter().flat_map(|(_, c)| c.iter().flatten());
+                    TilemapLayerStats {
+                        z_order: layer.z_order,
+                        chunk_size: layer.chunk_size,
+                        chunks: layer.storgae.len(),
+                        tiles: tiles.clone().count(),
+                        animated_tiles: tiles
+                            .clone()
+                            .filter(|t| matches!(t.atlas, BinaryAtlasIndex::Animated { .. }))
+                            .count(),
+                        animations: layer.animations.len(),
+                        textures: layer
+                            .tilesets
+                            .textures
+                            .iter()
+                            .map(|t| match (&t.id, &t.path) {
+                                (Some(id), _) => id.clone(),
+                                (None, Some(path)) => path.clone(),
+                                (None, None) => "<placeholder>".to_string(),
+                            })
+                            .collect(),
+                    }
+                })
+                .collect(),
+            data: self
+                .data
+                .iter()
+                .map(|(k, d)| (k.clone(), d.len()))
+                .collect(),
+        }
+    }
+}
+
+/// Summary of a [`BinaryTilemap`], for debugging.
+#[derive(Debug, Clone)]
+pub struct TilemapStats {
+    pub version: u32,
+    pub target_body: usize,
+    pub layers: Vec<TilemapLayerStats>,
+    /// Keys and sizes in bytes of tile data.
+    pub data: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TilemapLayerStats {
+    pub z_order: u32,
+    pub chunk_size: u32,
+    pub chunks: usize,
+    pub tiles: usize,
+    pub animated_tiles: usize,
+    /// Registered animations, including unused ones.
+    pub animations: usize,
+    pub textures: Vec<String>,
+}
+
+impl std::fmt::Display for TilemapStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "target body: {}", self.target_body)?;
+        for layer in &self.layers {
+            writeln!(f, "layer {}:", layer.z_order)?;
+            writeln!(f, "    chunk size: {}", layer.chunk_size)?;
+            writeln!(f, "    chunks: {}", layer.chunks)?;
+            writeln!(
+                f,
+                "    tiles: {} ({} animated)",
+                layer.tiles, layer.animated_tiles
+            )?;
+            writeln!(f, "    animations: {}", layer.animations)?;
+            writeln!(f, "    tilesets: {}", layer.textures.len())?;
+            for texture in &layer.textures {
+                writeln!(f, "        {}", texture)?;
+            }
+        }
+        for (key, len) in &self.data {
+            writeln!(f, "data {}: {} bytes", key, len)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TilemapTextLoadError {
+    #[error("Io error: {0:?}")]
+    Io(std::io::Error),
+    #[error("Json error: {0:?}")]
+    Json(serde_json::Error),
+}
+
+/// Loads tilemaps in the human-readable json format. See [`BinaryTilemap::to_text`].
+#[derive(Default)]
+pub struct TextTilemapLoader;
+
+impl AssetLoader for TextTilemapLoader {
+    type Asset = BinaryTilemap;
+
+    type Settings = ();
+
+    type Error = TilemapTextLoadError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut buf = Vec::new();
+        reader
+            .read_to_end(&mut buf)
+            .await
+            .map_err(|e| TilemapTextLoadError::Io(e))?;
+        BinaryTilemap::from_text(&buf).map_err(|e| TilemapTextLoadError::Json(e))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tm.json"]
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TilemapTextSaveError {
+    #[error("Io error: {0:?}")]
+    Io(std::io::Error),
+    #[error("Json error: {0:?}")]
+    Json(serde_json::Error),
+}
+
+#[derive(Default)]
+pub struct TextTilemapSaver;
+
+impl AssetSaver for TextTilemapSaver {
+    type Asset = BinaryTilemap;
+
+    type Settings = ();
+
+    type OutputLoader = TextTilemapLoader;
+
+    type Error = TilemapTextSaveError;
+
+    async fn save<'a>(
+        &'a self,
+        writer: &'a mut Writer,
+        asset: SavedAsset<'a, Self::Asset>,
+        _settings: &'a Self::Settings,
+    ) -> Result<<Self::OutputLoader as AssetLoader>::Settings, Self::Error> {
+        writer
+            .write_all(
+                asset
+                    .get()
+                    .to_text()
+                    .map_err(|e| TilemapTextSaveError::Json(e))?
+                    .as_bytes(),
+            )
+            .await
+            .map_err(|e| TilemapTextSaveError::Io(e))?;
+
+        Ok(())
+    }
+}
+
 fn save_tilemap(
     trigger: Trigger<OnInsert, ToSaveTilemap>,
     mut commands: Commands,
     to_save_query: Query<(Entity, &BodyIndex, &ToSaveTilemap, Option<&BodyTilemap>)>,
-    tilemaps_query: Query<(
+    roots_query: Query<(
         &TileRenderSize,
+        Option<&TilemapLayers>,
+        Option<&TilemapStreaming>,
+    )>,
+    layers_query: Query<(
+        &TilemapLayer,
         &TilemapStorage,
         &TilemapTint,
         &TilemapTilesets,
         &TilemapAnimations,
     )>,
+    entities_query: Query<EntityRef>,
+    tile_data_serializers: Res<TileDataSerializers>,
     asset_server: Res<AssetServer>,
     save_name: Res<SaveName>,
 ) {
@@ -206,94 +669,79 @@ fn save_tilemap(
         return;
     };
 
-    let Ok((tile_render_size, storage, tint, tilesets, animations)) =
-        tilemaps_query.get(**body_tilemap)
-    else {
+    let Ok((tile_render_size, layers, streaming)) = roots_query.get(**body_tilemap) else {
         return;
     };
 
     commands.entity(body_entity).remove::<ToSaveTilemap>();
 
+    let root = **body_tilemap;
+    let layers = match layers {
+        Some(layers) => std::iter::once(root)
+            .chain(layers.entities().filter(|e| *e != root))
+            .collect::<Vec<_>>(),
+        None => vec![root],
+    };
+
     let binary = BinaryTilemap {
-        version: VERSION.split('.').nth(0).unwrap().parse().unwrap(),
+        version: FORMAT_VERSION,
         target_body: **body_index,
         tile_render_size: tile_render_size.to_array(),
-        chunk_size: storage.chunk_size(),
-        storgae: unsafe {
-            (*storage.as_unsafe_cell_readonly().internal)
-                .par_iter()
-                .map(|(ci, c)| {
-                    (
-                        ci.to_array(),
-                        c.par_iter()
-                            .map(|t| {
-                                t.as_ref().map(|t| BinaryTile {
-                                    indices: (
-                                        t.index.direct().to_array(),
-                                        (
-                                            t.index.flattened().in_chunk.to_array(),
-                                            t.index.flattened().in_chunk_at,
-                                        ),
-                                    ),
-                                    atlas: match t.atlas_index {
-                                        TileAtlasIndex::Static(a) => BinaryAtlasIndex::Static {
-                                            texture: a.texture,
-                                            atlas: a.atlas,
-                                            flip: a.flip.bits(),
-                                        },
-                                        TileAtlasIndex::Animated {
-                                            anim,
-                                            offset_milisec,
-                                        } => BinaryAtlasIndex::Animated {
-                                            start: anim.start,
-                                            len: anim.len,
-                                            offset_milisec,
-                                        },
-                                    },
-                                    tint: t.tint.to_linear().to_f32_array(),
-                                    visible: t.visible,
-                                })
-                            })
-                            .collect(),
-                    )
-                })
-                .collect()
-        },
-        tint: tint.to_linear().to_f32_array(),
-        tilesets: BinaryTilesets {
-            size: tilesets.size().to_array(),
-            filter_mode: tilesets.filter_mode() as u32,
-            textures: tilesets
-                .textures()
-                .iter()
-                .map(|tex| {
-                    (
-                        asset_server.get_path(&tex.handle).unwrap().to_string(),
-                        tex.desc.size.to_array(),
-                        tex.desc.tile_size.to_array(),
-                    )
-                })
-                .collect(),
-        },
-        animations: animations.bytes().clone(),
+        layers: layers_query
+            .iter_many(layers)
+            .map(|(layer, storage, tint, tilesets, animations)| {
+                let mut binary =
+                    encode_layer(layer, storage, tint, tilesets, animations, &asset_server);
+                if let Some(streaming) = streaming {
+                    encode_unloaded_chunks(
+                        &mut binary,
+                        *layer,
+                        storage,
+                        animations,
+                        streaming,
+                        &save_name,
+                        **body_index,
+                    );
+                }
+                binary
+            })
+            .collect(),
+        data: entities_query
+            .get(root)
+            .map(|e| tile_data_serializers.encode_all(&e))
+            .unwrap_or_default(),
     };
 
-    match bincode::encode_to_vec(binary, ENCDEC_CONFIG) {
+    let format = save_options.format;
+    match format.encode(&binary) {
         Ok(data) => {
             let path = Path::new(&std::env::var("PROGRAM_ROOT").unwrap())
                 .join("assets")
-                .join("data")
-                .join("saves")
-                .join(&***save_name)
-                .join("maps")
-                .join(format!("{}.tmb", **body_index));
+                .join(construct_tilemap_path(&save_name, **body_index, format));
 
             // TODO move to standard way after issue #11216 get solved
             match write_bytes(&data, &path) {
                 Ok(len) => {
+                    // Otherwise the stale one may be loaded instead.
+                    for other in TilemapFormat::ALL.into_iter().filter(|f| *f != format) {
+                        let _ = std::fs::remove_file(
+                            Path::new(&std::env::var("PROGRAM_ROOT").unwrap())
+                                .join("assets")
+                                .join(construct_tilemap_path(&save_name, **body_index, other)),
+                        );
+                    }
+
                     if save_options.remove_after_done {
                         commands.entity(body_entity).remove::<BodyTilemap>();
-                        commands.entity(**body_tilemap).despawn();
+                        commands.entity(**body_tilemap).despawn_recursive();
+
+                        // Unloaded chunks are all in the save now.
+                        if streaming.is_some() {
+                            let _ = std::fs::remove_dir_all(chunk_records_dir(
+                                &save_name,
+                                **body_index,
+                            ));
+                        }
                     }
 
                     info!(
@@ -316,10 +764,203 @@ fn save_tilemap(
     }
 }
 
-fn write_bytes(bytes: &[u8], path: &Path) -> Result<usize, std::io::Error> {
+fn encode_layer(
+    layer: &TilemapLayer,
+    storage: &TilemapStorage,
+    tint: &TilemapTint,
+    tilesets: &TilemapTilesets,
+    animations: &TilemapAnimations,
+    asset_server: &AssetServer,
+) -> BinaryTilemapLayer {
+    BinaryTilemapLayer {
+        z_order: layer.z_order,
+        chunk_size: storage.chunk_size(),
+        storgae: storage
+            .chunked_storage()
+            .par_iter()
+            .map(|(ci, c)| (ci.to_array(), encode_chunk(c)))
+            .collect(),
+        tint: tint.to_linear().to_f32_array(),
+        tilesets: BinaryTilesets {
+            size: tilesets.size().to_array(),
+            filter_mode: tilesets.filter_mode() as u32,
+            textures: tilesets
+                .textures()
+                .iter()
+                .map(|tex| BinaryTexture {
+                    id: tex.id.clone(),
+                    path: match &tex.id {
+                        Some(_) => None,
+                        None => {
+                            let path = asset_server.get_path(&tex.handle).map(|p| p.to_string());
+                            if path.is_none() {
+                                warn!("Texture without id and path found, it will be a placeholder after loading.");
+                            }
+                            path
+                        }
+                    },
+                    size: tex.desc.size.to_array(),
+                    tile_size: tex.desc.tile_size.to_array(),
+                })
+                .collect(),
+        },
+        animations: animations
+            .entries()
+            .iter()
+            .map(|entry| BinaryAnimation {
+                name: entry.name.clone(),
+                fps: entry.fps,
+                mode: entry.mode as u32,
+                frames: entry
+                    .frames
+                    .iter()
+                    .map(|f| (f.texture, f.atlas, f.flip.bits()))
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+fn encode_chunk(chunk: &Chunk<Tile>) -> Vec<Option<BinaryTile>> {
+    chunk
+        .par_iter()
+        .map(|t| {
+            t.as_ref().map(|t| BinaryTile {
+                indices: (
+                    t.index.direct().to_array(),
+                    (
+                        t.index.flattened().in_chunk.to_array(),
+                        t.index.flattened().in_chunk_at,
+                    ),
+                ),
+                atlas: match t.atlas_index {
+                    TileAtlasIndex::Static(a) => BinaryAtlasIndex::Static {
+                        texture: a.texture,
+                        atlas: a.atlas,
+                        flip: a.flip.bits(),
+                    },
+                    TileAtlasIndex::Animated {
+                        anim,
+                        offset_milisec,
+                    } => BinaryAtlasIndex::Animated {
+                        animation: anim.id(),
+                        offset_milisec,
+                    },
+                },
+                tint: t.tint.to_linear().to_f32_array(),
+                visible: t.visible,
+            })
+        })
+        .collect()
+}
+
+/// `animations` maps ids of animations in the encoded data to the registered
+/// ones. `None` for those failed to register.
+fn decode_chunk(
+    chunk: Vec<Option<BinaryTile>>,
+    animations: &[Option<TileAnimation>],
+) -> Chunk<Tile> {
+    chunk
+        .into_iter()
+        .map(|t| {
+            t.map(|t| Tile {
+                index: TileIndex::new(
+                    t.indices.0.into(),
+                    FlattenedTileIndex {
+                        in_chunk: t.indices.1 .0.into(),
+                        in_chunk_at: t.indices.1 .1,
+                    },
+                ),
+                atlas_index: match t.atlas {
+                    BinaryAtlasIndex::Static {
+                        texture,
+                        atlas,
+                        flip,
+                    } => TileAtlasIndex::Static(TileStaticAtlas {
+                        texture,
+                        atlas,
+                        flip: TileFlip::from_bits(flip).unwrap(),
+                    }),
+                    BinaryAtlasIndex::Animated {
+                        animation,
+                        offset_milisec,
+                    } => match animations.get(animation as usize).copied().flatten() {
+                        Some(anim) => TileAtlasIndex::Animated {
+                            anim,
+                            offset_milisec,
+                        },
+                        None => {
+                            warn!("Animation {} not found, using default atlas.", animation);
+                            TileAtlasIndex::default()
+                        }
+                    },
+                },
+                tint: LinearRgba::from_f32_array(t.tint).into(),
+                visible: t.visible,
+            })
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Encode a single chunk, used by [`stream`](crate::map::stream).
+pub(super) fn encode_chunk_record(chunk: &Chunk<Tile>) -> Result<Vec<u8>, EncodeError> {
+    bincode::encode_to_vec(encode_chunk(chunk), ENCDEC_CONFIG)
+}
+
+pub(super) fn decode_chunk_record(
+    bytes: &[u8],
+    animations: &[Option<TileAnimation>],
+) -> Result<Chunk<Tile>, DecodeError> {
+    bincode::decode_from_slice(bytes, ENCDEC_CONFIG).map(|(c, _)| decode_chunk(c, animations))
+}
+
+/// Put chunks that are streamed onto disk back into the encoded layer.
+fn encode_unloaded_chunks(
+    binary: &mut BinaryTilemapLayer,
+    layer: TilemapLayer,
+    storage: &TilemapStorage,
+    animations: &TilemapAnimations,
+    streaming: &TilemapStreaming,
+    save_name: &str,
+    body_index: usize,
+) {
+    let animations = animations
+        .entries()
+        .iter()
+        .map(|e| Some(e.animation()))
+        .collect::<Vec<_>>();
+    for chunk_index in streaming.unloaded(layer) {
+        let path = chunk_record_path(save_name, body_index, layer, chunk_index);
+        let mut chunk = match read_chunk_record(&path, &animations) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                error!(
+                    "Failed to read chunk {} of body {}: {}",
+                    chunk_index, body_index, err
+                );
+                continue;
+            }
+        };
+
+        if let Some(loaded) = storage.get_chunk(chunk_index) {
+            merge_chunk(&mut chunk, loaded);
+            binary
+                .storgae
+                .retain(|(ci, _)| *ci != chunk_index.to_array());
+        }
+        binary
+            .storgae
+            .push((chunk_index.to_array(), encode_chunk(&chunk)));
+    }
+}
+
+/// Write all `bytes` into `path`, creating parent directories if necessary.
+pub(crate) fn write_bytes(bytes: &[u8], path: &Path) -> Result<usize, std::io::Error> {
     std::fs::create_dir_all(path.parent().unwrap())?;
     let mut file = File::create(path)?;
-    file.write(bytes)
+    file.write_all(bytes)?;
+    Ok(bytes.len())
 }
 
 fn load_tilemap(
@@ -332,12 +973,15 @@ fn load_tilemap(
     )>,
     save_name: Res<SaveName>,
     mut binary_tilemap_assets: ResMut<Assets<BinaryTilemap>>,
+    tile_data_serializers: Res<TileDataSerializers>,
+    tileset_registry: Res<TilesetRegistry>,
+    animation_registry: Res<TileAnimationRegistry>,
     asset_server: Res<AssetServer>,
 ) {
     for (body_entity, body_index, _load_options, binary_tilemap_handle) in &to_load_query {
         if binary_tilemap_handle.is_none() {
-            let path = construct_tmb_path(&save_name, **body_index);
-            if is_tilemap_exist_in_disk(&save_name, **body_index) {
+            // The loader is chosen by the extension.
+            if let Some(path) = find_tilemap_in_disk(&save_name, **body_index) {
                 commands
                     .entity(body_entity)
                     .insert(asset_server.load::<BinaryTilemap>(path));
@@ -356,81 +1000,24 @@ fn load_tilemap(
             return;
         };
 
-        let bundle = TilemapBundle {
-            tile_render_size: TileRenderSize(binary_tilemap.tile_render_size.into()),
-            storgae: TilemapStorage::from(ChunkedStorage::new_init(
-                binary_tilemap.chunk_size,
-                binary_tilemap
-                    .storgae
-                    .into_par_iter()
-                    .map(|(ci, c)| {
-                        (
-                            IVec2::from(ci),
-                            c.into_iter()
-                                .map(|t| {
-                                    t.map(|t| Tile {
-                                        index: TileIndex::new(
-                                            t.indices.0.into(),
-                                            FlattenedTileIndex {
-                                                in_chunk: t.indices.1 .0.into(),
-                                                in_chunk_at: t.indices.1 .1,
-                                            },
-                                        ),
-                                        atlas_index: match t.atlas {
-                                            BinaryAtlasIndex::Static {
-                                                texture,
-                                                atlas,
-                                                flip,
-                                            } => TileAtlasIndex::Static(TileStaticAtlas {
-                                                texture,
-                                                atlas,
-                                                flip: TileFlip::from_bits(flip).unwrap(),
-                                            }),
-                                            BinaryAtlasIndex::Animated {
-                                                start,
-                                                len,
-                                                offset_milisec,
-                                            } => TileAtlasIndex::Animated {
-                                                anim: TileAnimation { start, len },
-                                                offset_milisec,
-                                            },
-                                        },
-                                        tint: LinearRgba::from_f32_array(t.tint).into(),
-                                        visible: t.visible,
-                                    })
-                                })
-                                .collect::<Vec<_>>()
-                                .into(),
-                        )
-                    })
-                    .collect(),
-            )),
-            tilesets: TilemapTilesets {
-                size: binary_tilemap.tilesets.size.into(),
-                filter_mode: match binary_tilemap.tilesets.filter_mode {
-                    0 => FilterMode::Nearest,
-                    1 => FilterMode::Linear,
-                    _ => unreachable!(),
-                },
-                textures: binary_tilemap
-                    .tilesets
-                    .textures
-                    .into_iter()
-                    .map(|(path, size, tile_size)| TilemapTexture {
-                        handle: asset_server.load(path),
-                        desc: TilemapTextureDescriptor {
-                            size: size.into(),
-                            tile_size: tile_size.into(),
-                        },
-                    })
-                    .collect(),
-            },
-            tint: TilemapTint(LinearRgba::from_f32_array(binary_tilemap.tint).into()),
-            animations: binary_tilemap.animations.into(),
-            ..Default::default()
-        };
-
-        let tilemap = commands.spawn(bundle).id();
+        let tile_render_size = TileRenderSize(binary_tilemap.tile_render_size.into());
+        let tilemap = spawn_layered_tilemap(
+            &mut commands,
+            binary_tilemap
+                .layers
+                .into_iter()
+                .map(|layer| {
+                    decode_layer(
+                        layer,
+                        tile_render_size,
+                        &tileset_registry,
+                        &animation_registry,
+                        &asset_server,
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        tile_data_serializers.insert_all(&mut commands.entity(tilemap), binary_tilemap.data);
         commands
             .entity(body_entity)
             .insert(BodyTilemap::new(tilemap))
@@ -438,19 +1025,125 @@ fn load_tilemap(
     }
 }
 
-fn construct_tmb_path(save_name: &str, body_index: usize) -> PathBuf {
+fn decode_layer(
+    layer: BinaryTilemapLayer,
+    tile_render_size: TileRenderSize,
+    tileset_registry: &TilesetRegistry,
+    animation_registry: &TileAnimationRegistry,
+    asset_server: &AssetServer,
+) -> TilemapBundle {
+    let tilesets = TilemapTilesets {
+        size: layer.tilesets.size.into(),
+        filter_mode: match layer.tilesets.filter_mode {
+            0 => FilterMode::Nearest,
+            1 => FilterMode::Linear,
+            _ => unreachable!(),
+        },
+        textures: layer
+            .tilesets
+            .textures
+            .into_iter()
+            .map(|texture| {
+                let desc = TilemapTextureDescriptor {
+                    size: texture.size.into(),
+                    tile_size: texture.tile_size.into(),
+                };
+
+                match (texture.id, texture.path) {
+                    (Some(id), _) => {
+                        tileset_registry.texture_or_placeholder(&id, Some(desc), asset_server)
+                    }
+                    (None, Some(path)) => TilemapTexture {
+                        id: None,
+                        handle: asset_server.load(path),
+                        desc,
+                    },
+                    (None, None) => {
+                        warn!("Texture without id and path found, using placeholder instead.");
+                        tileset_registry.placeholder(Some(desc), asset_server)
+                    }
+                }
+            })
+            .collect(),
+    };
+
+    // Animations are registered again, so the buffer is always built from the
+    // latest definitions, and tiles are remapped to the new ones.
+    let mut animations = TilemapAnimations::default();
+    let animation_mapping = layer
+        .animations
+        .into_iter()
+        .map(|anim| {
+            let mode = TileAnimationMode::from_u32(anim.mode).unwrap_or_default();
+            let frames = anim
+                .frames
+                .into_iter()
+                .map(|(texture, atlas, flip)| TileStaticAtlas {
+                    texture,
+                    atlas,
+                    flip: TileFlip::from_bits_truncate(flip),
+                })
+                .collect::<Vec<_>>();
+
+            let registered = match &anim.name {
+                Some(name) => animation_registry
+                    .register(name, &mut animations, &tilesets)
+                    .or_else(|| animations.register_named(name, frames, anim.fps, mode)),
+                None => animations.register_with_mode(frames, anim.fps, mode),
+            };
+            if registered.is_none() {
+                warn!(
+                    "Animation {} in layer {} has no frames, skipped.",
+                    anim.name.as_deref().unwrap_or("<unnamed>"),
+                    layer.z_order
+                );
+            }
+            registered
+        })
+        .collect::<Vec<_>>();
+
+    TilemapBundle {
+        tile_render_size,
+        storgae: TilemapStorage::from(ChunkedStorage::new_init(
+            layer.chunk_size,
+            layer
+                .storgae
+                .into_par_iter()
+                .map(|(ci, c)| (IVec2::from(ci), decode_chunk(c, &animation_mapping)))
+                .collect(),
+        )),
+        tilesets,
+        tint: TilemapTint(LinearRgba::from_f32_array(layer.tint).into()),
+        animations,
+        layer: TilemapLayer {
+            z_order: layer.z_order,
+        },
+        ..Default::default()
+    }
+}
+
+fn construct_tilemap_path(save_name: &str, body_index: usize, format: TilemapFormat) -> PathBuf {
     Path::new("data")
         .join("saves")
         .join(save_name)
         .join("maps")
-        .join(format!("{}.tmb", body_index))
+        .join(format!("{}.{}", body_index, format.extension()))
 }
 
+/// Path of the tilemap save in any format, relative to the assets folder.
 // TODO use standard detecting way
+fn find_tilemap_in_disk(save_name: &str, body_index: usize) -> Option<PathBuf> {
+    TilemapFormat::ALL
+        .into_iter()
+        .map(|format| construct_tilemap_path(save_name, body_index, format))
+        .find(|path| {
+            Path::new(&std::env::var("PROGRAM_ROOT").unwrap())
+                .join("assets")
+                .join(path)
+                .exists()
+        })
+}
+
 pub fn is_tilemap_exist_in_disk(save_name: &str, body_index: usize) -> bool {
-    let path = construct_tmb_path(save_name, body_index);
-    Path::new(&std::env::var("PROGRAM_ROOT").unwrap())
-        .join("assets")
-        .join(&path)
-        .exists()
+    find_tilemap_in_disk(save_name, body_index).is_some()
 }
@@ -0,0 +1,265 @@
+//! Render tilemaps on CPU into images, so outputs of map generation can be
+//! checked without a gpu.
+//!
+//! The result follows `tilemap.wgsl` as close as possible, with nearest
+//! sampling. Animated tiles always show their first frames.
+
+use std::path::Path;
+
+use bevy::{
+    color::{ColorToComponents, LinearRgba},
+    math::{IVec2, UVec2, Vec2, Vec4},
+    prelude::GlobalTransform,
+};
+use image::{ImageError, ImageResult, Rgba, RgbaImage};
+
+use crate::map::{
+    picking::tile_to_world,
+    tilemap::{
+        TileAtlasIndex, TileFlip, TileRenderSize, TileStaticAtlas, TilemapAnimations,
+        TilemapStorage, TilemapTextureDescriptor,
+    },
+};
+
+/// A tileset image along with how it's divided.
+pub struct SnapshotTileset {
+    pub image: RgbaImage,
+    pub desc: TilemapTextureDescriptor,
+}
+
+impl SnapshotTileset {
+    pub fn open(path: impl AsRef<Path>, desc: TilemapTextureDescriptor) -> ImageResult<Self> {
+        Ok(Self {
+            image: image::open(path)?.into_rgba8(),
+            desc,
+        })
+    }
+
+    /// Sample the tile at `uv`, where `(0, 0)` is the top left corner.
+    fn sample(&self, atlas: u32, uv: Vec2) -> Vec4 {
+        let tile_count = self.desc.tile_count();
+        let atlas_2d = UVec2::new(atlas % tile_count.x, atlas / tile_count.x);
+        let texel = (atlas_2d.as_vec2() + uv.clamp(Vec2::ZERO, Vec2::splat(0.9999)))
+            * self.desc.tile_size.as_vec2();
+        let texel = texel
+            .as_uvec2()
+            .min(UVec2::new(self.image.width(), self.image.height()) - 1);
+
+        let Rgba(rgba) = *self.image.get_pixel(texel.x, texel.y);
+        Vec4::from_array(rgba.map(|c| c as f32 / 255.))
+    }
+}
+
+/// A tilemap layer to be rendered.
+pub struct SnapshotLayer<'a> {
+    pub storage: &'a TilemapStorage,
+    pub animations: &'a TilemapAnimations,
+    /// Indexed the same as [`TilemapTilesets::textures`](crate::map::tilemap::TilemapTilesets::textures).
+    pub tilesets: &'a [SnapshotTileset],
+    pub tint: LinearRgba,
+}
+
+/// The bottom left corner of the tile, without any transform.
+#[inline]
+fn tile_origin(index: IVec2, tile_render_size: &TileRenderSize) -> Vec2 {
+    tile_to_world(index, tile_render_size, &GlobalTransform::IDENTITY) - **tile_render_size / 2.
+}
+
+/// Render `layers`, from bottom to top, into an image that just fits all tiles.
+/// One pixel is one unit in world space. Returns `None` if there's no tile.
+pub fn render_snapshot(
+    layers: &[SnapshotLayer],
+    tile_render_size: TileRenderSize,
+    background: LinearRgba,
+) -> Option<RgbaImage> {
+    let (min, max) = layers
+        .iter()
+        .flat_map(|l| l.storage.chunked_storage().iter_direct())
+        .map(|(index, _)| tile_origin(index, &tile_render_size))
+        .fold(None, |acc: Option<(Vec2, Vec2)>, o| {
+            let (min, max) = acc.unwrap_or((o, o));
+            Some((min.min(o), max.max(o + *tile_render_size)))
+        })?;
+
+    let size = (max - min).ceil().as_uvec2();
+    let mut canvas = vec![background.to_vec4(); (size.x * size.y) as usize];
+
+    for layer in layers {
+        // Tiles further from the screen are drawn first.
+        let mut tiles = layer
+            .storage
+            .chunked_storage()
+            .iter_direct()
+            .filter(|(_, t)| t.visible)
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|(index, _)| (-(index.x + index.y), index.x));
+
+        for (index, tile) in tiles {
+            let Some(atlas) = static_atlas(tile.atlas_index, layer.animations) else {
+                continue;
+            };
+            let Some(tileset) = layer.tilesets.get(atlas.texture as usize) else {
+                continue;
+            };
+
+            // Image space is y-down, while world space is y-up.
+            let origin = tile_origin(index, &tile_render_size);
+            let top_left = Vec2::new(origin.x - min.x, max.y - origin.y - tile_render_size.y);
+            let from = top_left.floor().max(Vec2::ZERO).as_uvec2();
+            let to = (top_left + *tile_render_size).ceil().as_uvec2().min(size);
+
+            for y in from.y..to.y {
+                for x in from.x..to.x {
+                    let pixel_center = Vec2::new(x as f32, y as f32) + 0.5;
+                    let mut uv = (pixel_center - top_left) / *tile_render_size;
+                    if uv.cmplt(Vec2::ZERO).any() || uv.cmpge(Vec2::ONE).any() {
+                        continue;
+                    }
+
+                    if atlas.flip.contains(TileFlip::VERTICAL) {
+                        uv.y = 1. - uv.y;
+                    }
+                    if atlas.flip.contains(TileFlip::HORIZONTAL) {
+                        uv.x = 1. - uv.x;
+                    }
+
+                    let src = tileset.sample(atlas.atlas, uv) * layer.tint.to_vec4();
+                    let dst = &mut canvas[(x + y * size.x) as usize];
+                    *dst = blend(src, *dst);
+                }
+            }
+        }
+    }
+
+    Some(RgbaImage::from_fn(size.x, size.y, |x, y| {
+        let color = canvas[(x + y * size.x) as usize].clamp(Vec4::ZERO, Vec4::ONE);
+        Rgba(color.to_array().map(|c| (c * 255.).round() as u8))
+    }))
+}
+
+/// Render and save as png.
+pub fn save_snapshot(
+    layers: &[SnapshotLayer],
+    tile_render_size: TileRenderSize,
+    background: LinearRgba,
+    path: impl AsRef<Path>,
+) -> Result<(), ImageError> {
+    match render_snapshot(layers, tile_render_size, background) {
+        Some(image) => image.save(path),
+        None => RgbaImage::new(1, 1).save(path),
+    }
+}
+
+fn static_atlas(atlas: TileAtlasIndex, animations: &TilemapAnimations) -> Option<TileStaticAtlas> {
+    match atlas {
+        TileAtlasIndex::Static(atlas) => Some(atlas),
+        TileAtlasIndex::Animated { anim, .. } => animations
+            .entries()
+            .get(anim.id() as usize)
+            .map(|e| e.frames[0]),
+    }
+}
+
+/// Straight alpha blending, `src` over `dst`.
+#[inline]
+fn blend(src: Vec4, dst: Vec4) -> Vec4 {
+    let alpha = src.w + dst.w * (1. - src.w);
+    if alpha <= 0. {
+        return Vec4::ZERO;
+    }
+
+    let rgb = (src.truncate() * src.w + dst.truncate() * dst.w * (1. - src.w)) / alpha;
+    rgb.extend(alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        color::LinearRgba,
+        math::{IVec2, UVec2, Vec2},
+    };
+    use image::{Rgba, RgbaImage};
+
+    use crate::map::{
+        snapshot::{render_snapshot, SnapshotLayer, SnapshotTileset},
+        tilemap::{
+            Tile, TileAtlasIndex, TileIndex, TileRenderSize, TilemapAnimations, TilemapStorage,
+            TilemapTextureDescriptor,
+        },
+    };
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    /// Two 2x2 tiles side by side, the first red and the second blue.
+    fn tileset() -> SnapshotTileset {
+        SnapshotTileset {
+            image: RgbaImage::from_fn(4, 2, |x, _| if x < 2 { RED } else { BLUE }),
+            desc: TilemapTextureDescriptor {
+                size: UVec2::new(4, 2),
+                tile_size: UVec2::new(2, 2),
+            },
+        }
+    }
+
+    fn tile(index: IVec2, atlas: u32, chunk_size: u32) -> Tile {
+        Tile {
+            index: TileIndex::from_direct(index, chunk_size),
+            atlas_index: TileAtlasIndex::Static((0, atlas).into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_storage() {
+        let storage = TilemapStorage::new(4);
+        let animations = TilemapAnimations::default();
+        let tilesets = [tileset()];
+        let layers = [SnapshotLayer {
+            storage: &storage,
+            animations: &animations,
+            tilesets: &tilesets,
+            tint: LinearRgba::WHITE,
+        }];
+
+        assert!(render_snapshot(
+            &layers,
+            TileRenderSize(Vec2::new(32., 16.)),
+            LinearRgba::BLACK
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn two_tiles() {
+        let mut storage = TilemapStorage::new(4);
+        storage.set(tile(IVec2::new(0, 0), 0, 4));
+        storage.set(tile(IVec2::new(1, 0), 1, 4));
+        let animations = TilemapAnimations::default();
+        let tilesets = [tileset()];
+        let layers = [SnapshotLayer {
+            storage: &storage,
+            animations: &animations,
+            tilesets: &tilesets,
+            tint: LinearRgba::WHITE,
+        }];
+
+        let image = render_snapshot(
+            &layers,
+            TileRenderSize(Vec2::new(32., 16.)),
+            LinearRgba::BLACK,
+        )
+        .unwrap();
+
+        // (0, 0) covers x in [0, 32), y in [8, 24), and (1, 0) covers x in
+        // [16, 48), y in [0, 16).
+        assert_eq!(image.dimensions(), (48, 24));
+        assert_eq!(*image.get_pixel(4, 20), RED);
+        assert_eq!(*image.get_pixel(40, 4), BLUE);
+        // (0, 0) is closer to the screen, so it's drawn over (1, 0).
+        assert_eq!(*image.get_pixel(20, 12), RED);
+        assert_eq!(*image.get_pixel(4, 4), BLACK);
+        assert_eq!(*image.get_pixel(40, 20), BLACK);
+    }
+}
@@ -0,0 +1,334 @@
+//! Stream chunks of large tilemaps from/to disk, depending on the view of the
+//! main camera.
+//!
+//! Chunks far away from the view are written into per-chunk records in the
+//! save, and removed from [`TilemapStorage`], so the render side drops their
+//! meshes as well. When they get close to the view again, they're read back.
+//! Neither fires tile change events, as tiles aren't actually changed.
+//!
+//! Streaming is enabled on all body tilemaps once they're spawned.
+//!
+//! Only tiles are streamed. [`TileDataStorage`](crate::map::data::TileDataStorage)s
+//! and spatial queries only see chunks that are currently in memory.
+
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    app::{App, Plugin, Update},
+    log::error,
+    math::{IVec2, Vec2},
+    prelude::{
+        in_state, Camera, Commands, Component, GlobalTransform, InheritedVisibility,
+        IntoSystemConfigs, OnInsert, Query, Res, Trigger, With,
+    },
+    utils::{HashMap, HashSet},
+};
+use bincode::error::{DecodeError, EncodeError};
+use thiserror::Error;
+
+use crate::{
+    cosmos::celestial::{BodyIndex, BodyTilemap},
+    map::{
+        layer::{TilemapLayer, TilemapLayers},
+        picking::world_to_tile,
+        serde::{decode_chunk_record, encode_chunk_record, write_bytes},
+        tilemap::{Tile, TileAnimation, TileRenderSize, TilemapAnimations, TilemapStorage},
+    },
+    schedule::state::GameState,
+    sim::{MainCamera, SaveName},
+    util::chunking::Chunk,
+};
+
+pub const DEFAULT_STREAMING_MARGIN: u32 = 2;
+pub const DEFAULT_STREAMING_BUDGET: usize = 16;
+
+pub(super) struct TilemapStreamingPlugin;
+
+impl Plugin for TilemapStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, stream_chunks.run_if(in_state(GameState::Simulate)))
+            .observe(enable_streaming);
+    }
+}
+
+/// Enables chunk streaming on a tilemap. It's on the root layer, i.e. the one
+/// stored in [`BodyTilemap`], and all layers will be streamed. Body tilemaps
+/// get the default one unless another is inserted before [`BodyTilemap`].
+///
+/// Hidden tilemaps are left untouched.
+#[derive(Component, Debug, Clone)]
+pub struct TilemapStreaming {
+    /// How many chunks around the view are kept in memory.
+    pub margin: u32,
+    /// Max number of chunks loaded or unloaded in one frame.
+    pub budget: usize,
+    /// Unloaded chunks, keyed by the z-order of layers.
+    unloaded: HashMap<u32, HashSet<IVec2>>,
+}
+
+impl Default for TilemapStreaming {
+    fn default() -> Self {
+        Self::new(DEFAULT_STREAMING_MARGIN, DEFAULT_STREAMING_BUDGET)
+    }
+}
+
+impl TilemapStreaming {
+    pub fn new(margin: u32, budget: usize) -> Self {
+        assert_ne!(budget, 0, "Invalid budget: Must be larger than 0.");
+
+        Self {
+            margin,
+            budget,
+            unloaded: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn is_unloaded(&self, layer: TilemapLayer, chunk: IVec2) -> bool {
+        self.unloaded
+            .get(&layer.z_order)
+            .is_some_and(|c| c.contains(&chunk))
+    }
+
+    /// Chunks of `layer` that currently live on disk.
+    #[inline]
+    pub fn unloaded(&self, layer: TilemapLayer) -> impl Iterator<Item = IVec2> + '_ {
+        self.unloaded
+            .get(&layer.z_order)
+            .into_iter()
+            .flat_map(|c| c.iter().copied())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ChunkRecordError {
+    #[error("Io error: {0:?}")]
+    Io(std::io::Error),
+    #[error("Encode error: {0:?}")]
+    Encode(EncodeError),
+    #[error("Decode error: {0:?}")]
+    Decode(DecodeError),
+}
+
+/// The directory holding all chunk records of the tilemap of a body.
+pub(super) fn chunk_records_dir(save_name: &str, body_index: usize) -> PathBuf {
+    Path::new(&std::env::var("PROGRAM_ROOT").unwrap())
+        .join("assets")
+        .join("data")
+        .join("saves")
+        .join(save_name)
+        .join("maps")
+        .join(body_index.to_string())
+}
+
+pub(super) fn chunk_record_path(
+    save_name: &str,
+    body_index: usize,
+    layer: TilemapLayer,
+    chunk: IVec2,
+) -> PathBuf {
+    chunk_records_dir(save_name, body_index)
+        .join(layer.z_order.to_string())
+        .join(format!("{}_{}.tmc", chunk.x, chunk.y))
+}
+
+/// `animations` are all registered animations of the layer, as records refer to
+/// them by id.
+pub(super) fn read_chunk_record(
+    path: &Path,
+    animations: &[Option<TileAnimation>],
+) -> Result<Chunk<Tile>, ChunkRecordError> {
+    let bytes = std::fs::read(path).map_err(|e| ChunkRecordError::Io(e))?;
+    decode_chunk_record(&bytes, animations).map_err(|e| ChunkRecordError::Decode(e))
+}
+
+fn write_chunk_record(path: &Path, chunk: &Chunk<Tile>) -> Result<(), ChunkRecordError> {
+    let bytes = encode_chunk_record(chunk).map_err(|e| ChunkRecordError::Encode(e))?;
+    write_bytes(&bytes, path).map_err(|e| ChunkRecordError::Io(e))?;
+    Ok(())
+}
+
+/// Overwrite tiles in `base` with existing ones in `over`.
+pub(super) fn merge_chunk(base: &mut Chunk<Tile>, over: &Chunk<Tile>) {
+    base.iter_mut()
+        .zip(over.iter())
+        .filter(|(_, t)| t.is_some())
+        .for_each(|(b, t)| *b = t.clone());
+}
+
+fn enable_streaming(
+    trigger: Trigger<OnInsert, BodyTilemap>,
+    mut commands: Commands,
+    bodies_query: Query<&BodyTilemap>,
+    streaming_query: Query<(), With<TilemapStreaming>>,
+) {
+    let Ok(body_tilemap) = bodies_query.get(trigger.entity()) else {
+        return;
+    };
+
+    if streaming_query.contains(**body_tilemap) {
+        return;
+    }
+
+    if let Some(mut root) = commands.get_entity(**body_tilemap) {
+        root.insert(TilemapStreaming::default());
+    }
+}
+
+fn stream_chunks(
+    main_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    bodies_query: Query<(&BodyIndex, &BodyTilemap)>,
+    mut roots_query: Query<(
+        &mut TilemapStreaming,
+        &TileRenderSize,
+        &GlobalTransform,
+        &InheritedVisibility,
+        Option<&TilemapLayers>,
+    )>,
+    mut layers_query: Query<(&TilemapLayer, &mut TilemapStorage, &TilemapAnimations)>,
+    save_name: Res<SaveName>,
+) {
+    let Ok((camera, camera_transform)) = main_camera.get_single() else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_size() else {
+        return;
+    };
+    let Some(corners) = [
+        Vec2::ZERO,
+        Vec2::new(viewport.x, 0.),
+        Vec2::new(0., viewport.y),
+        viewport,
+    ]
+    .into_iter()
+    .map(|p| camera.viewport_to_world_2d(camera_transform, p))
+    .collect::<Option<Vec<_>>>() else {
+        return;
+    };
+
+    for (body_index, body_tilemap) in &bodies_query {
+        let root = **body_tilemap;
+        let Ok((mut streaming, tile_render_size, transform, visibility, layers)) =
+            roots_query.get_mut(root)
+        else {
+            continue;
+        };
+
+        if !visibility.get() {
+            continue;
+        }
+
+        // The view is a parallelogram in tile space, so take its bounding box.
+        let (view_min, view_max) = corners
+            .iter()
+            .map(|c| world_to_tile(*c, tile_render_size, transform))
+            .fold((IVec2::MAX, IVec2::MIN), |(min, max), t| {
+                (min.min(t), max.max(t))
+            });
+
+        let layers = match layers {
+            Some(layers) => layers.entities().collect::<Vec<_>>(),
+            None => vec![root],
+        };
+
+        let mut budget = streaming.budget;
+        for layer_entity in layers {
+            let Ok((layer, mut storage, animations)) = layers_query.get_mut(layer_entity) else {
+                continue;
+            };
+            let animations = animations
+                .entries()
+                .iter()
+                .map(|e| Some(e.animation()))
+                .collect::<Vec<_>>();
+
+            let chunk_size = IVec2::splat(storage.chunk_size() as i32);
+            let margin = IVec2::splat(streaming.margin as i32);
+            let min = view_min.div_euclid(chunk_size) - margin;
+            let max = view_max.div_euclid(chunk_size) + margin;
+            let is_in_view = |c: IVec2| c.cmpge(min).all() && c.cmple(max).all();
+
+            let to_unload = storage
+                .chunked_storage()
+                .keys()
+                .filter(|c| !is_in_view(**c))
+                .take(budget)
+                .copied()
+                .collect::<Vec<_>>();
+            budget -= to_unload.len();
+
+            for chunk_index in to_unload {
+                let path = chunk_record_path(&save_name, **body_index, *layer, chunk_index);
+                let mut chunk = storage.get_chunk(chunk_index).unwrap().clone();
+
+                // Tiles are set onto a chunk that is already unloaded.
+                if streaming.is_unloaded(*layer, chunk_index) {
+                    match read_chunk_record(&path, &animations) {
+                        Ok(mut unloaded) => {
+                            merge_chunk(&mut unloaded, &chunk);
+                            chunk = unloaded;
+                        }
+                        Err(err) => {
+                            error!(
+                                "Failed to read chunk {} of body {}: {}",
+                                chunk_index, **body_index, err
+                            );
+                            continue;
+                        }
+                    }
+                }
+
+                match write_chunk_record(&path, &chunk) {
+                    Ok(_) => {
+                        storage.stream_out_chunk(chunk_index);
+                        streaming
+                            .unloaded
+                            .entry(layer.z_order)
+                            .or_default()
+                            .insert(chunk_index);
+                    }
+                    Err(err) => error!(
+                        "Failed to unload chunk {} of body {}: {}",
+                        chunk_index, **body_index, err
+                    ),
+                }
+            }
+
+            let to_load = streaming
+                .unloaded(*layer)
+                .filter(|c| is_in_view(*c))
+                .take(budget)
+                .collect::<Vec<_>>();
+            budget -= to_load.len();
+
+            for chunk_index in to_load {
+                let path = chunk_record_path(&save_name, **body_index, *layer, chunk_index);
+
+                match read_chunk_record(&path, &animations) {
+                    Ok(mut chunk) => {
+                        // Tiles set while the chunk is unloaded take precedence.
+                        if let Some(existing) = storage.get_chunk(chunk_index) {
+                            merge_chunk(&mut chunk, existing);
+                        }
+                        storage.stream_in_chunk(chunk_index, chunk);
+                        streaming
+                            .unloaded
+                            .get_mut(&layer.z_order)
+                            .unwrap()
+                            .remove(&chunk_index);
+                        let _ = std::fs::remove_file(&path);
+                    }
+                    // Kept as unloaded, so it's not lost.
+                    Err(err) => error!(
+                        "Failed to load chunk {} of body {}: {}",
+                        chunk_index, **body_index, err
+                    ),
+                }
+            }
+
+            if budget == 0 {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,534 @@
+//! Import hand-authored maps and tilesets from [Tiled](https://www.mapeditor.org/).
+//!
+//! Only isometric maps with CSV encoded layers are supported. Tilesets must be
+//! a single image without margin and spacing. Diagonal flips are ignored, as
+//! tiles can't be rotated. Aseprite files should be exported as png sheets first
+//! and then referenced from a tileset.
+
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::{io::Reader, Asset, AssetApp, AssetLoader, Assets, AsyncReadExt, Handle, LoadContext},
+    log::warn,
+    math::{IVec2, UVec2, Vec2},
+    prelude::{in_state, Commands, Component, Entity, IntoSystemConfigs, Query, Res},
+    reflect::TypePath,
+    render::{render_resource::FilterMode, texture::Image},
+    utils::HashMap,
+};
+use roxmltree::{Document, Node};
+use thiserror::Error;
+
+use crate::{
+    cosmos::celestial::BodyTilemap,
+    map::{
+        bundle::TilemapBundle,
+        layer::{spawn_layered_tilemap, TilemapLayer},
+        tilemap::{
+            Tile, TileAnimation, TileAtlasIndex, TileFlip, TileIndex, TileRenderSize,
+            TileStaticAtlas, TilemapAnimations, TilemapStorage, TilemapTexture,
+            TilemapTextureDescriptor, TilemapTilesets,
+        },
+    },
+    schedule::state::GameState,
+    util::chunking::DEFAULT_CHUNK_SIZE,
+};
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const ROTATED_HEXAGONAL_120: u32 = 0x1000_0000;
+const GID_MASK: u32 =
+    !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL_120);
+
+pub(super) struct TiledImportPlugin;
+
+impl Plugin for TiledImportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<TiledTileset>()
+            .init_asset::<TiledMap>()
+            .init_asset_loader::<TiledTilesetLoader>()
+            .init_asset_loader::<TiledMapLoader>()
+            .add_systems(
+                Update,
+                import_tiled_maps.run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+/// Marks a body needs to use the Tiled map as its tilemap.
+#[derive(Component)]
+pub struct ToImportTiledMap(pub Handle<TiledMap>);
+
+#[derive(Error, Debug)]
+pub enum TiledImportError {
+    #[error("Io error: {0:?}")]
+    Io(std::io::Error),
+    #[error("Xml error: {0:?}")]
+    Xml(roxmltree::Error),
+    #[error("Missing element or attribute: {0}")]
+    Missing(&'static str),
+    #[error("Invalid value of {0}: {1}")]
+    Invalid(&'static str, String),
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledAnimationFrame {
+    pub tile_id: u32,
+    pub duration_milisec: u32,
+}
+
+/// A Tiled tileset, which is a single texture in [`TilemapTilesets`].
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct TiledTileset {
+    pub texture: TilemapTexture,
+    /// Animations keyed by the local id of the animated tile.
+    pub animations: HashMap<u32, Vec<TiledAnimationFrame>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledLayer {
+    pub name: String,
+    /// Global tile ids with flip bits. `0` means empty.
+    pub tiles: Vec<(IVec2, u32)>,
+}
+
+/// A Tiled map. Layers are ordered from bottom to top.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct TiledMap {
+    pub tile_render_size: Vec2,
+    /// Tilesets along with their first global id, sorted by the id.
+    pub tilesets: Vec<(u32, Handle<TiledTileset>)>,
+    pub layers: Vec<TiledLayer>,
+}
+
+impl TiledMap {
+    /// Convert into tilemap layers, which can be spawned using
+    /// [`spawn_layered_tilemap`]. Returns `None` if tilesets are not loaded yet.
+    pub fn to_bundles(&self, tilesets: &Assets<TiledTileset>) -> Option<Vec<TilemapBundle>> {
+        let tilesets = self
+            .tilesets
+            .iter()
+            .map(|(first_gid, handle)| tilesets.get(handle).map(|t| (*first_gid, t)))
+            .collect::<Option<Vec<_>>>()?;
+
+        // Nothing can be placed without tilesets.
+        if tilesets.is_empty() {
+            return Some(Vec::new());
+        }
+
+        let textures = TilemapTilesets::new(
+            tilesets.iter().map(|(_, t)| t.texture.clone()).collect(),
+            FilterMode::Nearest,
+        );
+
+        Some(
+            self.layers
+                .iter()
+                .enumerate()
+                .map(|(z_order, layer)| {
+                    let mut storage = TilemapStorage::new(DEFAULT_CHUNK_SIZE);
+                    let mut animations = TilemapAnimations::default();
+                    let mut registered = HashMap::default();
+
+                    for (index, gid) in &layer.tiles {
+                        let flip_bits = gid & !GID_MASK;
+                        let gid = gid & GID_MASK;
+                        // Tilesets are sorted, so the last one that starts before the gid
+                        // is the one containing it.
+                        let Some((texture, (first_gid, tileset))) = tilesets
+                            .iter()
+                            .enumerate()
+                            .rev()
+                            .find(|(_, (first_gid, _))| *first_gid <= gid)
+                        else {
+                            warn!("Tile {} in layer {} has no tileset.", gid, layer.name);
+                            continue;
+                        };
+
+                        let mut flip = TileFlip::NONE;
+                        if flip_bits & FLIPPED_HORIZONTALLY != 0 {
+                            flip |= TileFlip::HORIZONTAL;
+                        }
+                        if flip_bits & FLIPPED_VERTICALLY != 0 {
+                            flip |= TileFlip::VERTICAL;
+                        }
+
+                        let local_id = gid - first_gid;
+                        let animation = tileset.animations.get(&local_id).and_then(|frames| {
+                            *registered
+                                .entry((texture, local_id, flip))
+                                .or_insert_with(|| {
+                                    register_animation(
+                                        &mut animations,
+                                        texture as u32,
+                                        flip,
+                                        frames,
+                                    )
+                                })
+                        });
+                        let atlas_index = match animation {
+                            Some(anim) => TileAtlasIndex::Animated {
+                                anim,
+                                offset_milisec: 0,
+                            },
+                            None => TileAtlasIndex::Static(TileStaticAtlas {
+                                texture: texture as u32,
+                                atlas: local_id,
+                                flip,
+                            }),
+                        };
+
+                        storage.set(Tile {
+                            index: TileIndex::from_direct(*index, storage.chunk_size()),
+                            atlas_index,
+                            ..Default::default()
+                        });
+                    }
+
+                    TilemapBundle {
+                        tile_render_size: TileRenderSize(self.tile_render_size),
+                        storgae: storage,
+                        tilesets: textures.clone(),
+                        animations,
+                        layer: TilemapLayer {
+                            z_order: z_order as u32,
+                        },
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Frames in Tiled have their own durations, but ours share the same fps, so
+/// the average one is used. The flip of the tile applies to all frames.
+///
+/// Animations without frames are skipped, and tiles using them stay static.
+fn register_animation(
+    animations: &mut TilemapAnimations,
+    texture: u32,
+    flip: TileFlip,
+    frames: &[TiledAnimationFrame],
+) -> Option<TileAnimation> {
+    if frames.is_empty() {
+        warn!("Tiled animation without frames found, skipped.");
+        return None;
+    }
+
+    let total = frames
+        .iter()
+        .map(|f| f.duration_milisec)
+        .sum::<u32>()
+        .max(1);
+    let fps = (1000 * frames.len() as u32 / total).max(1);
+
+    animations.register(
+        frames.iter().map(|f| TileStaticAtlas {
+            texture,
+            atlas: f.tile_id,
+            flip,
+        }),
+        fps,
+    )
+}
+
+fn attribute<'a>(node: &Node<'a, '_>, name: &'static str) -> Result<&'a str, TiledImportError> {
+    node.attribute(name).ok_or(TiledImportError::Missing(name))
+}
+
+fn parse_attribute<T: std::str::FromStr>(
+    node: &Node,
+    name: &'static str,
+) -> Result<T, TiledImportError> {
+    let value = attribute(node, name)?;
+    value
+        .parse()
+        .map_err(|_| TiledImportError::Invalid(name, value.to_string()))
+}
+
+fn parse_attribute_or<T: std::str::FromStr>(
+    node: &Node,
+    name: &'static str,
+    default: T,
+) -> Result<T, TiledImportError> {
+    match node.attribute(name) {
+        Some(_) => parse_attribute(node, name),
+        None => Ok(default),
+    }
+}
+
+async fn read_to_string(reader: &mut Reader<'_>) -> Result<String, TiledImportError> {
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .await
+        .map_err(|e| TiledImportError::Io(e))?;
+    Ok(text)
+}
+
+/// Parse a `<tileset>` element, either in a `.tsx` file or embedded in a map.
+fn parse_tileset(
+    node: Node,
+    load_context: &mut LoadContext,
+) -> Result<TiledTileset, TiledImportError> {
+    if parse_attribute_or(&node, "margin", 0u32)? != 0
+        || parse_attribute_or(&node, "spacing", 0u32)? != 0
+    {
+        return Err(TiledImportError::Unsupported(
+            "tilesets with margin or spacing".to_string(),
+        ));
+    }
+
+    let tile_size = UVec2 {
+        x: parse_attribute(&node, "tilewidth")?,
+        y: parse_attribute(&node, "tileheight")?,
+    };
+
+    let image = node
+        .children()
+        .find(|n| n.has_tag_name("image"))
+        .ok_or_else(|| {
+            TiledImportError::Unsupported("tilesets made of separated images".to_string())
+        })?;
+    let source = attribute(&image, "source")?;
+    let path = load_context
+        .asset_path()
+        .resolve_embed(source)
+        .map_err(|_| TiledImportError::Invalid("source", source.to_string()))?;
+    let size = UVec2 {
+        x: parse_attribute(&image, "width")?,
+        y: parse_attribute(&image, "height")?,
+    };
+
+    let mut animations = HashMap::default();
+    for tile in node.children().filter(|n| n.has_tag_name("tile")) {
+        let Some(animation) = tile.children().find(|n| n.has_tag_name("animation")) else {
+            continue;
+        };
+
+        let frames = animation
+            .children()
+            .filter(|n| n.has_tag_name("frame"))
+            .map(|frame| {
+                Ok(TiledAnimationFrame {
+                    tile_id: parse_attribute(&frame, "tileid")?,
+                    duration_milisec: parse_attribute(&frame, "duration")?,
+                })
+            })
+            .collect::<Result<Vec<_>, TiledImportError>>()?;
+        animations.insert(parse_attribute(&tile, "id")?, frames);
+    }
+
+    Ok(TiledTileset {
+        texture: TilemapTexture {
+            id: None,
+            handle: load_context.load::<Image>(path),
+            desc: TilemapTextureDescriptor { size, tile_size },
+        },
+        animations,
+    })
+}
+
+fn parse_csv(
+    text: &str,
+    origin: IVec2,
+    width: i32,
+    tiles: &mut Vec<(IVec2, u32)>,
+) -> Result<(), TiledImportError> {
+    for (i, gid) in text
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .enumerate()
+    {
+        let gid = gid
+            .parse::<u32>()
+            .map_err(|_| TiledImportError::Invalid("data", gid.to_string()))?;
+        if gid == 0 {
+            continue;
+        }
+
+        let i = i as i32;
+        let tiled = origin + IVec2::new(i % width, i / width);
+        // Tiled's y axis points down, and x/y axes point to the bottom right and
+        // bottom left on screen. Ours are to the top right and top left.
+        tiles.push((IVec2::new(-tiled.y, -tiled.x), gid));
+    }
+
+    Ok(())
+}
+
+fn parse_layer(node: Node) -> Result<TiledLayer, TiledImportError> {
+    let data = node
+        .children()
+        .find(|n| n.has_tag_name("data"))
+        .ok_or(TiledImportError::Missing("data"))?;
+
+    match data.attribute("encoding") {
+        Some("csv") => {}
+        encoding => {
+            return Err(TiledImportError::Unsupported(format!(
+                "layer encoding {:?}",
+                encoding
+            )))
+        }
+    }
+
+    let mut tiles = Vec::new();
+    let chunks = data
+        .children()
+        .filter(|n| n.has_tag_name("chunk"))
+        .collect::<Vec<_>>();
+
+    if chunks.is_empty() {
+        parse_csv(
+            data.text().unwrap_or_default(),
+            IVec2::ZERO,
+            parse_attribute(&node, "width")?,
+            &mut tiles,
+        )?;
+    } else {
+        // Infinite maps.
+        for chunk in chunks {
+            parse_csv(
+                chunk.text().unwrap_or_default(),
+                IVec2::new(parse_attribute(&chunk, "x")?, parse_attribute(&chunk, "y")?),
+                parse_attribute(&chunk, "width")?,
+                &mut tiles,
+            )?;
+        }
+    }
+
+    Ok(TiledLayer {
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        tiles,
+    })
+}
+
+#[derive(Default)]
+pub struct TiledTilesetLoader;
+
+impl AssetLoader for TiledTilesetLoader {
+    type Asset = TiledTileset;
+
+    type Settings = ();
+
+    type Error = TiledImportError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let text = read_to_string(reader).await?;
+        let document = Document::parse(&text).map_err(|e| TiledImportError::Xml(e))?;
+        parse_tileset(document.root_element(), load_context)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tsx"]
+    }
+}
+
+#[derive(Default)]
+pub struct TiledMapLoader;
+
+impl AssetLoader for TiledMapLoader {
+    type Asset = TiledMap;
+
+    type Settings = ();
+
+    type Error = TiledImportError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let text = read_to_string(reader).await?;
+        let document = Document::parse(&text).map_err(|e| TiledImportError::Xml(e))?;
+        let map = document.root_element();
+
+        let orientation = attribute(&map, "orientation")?;
+        if orientation != "isometric" {
+            return Err(TiledImportError::Unsupported(format!(
+                "{} maps",
+                orientation
+            )));
+        }
+
+        let mut tilesets = Vec::new();
+        for (i, tileset) in map
+            .children()
+            .filter(|n| n.has_tag_name("tileset"))
+            .enumerate()
+        {
+            let first_gid = parse_attribute::<u32>(&tileset, "firstgid")?;
+            let handle = match tileset.attribute("source") {
+                Some(source) => {
+                    let path = load_context
+                        .asset_path()
+                        .resolve_embed(source)
+                        .map_err(|_| TiledImportError::Invalid("source", source.to_string()))?;
+                    load_context.load::<TiledTileset>(path)
+                }
+                None => {
+                    let embedded = parse_tileset(tileset, load_context)?;
+                    load_context.add_labeled_asset(format!("tileset{}", i), embedded)
+                }
+            };
+            tilesets.push((first_gid, handle));
+        }
+        tilesets.sort_by_key(|(first_gid, _)| *first_gid);
+
+        Ok(TiledMap {
+            tile_render_size: Vec2 {
+                x: parse_attribute(&map, "tilewidth")?,
+                y: parse_attribute(&map, "tileheight")?,
+            },
+            tilesets,
+            layers: map
+                .children()
+                .filter(|n| n.has_tag_name("layer"))
+                .map(parse_layer)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx"]
+    }
+}
+
+fn import_tiled_maps(
+    mut commands: Commands,
+    to_import_query: Query<(Entity, &ToImportTiledMap)>,
+    tiled_maps: Res<Assets<TiledMap>>,
+    tiled_tilesets: Res<Assets<TiledTileset>>,
+) {
+    for (body_entity, to_import) in &to_import_query {
+        let Some(bundles) = tiled_maps
+            .get(&to_import.0)
+            .and_then(|map| map.to_bundles(&tiled_tilesets))
+        else {
+            continue;
+        };
+
+        if bundles.is_empty() {
+            warn!("Tiled map has no layer or tileset, skipped.");
+            commands.entity(body_entity).remove::<ToImportTiledMap>();
+            continue;
+        }
+
+        let tilemap = spawn_layered_tilemap(&mut commands, bundles);
+        commands
+            .entity(body_entity)
+            .insert(BodyTilemap::new(tilemap))
+            .remove::<ToImportTiledMap>();
+    }
+}
@@ -1,15 +1,18 @@
+use std::time::Duration;
+
 use bevy::{
     asset::Handle,
     color::Color,
     math::{IVec2, UVec2, UVec3, Vec2},
     prelude::{Component, Deref, DerefMut},
     render::{render_resource::FilterMode, texture::Image},
-    utils::HashSet,
+    utils::{HashMap, HashSet},
 };
+use serde::{Deserialize, Serialize};
 
 use crate::util::chunking::{Chunk, ChunkStorageIndex, ChunkedStorage, DEFAULT_CHUNK_SIZE};
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Tile {
     pub index: TileIndex,
     pub atlas_index: TileAtlasIndex,
@@ -71,7 +74,7 @@ impl Default for TileAtlasIndex {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TileStaticAtlas {
     pub texture: u32,
     pub atlas: u32,
@@ -116,14 +119,30 @@ impl TileStaticAtlas {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TileAnimation {
+    /// Index in [`TilemapAnimations::entries`].
+    pub(crate) id: u32,
     pub(crate) start: usize,
     pub(crate) len: usize,
 }
 
+impl TileAnimation {
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The `offset_milisec` that makes the animation start from now, given the
+    /// wrapped elapsed time. Mainly for [`TileAnimationMode::Once`].
+    pub fn once_offset(elapsed_wrapped: Duration) -> u32 {
+        const WRAP_PERIOD: u128 = 3_600_000;
+        ((WRAP_PERIOD - elapsed_wrapped.as_millis() % WRAP_PERIOD) % WRAP_PERIOD) as u32
+    }
+}
+
 bitflags::bitflags! {
-    #[derive(Debug, Default, Clone, Copy)]
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct TileFlip: u32 {
         const NONE       = 0b00;
         const HORIZONTAL = 0b10;
@@ -149,13 +168,27 @@ impl FlattenedTileIndex {
     #[inline]
     pub fn from_direct(index: IVec2, chunk_size: u32) -> Self {
         let chunk_size = chunk_size as i32;
-        let ic = (index % chunk_size).abs();
+        // Use euclidean division, otherwise tiles at negative indices will share the
+        // same slot with their positive counterparts.
+        let ic = index.rem_euclid(IVec2::splat(chunk_size));
         Self {
-            in_chunk: index / chunk_size,
+            in_chunk: index.div_euclid(IVec2::splat(chunk_size)),
             in_chunk_at: (ic.x + ic.y * chunk_size) as usize,
         }
     }
 
+    /// The inverse of [`FlattenedTileIndex::from_direct`].
+    #[inline]
+    pub fn to_direct(self, chunk_size: u32) -> IVec2 {
+        let chunk_size = chunk_size as i32;
+        let at = self.in_chunk_at as i32;
+        self.in_chunk * chunk_size
+            + IVec2 {
+                x: at % chunk_size,
+                y: at / chunk_size,
+            }
+    }
+
     #[inline]
     pub fn from_chunked(index: ChunkedTileIndex, chunk_size: u32) -> Self {
         FlattenedTileIndex {
@@ -172,14 +205,20 @@ impl FlattenedTileIndex {
 #[derive(Component)]
 pub struct TilemapStorage {
     internal: ChunkedStorage<IVec2, Tile>,
-    changed_tiles: HashSet<FlattenedTileIndex>,
+    /// Tiles changed since last clear, along with their values before the first
+    /// change.
+    changed_tiles: HashMap<FlattenedTileIndex, Option<Tile>>,
     changed_chunks: HashSet<IVec2>,
+    /// Chunks moved between memory and disk by [`stream`](crate::map::stream).
+    /// Their content isn't changed, so only the renderer cares about them.
+    streamed_chunks: HashSet<IVec2>,
 }
 
 pub struct UnsafePubTilemapStorageCell {
     pub internal: *mut ChunkedStorage<IVec2, Tile>,
-    pub changed_tiles: *mut HashSet<FlattenedTileIndex>,
+    pub changed_tiles: *mut HashMap<FlattenedTileIndex, Option<Tile>>,
     pub changed_chunks: *mut HashSet<IVec2>,
+    pub streamed_chunks: *mut HashSet<IVec2>,
 }
 
 impl Default for TilemapStorage {
@@ -194,6 +233,7 @@ impl TilemapStorage {
             internal: ChunkedStorage::new(chunk_size),
             changed_chunks: Default::default(),
             changed_tiles: Default::default(),
+            streamed_chunks: Default::default(),
         }
     }
 
@@ -202,8 +242,17 @@ impl TilemapStorage {
         self.internal.chunk_size()
     }
 
+    /// Read-only access to the underlying storage. Useful for spatial queries in
+    /// [`query`](crate::map::query).
     #[inline]
-    pub fn changed_tiles(&self) -> &HashSet<FlattenedTileIndex> {
+    pub fn chunked_storage(&self) -> &ChunkedStorage<IVec2, Tile> {
+        &self.internal
+    }
+
+    /// Tiles changed since last clear, along with their values before the first
+    /// change. See [`TilemapChangeSystems`](crate::map::event::TilemapChangeSystems).
+    #[inline]
+    pub fn changed_tiles(&self) -> &HashMap<FlattenedTileIndex, Option<Tile>> {
         &self.changed_tiles
     }
 
@@ -212,12 +261,18 @@ impl TilemapStorage {
         &self.changed_chunks
     }
 
+    #[inline]
+    pub fn streamed_chunks(&self) -> &HashSet<IVec2> {
+        &self.streamed_chunks
+    }
+
     #[inline]
     pub unsafe fn as_unsafe_cell_readonly(&self) -> UnsafePubTilemapStorageCell {
         UnsafePubTilemapStorageCell {
             internal: std::ptr::from_ref(&self.internal).cast_mut(),
             changed_tiles: std::ptr::from_ref(&self.changed_tiles).cast_mut(),
             changed_chunks: std::ptr::from_ref(&self.changed_chunks).cast_mut(),
+            streamed_chunks: std::ptr::from_ref(&self.streamed_chunks).cast_mut(),
         }
     }
 
@@ -227,6 +282,7 @@ impl TilemapStorage {
             internal: std::ptr::from_mut(&mut self.internal),
             changed_tiles: std::ptr::from_mut(&mut self.changed_tiles),
             changed_chunks: std::ptr::from_mut(&mut self.changed_chunks),
+            streamed_chunks: std::ptr::from_mut(&mut self.streamed_chunks),
         }
     }
 
@@ -257,13 +313,13 @@ impl TilemapStorage {
 
     #[inline]
     pub fn flattened_get_mut(&mut self, index: FlattenedTileIndex) -> Option<&mut Tile> {
-        self.changed_tiles.insert(index);
+        self.mark_changed(index);
         self.internal.get_mut(&index)
     }
 
     #[inline]
     pub fn set(&mut self, tile: Tile) -> Option<Tile> {
-        self.changed_tiles.insert(tile.index.flattened);
+        self.mark_changed(tile.index.flattened);
         self.internal.set(tile.index.flattened, tile)
     }
 
@@ -279,10 +335,27 @@ impl TilemapStorage {
 
     #[inline]
     pub fn flattened_remove(&mut self, index: FlattenedTileIndex) -> Option<Tile> {
-        self.changed_tiles.insert(index);
+        self.mark_changed(index);
         self.internal.remove(&index)
     }
 
+    #[inline]
+    fn mark_changed(&mut self, index: FlattenedTileIndex) {
+        if !self.changed_tiles.contains_key(&index) {
+            let old = self.internal.get(&index).cloned();
+            self.changed_tiles.insert(index, old);
+        }
+    }
+
+    /// Forget all changes. This is done in
+    /// [`TilemapChangeSystems::Clear`](crate::map::event::TilemapChangeSystems::Clear).
+    #[inline]
+    pub fn clear_changes(&mut self) {
+        self.changed_tiles.clear();
+        self.changed_chunks.clear();
+        self.streamed_chunks.clear();
+    }
+
     #[inline]
     pub fn get_chunk(&self, index: IVec2) -> Option<&Chunk<Tile>> {
         self.internal.get_chunk(&index)
@@ -306,6 +379,22 @@ impl TilemapStorage {
         self.internal.remove_chunk(&index)
     }
 
+    /// Like [`TilemapStorage::set_chunk`], but no change events are sent, as the
+    /// chunk is just read back from disk.
+    #[inline]
+    pub(crate) fn stream_in_chunk(&mut self, index: IVec2, chunk: Chunk<Tile>) {
+        self.streamed_chunks.insert(index);
+        self.internal.set_chunk(index, chunk);
+    }
+
+    /// Like [`TilemapStorage::remove_chunk`], but no change events are sent, as
+    /// the chunk still exists on disk.
+    #[inline]
+    pub(crate) fn stream_out_chunk(&mut self, index: IVec2) -> Option<Chunk<Tile>> {
+        self.streamed_chunks.insert(index);
+        self.internal.remove_chunk(&index)
+    }
+
     #[inline]
     pub fn clear(&mut self) {
         self.changed_chunks.extend(self.internal.keys());
@@ -319,18 +408,32 @@ impl From<ChunkedStorage<IVec2, Tile>> for TilemapStorage {
             changed_chunks: value.keys().cloned().collect(),
             internal: value,
             changed_tiles: Default::default(),
+            streamed_chunks: Default::default(),
         }
     }
 }
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct TilemapTextureDescriptor {
+    /// Size of the whole image, which doesn't need to be divisible by
+    /// `tile_size`. Pixels at the right and bottom that can't fit a tile are
+    /// unused.
     pub size: UVec2,
     pub tile_size: UVec2,
 }
 
+impl TilemapTextureDescriptor {
+    #[inline]
+    pub fn tile_count(&self) -> UVec2 {
+        self.size / self.tile_size
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct TilemapTexture {
+    /// Id in [`TilesetRegistry`](crate::map::tileset::TilesetRegistry). Textures
+    /// without id are saved by their asset paths.
+    pub id: Option<String>,
     pub handle: Handle<Image>,
     pub desc: TilemapTextureDescriptor,
 }
@@ -353,10 +456,9 @@ impl TilemapTilesets {
         let mut size = UVec2::default();
         textures.iter().for_each(|t| {
             size = size.max(t.desc.size);
-            assert_eq!(
-                t.desc.size % t.desc.tile_size,
-                UVec2::ZERO,
-                "Invalid descriptor: `size` must be divisible by `tile_size`."
+            assert!(
+                t.desc.tile_count().cmpgt(UVec2::ZERO).all(),
+                "Invalid descriptor: `size` must fit at least one tile."
             );
         });
 
@@ -383,49 +485,174 @@ impl TilemapTilesets {
 #[derive(Component, Debug, Default, Clone, Copy, Deref, DerefMut)]
 pub struct TilemapTint(pub Color);
 
-/// Layout: `[fps, frame_1_tex, frame_1_atl, frame_2_tex, frame_2_atl, fps, frame_1_tex, frame_1_atl, ...]`
-#[derive(Component, Debug, Clone)]
-pub struct TilemapAnimations(Vec<u32>);
+/// How an animation plays. Keep synced with `tilemap.wgsl`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TileAnimationMode {
+    #[default]
+    Loop = 0,
+    /// Plays forward then backward, without repeating the first and last frame.
+    PingPong = 1,
+    /// Plays once and stays at the last frame. Use [`TileAnimation::once_offset`]
+    /// to start playing from now.
+    Once = 2,
+}
+
+impl TileAnimationMode {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Loop),
+            1 => Some(Self::PingPong),
+            2 => Some(Self::Once),
+            _ => None,
+        }
+    }
+}
 
-impl From<Vec<u32>> for TilemapAnimations {
-    fn from(value: Vec<u32>) -> Self {
-        Self(value)
+#[derive(Debug, Clone)]
+pub struct TileAnimationEntry {
+    pub name: Option<String>,
+    pub frames: Vec<TileStaticAtlas>,
+    pub fps: u32,
+    pub mode: TileAnimationMode,
+    anim: TileAnimation,
+}
+
+impl TileAnimationEntry {
+    #[inline]
+    pub fn animation(&self) -> TileAnimation {
+        self.anim
     }
 }
 
+/// Animations used by tiles on this tilemap. Identical animations are only
+/// registered once.
+///
+/// Layout: `[dummy, mode, fps, frame_1_tex, frame_1_atl, frame_2_tex, frame_2_atl, mode, fps, frame_1_tex, ...]`
+#[derive(Component, Debug, Clone)]
+pub struct TilemapAnimations {
+    buffer: Vec<u32>,
+    entries: Vec<TileAnimationEntry>,
+}
+
 impl Default for TilemapAnimations {
     fn default() -> Self {
-        // A dummy value. This will force the gpu-side buffer to be created.
-        // If leave empty, [`RawBufferVec::write_buffer`] will not take affect.
-        Self([0].into())
+        Self {
+            // A dummy value. This will force the gpu-side buffer to be created.
+            // If leave empty, [`RawBufferVec::write_buffer`] will not take affect.
+            buffer: [0].into(),
+            entries: Default::default(),
+        }
     }
 }
 
 impl TilemapAnimations {
     pub fn bytes(&self) -> &Vec<u32> {
-        &self.0
+        &self.buffer
+    }
+
+    /// All registered animations. The index is the id of the animation.
+    #[inline]
+    pub fn entries(&self) -> &Vec<TileAnimationEntry> {
+        &self.entries
+    }
+
+    /// Find an animation by its name.
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<TileAnimation> {
+        self.entries
+            .iter()
+            .find(|e| e.name.as_deref() == Some(name))
+            .map(|e| e.anim)
     }
 
+    /// Register a looping animation. Returns `None` if there's no frame.
     pub fn register(
         &mut self,
         animation: impl IntoIterator<IntoIter: Iterator<Item = impl Into<TileStaticAtlas>>>,
         fps: u32,
-    ) -> TileAnimation {
-        let animation = animation
-            .into_iter()
-            .map(Into::into)
-            .map(|f| f.encode())
-            .collect::<Vec<_>>();
-        self.0.push(fps);
+    ) -> Option<TileAnimation> {
+        self.register_with_mode(animation, fps, TileAnimationMode::Loop)
+    }
+
+    pub fn register_with_mode(
+        &mut self,
+        animation: impl IntoIterator<IntoIter: Iterator<Item = impl Into<TileStaticAtlas>>>,
+        fps: u32,
+        mode: TileAnimationMode,
+    ) -> Option<TileAnimation> {
+        self.register_entry(
+            None,
+            animation.into_iter().map(Into::into).collect(),
+            fps,
+            mode,
+        )
+    }
+
+    /// Register an animation with name. If the name is already used, the
+    /// existing one is returned.
+    pub fn register_named(
+        &mut self,
+        name: &str,
+        animation: impl IntoIterator<IntoIter: Iterator<Item = impl Into<TileStaticAtlas>>>,
+        fps: u32,
+        mode: TileAnimationMode,
+    ) -> Option<TileAnimation> {
+        if let Some(anim) = self.get(name) {
+            return Some(anim);
+        }
+
+        self.register_entry(
+            Some(name.to_string()),
+            animation.into_iter().map(Into::into).collect(),
+            fps,
+            mode,
+        )
+    }
+
+    fn register_entry(
+        &mut self,
+        name: Option<String>,
+        frames: Vec<TileStaticAtlas>,
+        fps: u32,
+        mode: TileAnimationMode,
+    ) -> Option<TileAnimation> {
+        if frames.is_empty() {
+            return None;
+        }
+
+        if let Some(entry) = self.entries.iter_mut().find(|e| {
+            e.frames == frames
+                && e.fps == fps
+                && e.mode == mode
+                && (name.is_none() || e.name.is_none())
+        }) {
+            // Name the existing unnamed animation if necessary.
+            if entry.name.is_none() {
+                entry.name = name;
+            }
+            return Some(entry.anim);
+        }
+
+        self.buffer.push(mode as u32);
+        self.buffer.push(fps);
         let anim = TileAnimation {
-            start: self.0.len(),
-            len: animation.len(),
+            id: self.entries.len() as u32,
+            start: self.buffer.len(),
+            len: frames.len(),
         };
-        self.0.extend(
-            animation
-                .into_iter()
+        self.buffer.extend(
+            frames
+                .iter()
+                .map(|f| f.encode())
                 .flat_map(|frame| [frame.texture, frame.atlas]),
         );
-        anim
+        self.entries.push(TileAnimationEntry {
+            name,
+            frames,
+            fps,
+            mode,
+            anim,
+        });
+        Some(anim)
     }
 }
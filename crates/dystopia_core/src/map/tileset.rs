@@ -0,0 +1,142 @@
+//! Tilesets referenced by stable ids, so saves don't depend on where the images
+//! are.
+
+use bevy::{
+    app::{App, Plugin},
+    asset::{Asset, AssetServer},
+    log::warn,
+    prelude::Resource,
+    reflect::TypePath,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{app_ext::DystopiaAssetAppExt, config::RawConfig},
+    map::tilemap::{TilemapTexture, TilemapTextureDescriptor},
+};
+
+pub(super) struct TilesetRegistryPlugin;
+
+impl Plugin for TilesetRegistryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_config::<RawTilesetRegistry>();
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct RawTileset {
+    path: String,
+    size: [u32; 2],
+    tile_size: [u32; 2],
+}
+
+impl From<RawTileset> for TilesetEntry {
+    fn from(value: RawTileset) -> Self {
+        Self {
+            path: value.path,
+            desc: TilemapTextureDescriptor {
+                size: value.size.into(),
+                tile_size: value.tile_size.into(),
+            },
+        }
+    }
+}
+
+#[derive(Asset, TypePath, Clone, Serialize, Deserialize)]
+pub struct RawTilesetRegistry {
+    placeholder: RawTileset,
+    tilesets: HashMap<String, RawTileset>,
+}
+
+impl RawConfig for RawTilesetRegistry {
+    type Processed = TilesetRegistry;
+
+    const PATH: &'static str = "configs/tilesets.json";
+}
+
+#[derive(Debug, Clone)]
+pub struct TilesetEntry {
+    pub path: String,
+    pub desc: TilemapTextureDescriptor,
+}
+
+/// All known tilesets, keyed by their ids. Ids should never be changed once
+/// used, otherwise tilesets in existing saves will become placeholders.
+#[derive(Resource)]
+pub struct TilesetRegistry {
+    placeholder: TilesetEntry,
+    tilesets: HashMap<String, TilesetEntry>,
+}
+
+impl From<RawTilesetRegistry> for TilesetRegistry {
+    fn from(value: RawTilesetRegistry) -> Self {
+        Self {
+            placeholder: value.placeholder.into(),
+            tilesets: value
+                .tilesets
+                .into_iter()
+                .map(|(id, tileset)| (id, tileset.into()))
+                .collect(),
+        }
+    }
+}
+
+impl TilesetRegistry {
+    #[inline]
+    pub fn get(&self, id: &str) -> Option<&TilesetEntry> {
+        self.tilesets.get(id)
+    }
+
+    /// The texture of the tileset, which remembers its id.
+    pub fn texture(&self, id: &str, asset_server: &AssetServer) -> Option<TilemapTexture> {
+        self.get(id).map(|entry| TilemapTexture {
+            id: Some(id.to_string()),
+            handle: asset_server.load(&entry.path),
+            desc: entry.desc,
+        })
+    }
+
+    /// Like [`Self::texture`], but falls back to the placeholder if the tileset
+    /// doesn't exist.
+    ///
+    /// `desc` is the expected descriptor, which makes the placeholder keep the
+    /// same layout, so atlas indices of tiles are still valid. The placeholder
+    /// still remembers `id`, so the tileset comes back once it's registered again.
+    pub fn texture_or_placeholder(
+        &self,
+        id: &str,
+        desc: Option<TilemapTextureDescriptor>,
+        asset_server: &AssetServer,
+    ) -> TilemapTexture {
+        self.texture(id, asset_server).unwrap_or_else(|| {
+            warn!("Tileset {} not found, using placeholder instead.", id);
+            TilemapTexture {
+                id: Some(id.to_string()),
+                ..self.placeholder(desc, asset_server)
+            }
+        })
+    }
+
+    /// The placeholder texture, without id.
+    pub fn placeholder(
+        &self,
+        desc: Option<TilemapTextureDescriptor>,
+        asset_server: &AssetServer,
+    ) -> TilemapTexture {
+        TilemapTexture {
+            id: None,
+            handle: asset_server.load(&self.placeholder.path),
+            desc: desc.unwrap_or(self.placeholder.desc),
+        }
+    }
+
+    /// Find the id of the tileset at `path`. Useful for migrating textures that
+    /// are loaded by path.
+    pub fn id_of_path(&self, path: &str) -> Option<&str> {
+        self.tilesets
+            .iter()
+            .find(|(_, entry)| entry.path == path)
+            .map(|(id, _)| id.as_str())
+    }
+}
@@ -66,6 +66,12 @@ pub enum Density {
     GPerCm3(f64),
 }
 
+#[derive(Unit, LocalizableEnum, Debug, Clone, Copy)]
+pub enum AreaDensity {
+    #[si]
+    GPerM2(f64),
+}
+
 #[derive(Unit, LocalizableEnum, Debug, Clone, Copy)]
 pub enum Temperature {
     #[conversion = 273.15]
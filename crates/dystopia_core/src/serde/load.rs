@@ -1,12 +1,33 @@
+use std::path::Path;
+
 use bevy::prelude::{Commands, Entity, EventReader, Query, Res};
+use bincode::{error::DecodeError, Decode};
+use thiserror::Error;
 
 use crate::{
     cosmos::celestial::{BodyIndex, ToLoadTilemap},
     map::{gen::ToGenerateMap, serde::is_tilemap_exist_in_disk},
+    serde::ENCDEC_CONFIG,
     sim::SaveName,
     ui::panel::{body_data::BodyDataPanel, PanelTargetChange},
 };
 
+#[derive(Error, Debug)]
+pub enum SaveFileLoadError {
+    #[error("Io error: {0:?}")]
+    Io(std::io::Error),
+    #[error("Decode error: {0:?}")]
+    Decode(DecodeError),
+}
+
+/// Read a bincode file in save. See [`save_file_path`](crate::serde::save_file_path).
+pub(crate) fn read_save_file<T: Decode>(path: &Path) -> Result<T, SaveFileLoadError> {
+    let bytes = std::fs::read(path).map_err(|e| SaveFileLoadError::Io(e))?;
+    bincode::decode_from_slice(&bytes, ENCDEC_CONFIG)
+        .map(|r| r.0)
+        .map_err(|e| SaveFileLoadError::Decode(e))
+}
+
 pub fn init_tilemap_when_body_clicked(
     mut commands: Commands,
     bodies_query: Query<(Entity, &BodyIndex)>,
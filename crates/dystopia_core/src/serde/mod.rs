@@ -1,13 +1,20 @@
+use std::path::{Path, PathBuf};
+
 use bevy::{
     app::{App, Plugin, Update},
     prelude::{in_state, IntoSystemConfigs},
 };
+use bincode::config::Configuration;
 
 use crate::schedule::state::SceneState;
 
 pub mod load;
 pub mod save;
 
+/// Config of bincode files in saves. See [`load::read_save_file`] and
+/// [`save::write_save_file`].
+pub(crate) const ENCDEC_CONFIG: Configuration = bincode::config::standard();
+
 pub struct DystopiaSerdePlugin;
 
 impl Plugin for DystopiaSerdePlugin {
@@ -18,3 +25,13 @@ impl Plugin for DystopiaSerdePlugin {
         );
     }
 }
+
+/// Path of `file` in the folder of the save.
+pub fn save_file_path(save_name: &str, file: &str) -> PathBuf {
+    Path::new(&std::env::var("PROGRAM_ROOT").unwrap())
+        .join("assets")
+        .join("data")
+        .join("saves")
+        .join(save_name)
+        .join(file)
+}
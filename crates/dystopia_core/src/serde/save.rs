@@ -0,0 +1,22 @@
+use std::path::Path;
+
+use bincode::{error::EncodeError, Encode};
+use thiserror::Error;
+
+use crate::{map::serde::write_bytes, serde::ENCDEC_CONFIG};
+
+#[derive(Error, Debug)]
+pub enum SaveFileSaveError {
+    #[error("Io error: {0:?}")]
+    Io(std::io::Error),
+    #[error("Encode error: {0:?}")]
+    Encode(EncodeError),
+}
+
+/// Write a bincode file in save, returns the number of bytes written. See
+/// [`save_file_path`](crate::serde::save_file_path).
+pub(crate) fn write_save_file<T: Encode>(path: &Path, data: T) -> Result<usize, SaveFileSaveError> {
+    let bytes =
+        bincode::encode_to_vec(data, ENCDEC_CONFIG).map_err(|e| SaveFileSaveError::Encode(e))?;
+    write_bytes(&bytes, path).map_err(|e| SaveFileSaveError::Io(e))
+}
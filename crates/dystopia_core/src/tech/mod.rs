@@ -0,0 +1,251 @@
+//! Researching technologies to unlock crops, buildings and landing on more
+//! kinds of bodies.
+//!
+//! Only one technology is researched at a time, progressing by one each tick.
+//! The state is saved along with the game save.
+
+use bevy::{
+    app::{App, FixedUpdate, Plugin, Update},
+    log::{error, info, warn},
+    prelude::{
+        in_state, resource_changed, resource_exists, Commands, Entity, Event, EventReader,
+        EventWriter, IntoSystemConfigs, OnTransition, Query, Res, ResMut, Resource, Without,
+    },
+    utils::HashSet,
+};
+use bincode::{Decode, Encode};
+
+use crate::{
+    assets::app_ext::DystopiaAssetAppExt,
+    cosmos::celestial::{BodyType, Landable},
+    schedule::state::GameState,
+    serde::{load::read_save_file, save::write_save_file, save_file_path},
+    sim::{global_clock, SaveName, Ticker},
+    tech::tree::{RawTechTree, TechTree, TechUnlock},
+};
+
+pub mod tree;
+
+/// Research progress is saved once per this many ticks.
+pub const RESEARCH_AUTOSAVE_INTERVAL: u64 = 600;
+
+pub struct DystopiaTechPlugin;
+
+impl Plugin for DystopiaTechPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_config::<RawTechTree>()
+            .init_resource::<ResearchState>()
+            .add_event::<StartResearch>()
+            .add_event::<ResearchStarted>()
+            .add_event::<ResearchCompleted>()
+            .add_systems(
+                OnTransition {
+                    exited: GameState::Initialize,
+                    entered: GameState::Simulate,
+                },
+                load_research
+                    .run_if(resource_exists::<SaveName>)
+                    .run_if(resource_exists::<TechTree>),
+            )
+            .add_systems(Update, start_research.run_if(in_state(GameState::Simulate)))
+            .add_systems(
+                FixedUpdate,
+                (
+                    advance_research,
+                    unlock_landing.run_if(resource_changed::<ResearchState>),
+                    save_research,
+                )
+                    .chain()
+                    .after(global_clock)
+                    .run_if(in_state(GameState::Simulate)),
+            );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Research {
+    /// Id in [`TechTree`].
+    pub tech: String,
+    /// Ticks researched.
+    pub progress: u64,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct ResearchState {
+    researched: HashSet<String>,
+    unlocked: HashSet<TechUnlock>,
+    current: Option<Research>,
+}
+
+impl ResearchState {
+    #[inline]
+    pub fn is_researched(&self, tech: &str) -> bool {
+        self.researched.contains(tech)
+    }
+
+    #[inline]
+    pub fn current(&self) -> Option<&Research> {
+        self.current.as_ref()
+    }
+
+    /// Whether `tech` exists, isn't researched yet and all its prerequisites
+    /// are researched.
+    pub fn can_research(&self, tree: &TechTree, tech: &str) -> bool {
+        tree.get(tech).is_some_and(|def| {
+            !self.is_researched(tech) && def.prerequisites.iter().all(|p| self.is_researched(p))
+        })
+    }
+
+    /// Things not locked by any technology are always available.
+    #[inline]
+    pub fn is_available(&self, tree: &TechTree, unlock: &TechUnlock) -> bool {
+        !tree.is_locked(unlock) || self.unlocked.contains(unlock)
+    }
+
+    fn complete(&mut self, tree: &TechTree, tech: String) {
+        if let Some(def) = tree.get(&tech) {
+            self.unlocked.extend(def.unlocks.iter().cloned());
+        }
+        self.researched.insert(tech);
+    }
+}
+
+/// Request to research a technology, replacing the current one. Progress of
+/// the replaced research is lost.
+#[derive(Event, Debug, Clone)]
+pub struct StartResearch {
+    pub tech: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct ResearchStarted {
+    pub tech: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct ResearchCompleted {
+    pub tech: String,
+}
+
+fn start_research(
+    mut requests: EventReader<StartResearch>,
+    mut started: EventWriter<ResearchStarted>,
+    mut research: ResMut<ResearchState>,
+    tree: Res<TechTree>,
+) {
+    for request in requests.read() {
+        if !research.can_research(&tree, &request.tech) {
+            warn!("Technology {} can't be researched now.", request.tech);
+            continue;
+        }
+
+        research.current = Some(Research {
+            tech: request.tech.clone(),
+            progress: 0,
+        });
+        started.send(ResearchStarted {
+            tech: request.tech.clone(),
+        });
+    }
+}
+
+fn advance_research(
+    mut completed: EventWriter<ResearchCompleted>,
+    mut research: ResMut<ResearchState>,
+    tree: Res<TechTree>,
+) {
+    let Some(current) = &mut research.current else {
+        return;
+    };
+
+    let Some(def) = tree.get(&current.tech) else {
+        research.current = None;
+        return;
+    };
+
+    current.progress += 1;
+    if current.progress >= def.cost {
+        let tech = research.current.take().unwrap().tech;
+        research.complete(&tree, tech.clone());
+        completed.send(ResearchCompleted { tech });
+    }
+}
+
+fn unlock_landing(
+    mut commands: Commands,
+    bodies_query: Query<(Entity, &BodyType), Without<Landable>>,
+    research: Res<ResearchState>,
+    tree: Res<TechTree>,
+) {
+    for (entity, ty) in &bodies_query {
+        let unlock = TechUnlock::Landable(*ty);
+        if tree.is_locked(&unlock) && research.is_available(&tree, &unlock) {
+            commands.entity(entity).insert(Landable);
+        }
+    }
+}
+
+/// The research state in save.
+#[derive(Encode, Decode)]
+struct BinaryResearchState {
+    researched: Vec<String>,
+    current: Option<(String, u64)>,
+}
+
+fn load_research(
+    mut research: ResMut<ResearchState>,
+    tree: Res<TechTree>,
+    save_name: Res<SaveName>,
+) {
+    *research = ResearchState::default();
+    let path = save_file_path(&save_name, "research.bin");
+    if !path.exists() {
+        return;
+    }
+
+    let binary = match read_save_file::<BinaryResearchState>(&path) {
+        Ok(binary) => binary,
+        Err(err) => {
+            error!("Failed to load research state: {}", err);
+            return;
+        }
+    };
+
+    for tech in binary.researched {
+        research.complete(&tree, tech);
+    }
+    research.current = binary
+        .current
+        .map(|(tech, progress)| Research { tech, progress });
+
+    info!(
+        "Research state loaded, {} technologies researched.",
+        research.researched.len()
+    );
+}
+
+fn save_research(
+    mut started: EventReader<ResearchStarted>,
+    mut completed: EventReader<ResearchCompleted>,
+    research: Res<ResearchState>,
+    save_name: Option<Res<SaveName>>,
+    ticker: Res<Ticker>,
+) {
+    let changed = started.read().count() + completed.read().count() > 0;
+    let autosave = research.current.is_some() && **ticker % RESEARCH_AUTOSAVE_INTERVAL == 0;
+    let Some(save_name) = save_name.filter(|_| changed || autosave) else {
+        return;
+    };
+
+    let binary = BinaryResearchState {
+        researched: research.researched.iter().cloned().collect(),
+        current: research
+            .current
+            .as_ref()
+            .map(|r| (r.tech.clone(), r.progress)),
+    };
+
+    if let Err(err) = write_save_file(&save_file_path(&save_name, "research.bin"), binary) {
+        error!("Failed to save research state: {}", err);
+    }
+}
@@ -0,0 +1,116 @@
+//! Technology definitions.
+
+use bevy::{
+    asset::Asset,
+    log::warn,
+    prelude::{Deref, Resource},
+    reflect::TypePath,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{assets::config::RawConfig, cosmos::celestial::BodyType};
+
+/// Something that becomes available after researching a technology.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TechUnlock {
+    /// Id in [`CropRegistry`](crate::farming::crop::CropRegistry).
+    Crop(String),
+    /// Id of the building.
+    Building(String),
+    /// Bodies of this type can be landed on.
+    Landable(BodyType),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RawTech {
+    #[serde(default)]
+    prerequisites: Vec<String>,
+    cost: u64,
+    #[serde(default)]
+    unlocks: Vec<TechUnlock>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TechDef {
+    /// Technologies to be researched before this one.
+    pub prerequisites: Vec<String>,
+    /// Ticks the research takes.
+    pub cost: u64,
+    pub unlocks: Vec<TechUnlock>,
+}
+
+impl From<RawTech> for TechDef {
+    fn from(value: RawTech) -> Self {
+        Self {
+            prerequisites: value.prerequisites,
+            cost: value.cost,
+            unlocks: value.unlocks,
+        }
+    }
+}
+
+#[derive(Asset, TypePath, Clone, Serialize, Deserialize)]
+pub struct RawTechTree(HashMap<String, RawTech>);
+
+impl RawConfig for RawTechTree {
+    type Processed = TechTree;
+
+    const PATH: &'static str = "configs/tech.json";
+}
+
+/// All technologies, keyed by their ids.
+#[derive(Resource, Deref)]
+pub struct TechTree {
+    #[deref]
+    techs: HashMap<String, TechDef>,
+    /// Things that are unlocked by any technology.
+    locked: HashSet<TechUnlock>,
+}
+
+impl From<RawTechTree> for TechTree {
+    fn from(value: RawTechTree) -> Self {
+        let mut pending = value.0;
+        let mut techs = HashMap::<String, TechDef>::default();
+
+        // Only keep technologies whose prerequisites can all be researched, which
+        // also rules out cycles.
+        loop {
+            let resolved = pending
+                .iter()
+                .filter(|(_, tech)| tech.prerequisites.iter().all(|p| techs.contains_key(p)))
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<_>>();
+            if resolved.is_empty() {
+                break;
+            }
+
+            for id in resolved {
+                let tech = pending.remove(&id).unwrap();
+                techs.insert(id, tech.into());
+            }
+        }
+
+        for id in pending.keys() {
+            warn!(
+                "Technology {} has missing or cyclic prerequisites, skipped.",
+                id
+            );
+        }
+
+        let locked = techs
+            .values()
+            .flat_map(|tech| tech.unlocks.iter().cloned())
+            .collect();
+
+        Self { techs, locked }
+    }
+}
+
+impl TechTree {
+    /// Whether `unlock` requires researching some technology.
+    #[inline]
+    pub fn is_locked(&self, unlock: &TechUnlock) -> bool {
+        self.locked.contains(unlock)
+    }
+}
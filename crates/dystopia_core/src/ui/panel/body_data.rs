@@ -21,7 +21,7 @@ use crate::{
     localization::{ui::LUiPanel, LangFile, Localizable, LocalizableData},
     merge_list,
     schedule::state::{GameState, SceneState},
-    sci::unit::{Density, Illuminance, Length, Temperature, Time, Unit},
+    sci::unit::{AreaDensity, Density, Illuminance, Length, Temperature, Time, Unit},
     ui::{
         ext::DefaultWithStyle,
         interation::{
@@ -43,7 +43,7 @@ use crate::{
 
 localizable_enum!(LBodyType, pub, Star, Planet, Moon);
 localizable_enum!(LDetailedBodyType, O, B, A, F, G, K, M, Rocky, Gas, Ice);
-localizable_enum!(LBodyInfoType, Temperature, Density, Illuminance);
+localizable_enum!(LBodyInfoType, Temperature, Density, Illuminance, Biomass);
 localizable_enum!(
     LBodyOrbitInfoType,
     ParentBody,
@@ -94,6 +94,8 @@ struct BodyDataPanelData {
     density: Localizable<Density>,
     title_illuminance: Localizable<LBodyInfoType>,
     illuminance: Localizable<Illuminance>,
+    title_biomass: Localizable<LBodyInfoType>,
+    biomass: Localizable<AreaDensity>,
 
     section_orbit_info: Localizable<LBodyDataPanelSectionType>,
     #[lang_skip]
@@ -225,6 +227,12 @@ impl UiAggregate for BodyDataPanelData {
                                 TextBundle::default_with_style(PANEL_ELEM_TEXT_STYLE)
                             ),
                             // illuminance
+                            distributed_list_element!(
+                                section_root,
+                                TextBundle::default_with_style(PANEL_ELEM_TEXT_STYLE),
+                                TextBundle::default_with_style(PANEL_ELEM_TEXT_STYLE)
+                            ),
+                            // biomass
                             distributed_list_element!(
                                 section_root,
                                 TextBundle::default_with_style(PANEL_ELEM_TEXT_STYLE),
@@ -406,6 +414,8 @@ fn pack_body_data_panel_data(
             density: Density::wrap_with_si(parameterized.density).into(),
             title_illuminance: LBodyInfoType::Illuminance.into(),
             illuminance: Illuminance::wrap_with_si(parameterized.illuminance).into(),
+            title_biomass: LBodyInfoType::Biomass.into(),
+            biomass: AreaDensity::wrap_with_si(parameterized.biomass).into(),
 
             section_orbit_info: LBodyDataPanelSectionType::OrbitInfo.into(),
             title_parent_body: LBodyOrbitInfoType::ParentBody.into(),
@@ -155,10 +155,8 @@ where
     #[inline]
     pub fn remove(&mut self, index: &ChunkStorageIndex<I>) -> Option<T> {
         self.storage
-            .entry(index.in_chunk.clone())
-            .or_insert_with(|| Chunk(vec![None; self.chunk_size.pow(I::DIM) as usize]))
-            [index.in_chunk_at]
-            .take()
+            .get_mut(&index.in_chunk)
+            .and_then(|c| c[index.in_chunk_at].take())
     }
 
     #[inline]
@@ -228,3 +226,353 @@ where
         }
     }
 }
+
+/// A fixed-size chunk storing items densely, with a bitset recording which
+/// slots are occupied.
+///
+/// Compared to [`Chunk`], there's no per-slot `Option` overhead, and iterating
+/// over occupied slots skips empty ones 64 at a time.
+#[derive(Clone)]
+pub struct DenseChunk<T> {
+    data: Box<[T]>,
+    occupancy: Box<[u64]>,
+    len: usize,
+}
+
+impl<T: Default> DenseChunk<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            data: std::iter::repeat_with(T::default).take(capacity).collect(),
+            occupancy: vec![0; capacity.div_ceil(64)].into(),
+            len: 0,
+        }
+    }
+
+    #[inline]
+    pub fn set(&mut self, at: usize, item: T) -> Option<T> {
+        let old = std::mem::replace(&mut self.data[at], item);
+        if self.occupy(at) {
+            Some(old)
+        } else {
+            self.len += 1;
+            None
+        }
+    }
+
+    #[inline]
+    pub fn remove(&mut self, at: usize) -> Option<T> {
+        if !self.contains(at) {
+            return None;
+        }
+
+        self.occupancy[at / 64] &= !(1u64 << (at % 64));
+        self.len -= 1;
+        Some(std::mem::take(&mut self.data[at]))
+    }
+}
+
+impl<T> DenseChunk<T> {
+    /// Number of slots, occupied or not.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Number of occupied slots.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn contains(&self, at: usize) -> bool {
+        self.occupancy[at / 64] & (1u64 << (at % 64)) != 0
+    }
+
+    #[inline]
+    pub fn get(&self, at: usize) -> Option<&T> {
+        self.contains(at).then(|| &self.data[at])
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, at: usize) -> Option<&mut T> {
+        if self.contains(at) {
+            Some(&mut self.data[at])
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over occupied slots, along with their indices.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.occupancy
+            .iter()
+            .enumerate()
+            .flat_map(|(word, bits)| OccupiedBits(*bits).map(move |bit| word * 64 + bit))
+            .map(|at| (at, &self.data[at]))
+    }
+
+    /// Iterate over occupied slots mutably, along with their indices.
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+        self.data
+            .chunks_mut(64)
+            .zip(self.occupancy.iter())
+            .enumerate()
+            .flat_map(|(word, (slots, bits))| {
+                slots
+                    .iter_mut()
+                    .enumerate()
+                    .filter(move |(bit, _)| *bits & (1u64 << bit) != 0)
+                    .map(move |(bit, item)| (word * 64 + bit, item))
+            })
+    }
+
+    /// Mark the slot as occupied, and return whether it's already occupied.
+    #[inline]
+    fn occupy(&mut self, at: usize) -> bool {
+        let word = &mut self.occupancy[at / 64];
+        let mask = 1u64 << (at % 64);
+        let occupied = *word & mask != 0;
+        *word |= mask;
+        occupied
+    }
+}
+
+impl<T: Default> From<Chunk<T>> for DenseChunk<T> {
+    fn from(value: Chunk<T>) -> Self {
+        let mut chunk = Self::new(value.len());
+        value
+            .0
+            .into_iter()
+            .enumerate()
+            .filter_map(|(at, item)| item.map(|item| (at, item)))
+            .for_each(|(at, item)| {
+                chunk.set(at, item);
+            });
+        chunk
+    }
+}
+
+impl<T: Default> From<DenseChunk<T>> for Chunk<T> {
+    fn from(mut value: DenseChunk<T>) -> Self {
+        Chunk((0..value.capacity()).map(|at| value.remove(at)).collect())
+    }
+}
+
+/// Iterates over indices of set bits, from the lowest.
+struct OccupiedBits(u64);
+
+impl Iterator for OccupiedBits {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+
+        let bit = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(bit)
+    }
+}
+
+/// Like [`ChunkedStorage`], but uses [`DenseChunk`]s. Chunks are dropped once
+/// they become empty, so there won't be any empty chunk in this storage.
+#[derive(Clone, Deref)]
+pub struct DenseChunkedStorage<I, T>
+where
+    I: ChunkIndex,
+    T: Default,
+{
+    chunk_size: u32,
+    #[deref]
+    storage: HashMap<I, DenseChunk<T>>,
+}
+
+impl<I, T> Default for DenseChunkedStorage<I, T>
+where
+    I: ChunkIndex,
+    T: Default,
+{
+    fn default() -> Self {
+        Self::new(DEFAULT_CHUNK_SIZE)
+    }
+}
+
+impl<I, T> DenseChunkedStorage<I, T>
+where
+    I: ChunkIndex,
+    T: Default,
+{
+    pub fn new(chunk_size: u32) -> Self {
+        Self {
+            chunk_size,
+            storage: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    #[inline]
+    fn chunk_capacity(&self) -> usize {
+        self.chunk_size.pow(I::DIM) as usize
+    }
+
+    #[inline]
+    pub fn contains(&self, index: &ChunkStorageIndex<I>) -> bool {
+        self.storage
+            .get(&index.in_chunk)
+            .is_some_and(|c| c.contains(index.in_chunk_at))
+    }
+
+    #[inline]
+    pub fn contains_chunk(&self, index: &I) -> bool {
+        self.storage.contains_key(index)
+    }
+
+    #[inline]
+    pub fn get(&self, index: &ChunkStorageIndex<I>) -> Option<&T> {
+        self.storage
+            .get(&index.in_chunk)
+            .and_then(|c| c.get(index.in_chunk_at))
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, index: &ChunkStorageIndex<I>) -> Option<&mut T> {
+        self.storage
+            .get_mut(&index.in_chunk)
+            .and_then(|c| c.get_mut(index.in_chunk_at))
+    }
+
+    #[inline]
+    pub fn set(&mut self, index: ChunkStorageIndex<I>, item: T) -> Option<T> {
+        let capacity = self.chunk_capacity();
+        self.storage
+            .entry(index.in_chunk)
+            .or_insert_with(|| DenseChunk::new(capacity))
+            .set(index.in_chunk_at, item)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, index: &ChunkStorageIndex<I>) -> Option<T> {
+        let Entry::Occupied(mut e) = self.storage.entry(index.in_chunk.clone()) else {
+            return None;
+        };
+
+        let removed = e.get_mut().remove(index.in_chunk_at);
+        if e.get().is_empty() {
+            e.remove();
+        }
+        removed
+    }
+
+    #[inline]
+    pub fn get_chunk(&self, index: &I) -> Option<&DenseChunk<T>> {
+        self.storage.get(index)
+    }
+
+    /// Empty chunks are not inserted.
+    #[inline]
+    pub fn set_chunk(&mut self, index: I, chunk: DenseChunk<T>) -> Option<DenseChunk<T>> {
+        if chunk.is_empty() {
+            self.storage.remove(&index)
+        } else {
+            self.storage.insert(index, chunk)
+        }
+    }
+
+    #[inline]
+    pub fn remove_chunk(&mut self, index: &I) -> Option<DenseChunk<T>> {
+        self.storage.remove(index)
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.storage.clear();
+    }
+
+    /// Number of occupied slots in all chunks.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.storage.values().map(DenseChunk::len).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty()
+    }
+
+    /// Iterate over all items, without visiting empty slots.
+    #[inline]
+    pub fn iter_occupied(&self) -> impl Iterator<Item = (ChunkStorageIndex<I>, &T)> {
+        self.storage.iter().flat_map(|(ci, c)| {
+            c.iter().map(move |(at, item)| {
+                (
+                    ChunkStorageIndex {
+                        in_chunk: ci.clone(),
+                        in_chunk_at: at,
+                    },
+                    item,
+                )
+            })
+        })
+    }
+
+    /// Mutably iterate over all items, without visiting empty slots.
+    #[inline]
+    pub fn iter_occupied_mut(&mut self) -> impl Iterator<Item = (ChunkStorageIndex<I>, &mut T)> {
+        self.storage.iter_mut().flat_map(|(ci, c)| {
+            c.iter_mut().map(move |(at, item)| {
+                (
+                    ChunkStorageIndex {
+                        in_chunk: ci.clone(),
+                        in_chunk_at: at,
+                    },
+                    item,
+                )
+            })
+        })
+    }
+}
+
+impl<I, T> From<ChunkedStorage<I, T>> for DenseChunkedStorage<I, T>
+where
+    I: ChunkIndex,
+    T: Clone + Default,
+{
+    fn from(value: ChunkedStorage<I, T>) -> Self {
+        let mut dense = Self::new(value.chunk_size);
+        for (index, chunk) in value.storage {
+            dense.set_chunk(index, chunk.into());
+        }
+        dense
+    }
+}
+
+impl<I, T> From<DenseChunkedStorage<I, T>> for ChunkedStorage<I, T>
+where
+    I: ChunkIndex,
+    T: Clone + Default,
+{
+    fn from(value: DenseChunkedStorage<I, T>) -> Self {
+        Self::new_init(
+            value.chunk_size,
+            value
+                .storage
+                .into_iter()
+                .map(|(index, chunk)| (index, chunk.into()))
+                .collect(),
+        )
+    }
+}
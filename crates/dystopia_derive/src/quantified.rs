@@ -58,21 +58,16 @@ pub fn expand_quantified_derive(input: syn::DeriveInput) -> proc_macro::TokenStr
 
         if i_variant == 0 {
             let boundary = boundaries[0];
+            quantifying.push(quote::quote! {
+                if value < #boundary {
+                    Self::#ident
+                }
+            });
             if let Some(min) = min {
-                quantifying.push(quote::quote! {
-                    if value > #min && value < #boundary {
-                        Self::#ident
-                    }
-                });
                 sampling.push(quote::quote! {
                     Self::#ident => rng.gen_range(#min..#boundary),
                 });
             } else {
-                quantifying.push(quote::quote! {
-                    if value < #boundary {
-                        Self::#ident
-                    }
-                });
                 sampling.push(quote::quote! {
                     Self::#ident => panic!("To sample the first variant, you need to specify `min` attribute."),
                 });
@@ -105,7 +100,7 @@ pub fn expand_quantified_derive(input: syn::DeriveInput) -> proc_macro::TokenStr
             let lower = boundaries[i_variant - 1];
             let upper = boundaries[i_variant];
             quantifying.push(quote::quote! {
-                else if value > #lower && value > #upper {
+                else if value >= #lower && value < #upper {
                     Self::#ident
                 }
             });